@@ -1,22 +1,184 @@
-use crate::mooncakesio::get_all_mooncakes;
+use std::{cmp::Ordering, collections::BTreeMap};
 
+use crate::{
+    mooncakesio::get_all_mooncakes,
+    util::{compare_versions, latest_version, with_file_lock, write_atomic},
+};
+
+const GENERATED_MARKER: &str = "# generated list to test mooncakes on mooncakes.io";
+
+/// Generates the `name version` list of the latest mooncakes by semver. A pin
+/// in `pinned` newer than the registry's latest is kept unless
+/// `allow_downgrade`, the skipped downgrades are returned as
+/// `(name, pinned, latest)`.
+fn update_mooncakes_list(
+    db: &BTreeMap<String, Vec<String>>,
+    pinned: &BTreeMap<String, String>,
+    allow_downgrade: bool,
+    include_prerelease: bool,
+) -> (String, Vec<(String, String, String)>) {
+    let mut mooncakesio = String::new();
+    let mut skipped = vec![];
+    for (name, versions) in db {
+        let Some(latest_version) = latest_version(versions, include_prerelease) else {
+            continue;
+        };
+        let version = match pinned.get(name) {
+            Some(pin)
+                if !allow_downgrade && compare_versions(latest_version, pin) == Ordering::Less =>
+            {
+                skipped.push((name.clone(), pin.clone(), latest_version.clone()));
+                pin
+            }
+            _ => latest_version,
+        };
+        mooncakesio.push_str(&format!("{} {}\n", name, version));
+    }
+    (mooncakesio, skipped)
+}
+
+fn parse_pins(generated: &str) -> BTreeMap<String, String> {
+    generated
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .map(|(name, version)| (name.to_string(), version.trim().to_string()))
+        .collect()
+}
+
+/// Changes of the generated list, by package name.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ConfigDiff {
+    added: Vec<String>,
+    /// `(name, before, after)`
+    updated: Vec<(String, String, String)>,
+    removed: Vec<String>,
+}
+
+impl std::fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} added, {} updated, {} removed",
+            self.added.len(),
+            self.updated.len(),
+            self.removed.len()
+        )?;
+        for name in self.added.iter() {
+            writeln!(f, "+ {}", name)?;
+        }
+        for (name, before, after) in self.updated.iter() {
+            writeln!(f, "~ {} {} -> {}", name, before, after)?;
+        }
+        for name in self.removed.iter() {
+            writeln!(f, "- {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_pins(before: &BTreeMap<String, String>, after: &BTreeMap<String, String>) -> ConfigDiff {
+    let mut diff = ConfigDiff::default();
+    for (name, version) in after {
+        match before.get(name) {
+            None => diff.added.push(name.clone()),
+            Some(old) if old != version => {
+                diff.updated
+                    .push((name.clone(), old.clone(), version.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    diff.removed = before
+        .keys()
+        .filter(|name| !after.contains_key(*name))
+        .cloned()
+        .collect();
+    diff
+}
+
+/// Set `ALLOW_DOWNGRADE=1` to accept a latest version older than the current pin,
+/// `EXCLUDE_PRERELEASE=1` to pin the latest release instead of a prerelease.
 #[test]
 fn gen_latest_list_with_version() {
-    let repos = std::fs::read_to_string("repos.txt").unwrap();
-    let parts: Vec<&str> = repos
-        .splitn(2, "# generated list to test mooncakes on mooncakes.io")
-        .collect();
+    let path = std::path::Path::new("repos.txt");
+    let diff = with_file_lock(path, || update_repos_config(path))
+        .unwrap()
+        .unwrap();
+    eprint!("{}", diff);
+}
+
+/// Regenerates the mooncakes list of `path`, returning what changed.
+fn update_repos_config(path: &std::path::Path) -> std::io::Result<ConfigDiff> {
+    let repos = std::fs::read_to_string(path)?;
+    let parts: Vec<&str> = repos.splitn(2, GENERATED_MARKER).collect();
 
-    let mut mooncakesio = String::new();
     let db = get_all_mooncakes().unwrap();
-    for (name, versions) in db.db {
-        let latest_version = versions.last().unwrap();
-        mooncakesio.push_str(&format!("{} {}\n", name, latest_version));
+    let pinned = parse_pins(parts.get(1).copied().unwrap_or_default());
+    let allow_downgrade = std::env::var("ALLOW_DOWNGRADE").is_ok();
+    let include_prerelease = std::env::var("EXCLUDE_PRERELEASE").is_err();
+    let (mooncakesio, skipped) =
+        update_mooncakes_list(&db.db, &pinned, allow_downgrade, include_prerelease);
+    for (name, pin, latest) in skipped {
+        eprintln!("skip downgrade of {} from {} to {}", name, pin, latest);
     }
 
-    let updated = format!(
-        "{}# generated list to test mooncakes on mooncakes.io\n{}",
-        parts[0], mooncakesio
+    let diff = diff_pins(&pinned, &parse_pins(&mooncakesio));
+    let updated = format!("{}{}\n{}", parts[0], GENERATED_MARKER, mooncakesio);
+    write_atomic(path, updated)?;
+    Ok(diff)
+}
+
+#[test]
+fn refuse_downgrade() {
+    let db = BTreeMap::from([
+        (
+            "alice/a".to_string(),
+            vec!["0.9.0".to_string(), "0.8.0".to_string()],
+        ),
+        // not in semver order in the index
+        (
+            "alice/b".to_string(),
+            vec!["0.10.0".to_string(), "0.9.0".to_string()],
+        ),
+    ]);
+    let pinned = parse_pins("alice/a 0.10.0\nalice/b 0.1.0\n");
+
+    let (list, skipped) = update_mooncakes_list(&db, &pinned, false, true);
+    assert_eq!(list, "alice/a 0.10.0\nalice/b 0.10.0\n");
+    assert_eq!(
+        skipped,
+        [(
+            "alice/a".to_string(),
+            "0.10.0".to_string(),
+            "0.9.0".to_string()
+        )]
+    );
+
+    let (list, skipped) = update_mooncakes_list(&db, &pinned, true, true);
+    assert_eq!(list, "alice/a 0.9.0\nalice/b 0.10.0\n");
+    assert!(skipped.is_empty());
+}
+
+#[test]
+fn config_diff() {
+    let before = parse_pins("alice/a 0.1.0\nalice/b 0.1.0\nbob/c 1.0.0\n");
+    let after = parse_pins("alice/a 0.1.0\nalice/b 0.2.0\ncarol/d 0.0.1\n");
+    let diff = diff_pins(&before, &after);
+    assert_eq!(
+        diff,
+        ConfigDiff {
+            added: vec!["carol/d".to_string()],
+            updated: vec![(
+                "alice/b".to_string(),
+                "0.1.0".to_string(),
+                "0.2.0".to_string()
+            )],
+            removed: vec!["bob/c".to_string()],
+        }
+    );
+    assert_eq!(
+        diff.to_string(),
+        "1 added, 1 updated, 1 removed\n+ carol/d\n~ alice/b 0.1.0 -> 0.2.0\n- bob/c\n"
     );
-    std::fs::write("repos.txt", updated).unwrap();
+    assert_eq!(diff_pins(&after, &after), ConfigDiff::default());
 }
@@ -0,0 +1,64 @@
+/// Where a source starts failing over toolchain candidates ordered oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bisection {
+    /// index of the first failing candidate, the one before it passes
+    FirstFailing(usize),
+    /// the oldest candidate already fails
+    FailsFromStart,
+    /// the newest candidate still passes
+    NoFailure,
+}
+
+/// Binary search for the pass to fail transition of `passes` over `count`
+/// candidates, assuming it happens once. Probes both ends, then about
+/// `log2(count)` candidates in between.
+pub fn bisect<E>(
+    count: usize,
+    mut passes: impl FnMut(usize) -> Result<bool, E>,
+) -> Result<Bisection, E> {
+    if count == 0 || !passes(0)? {
+        return Ok(Bisection::FailsFromStart);
+    }
+    if passes(count - 1)? {
+        return Ok(Bisection::NoFailure);
+    }
+    // `good` passes, `bad` fails
+    let (mut good, mut bad) = (0, count - 1);
+    while bad - good > 1 {
+        let mid = good + (bad - good) / 2;
+        if passes(mid)? {
+            good = mid;
+        } else {
+            bad = mid;
+        }
+    }
+    Ok(Bisection::FirstFailing(bad))
+}
+
+#[test]
+fn bisect_over_synthetic_sequence() {
+    let run = |outcomes: &[bool]| {
+        let mut probed = vec![];
+        let result = bisect::<()>(outcomes.len(), |i| {
+            probed.push(i);
+            Ok(outcomes[i])
+        });
+        (result.unwrap(), probed)
+    };
+
+    for first_failing in 1..16 {
+        let outcomes: Vec<bool> = (0..16).map(|i| i < first_failing).collect();
+        let (result, probed) = run(&outcomes);
+        assert_eq!(result, Bisection::FirstFailing(first_failing));
+        // both ends, then log2(16)
+        assert!(probed.len() <= 2 + 4, "{:?}", probed);
+    }
+    assert_eq!(run(&[true, false]).0, Bisection::FirstFailing(1));
+    assert_eq!(run(&[false, false, false]).0, Bisection::FailsFromStart);
+    assert_eq!(run(&[true, true, true]).0, Bisection::NoFailure);
+    assert_eq!(run(&[]).0, Bisection::FailsFromStart);
+
+    // an install failure stops the search
+    let result = bisect(8, |i| if i == 7 { Err("install") } else { Ok(true) });
+    assert_eq!(result, Err("install"));
+}
@@ -1,4 +1,11 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    dashboard::{DynamicBackend, MoonCommand},
+    parse::{DEFAULT_COMPILER_MISMATCH_PATTERNS, DEFAULT_FULLY_CACHED_PATTERNS},
+    pipeline::Schedule,
+    util::current_os,
+};
 
 #[derive(Debug, clap::Parser)]
 pub struct MoonBuildDashBoardCli {
@@ -7,8 +14,19 @@ pub struct MoonBuildDashBoardCli {
 }
 
 #[derive(Debug, clap::Parser)]
+#[allow(clippy::large_enum_variant)]
 pub enum MoonBuildDashBoardSubcommands {
     Stat(StatSubcommand),
+    /// check that every git url answers and every mooncake version exists,
+    /// without downloading; takes the source options of `stat`
+    Preflight(StatSubcommand),
+    Merge(MergeSubcommand),
+    Render(RenderSubcommand),
+    ValidateOutput(ValidateOutputSubcommand),
+    Doctor(DoctorSubcommand),
+    Bisect(BisectSubcommand),
+    Diff(DiffSubcommand),
+    Stats(StatsSubcommand),
 }
 
 #[derive(Debug, clap::Parser)]
@@ -21,4 +39,564 @@ pub struct StatSubcommand {
     pub skip_install: bool,
     #[clap(long)]
     pub skip_update: bool,
+    /// fail instead of warning when a package has more than one registry index file,
+    /// or an index line is malformed
+    #[clap(long)]
+    pub strict_index: bool,
+    /// follow symlinks when scanning the registry index
+    #[clap(long)]
+    pub follow_symlinks: bool,
+    /// reuse the registry scan saved in this file if it is at most `--db-max-age`
+    /// old, otherwise rescan and save it there
+    #[clap(long, value_name = "FILE")]
+    pub db_snapshot: Option<PathBuf>,
+    /// how old a `--db-snapshot` may be, in seconds
+    #[clap(
+        long,
+        value_name = "SECS",
+        default_value_t = 3600,
+        requires = "db_snapshot"
+    )]
+    pub db_max_age: u64,
+    /// print one JSON work item per matrix cell instead of running the matrix
+    #[clap(long)]
+    pub print_work_items: bool,
+    /// print how many commands the run would execute, and with `--cost-history`
+    /// how long they would take, instead of running the matrix
+    #[clap(long)]
+    pub print_matrix_cost: bool,
+    /// a prior dashboard whose timings estimate `--print-matrix-cost`
+    #[clap(long, value_name = "FILE", requires = "print_matrix_cost")]
+    pub cost_history: Option<PathBuf>,
+    /// only run mooncakes that depend on this package, directly or transitively
+    #[clap(long, value_name = "NAME")]
+    pub reverse_deps_of: Option<String>,
+    /// only run mooncakes whose latest version lists this keyword
+    #[clap(long, value_name = "K")]
+    pub keyword: Option<String>,
+    /// resolve `latest` to the greatest version that is not a semver prerelease
+    #[clap(long)]
+    pub exclude_prerelease: bool,
+    /// only run mooncakes that got new versions in the registry by `moon update`
+    #[clap(long, conflicts_with = "skip_update")]
+    pub build_updated_only: bool,
+    /// re-run the changed sources of `--file` whenever it is saved
+    #[clap(long, requires = "file")]
+    pub watch: bool,
+    /// drop logs, successful cells first, until the gzipped output fits
+    #[clap(long, value_name = "BYTES")]
+    pub max_output_file_bytes: Option<usize>,
+    /// gzip and base64 encode the logs embedded in the output
+    #[clap(long, conflicts_with = "max_output_file_bytes")]
+    pub compress_logs: bool,
+    /// format of the output file, the webapp reads `jsonl-gz`. Other formats need
+    /// `--output-path`, they would not be readable as the dated file and latest link
+    #[clap(
+        long,
+        value_enum,
+        default_value_t = OutputFormat::JsonlGz,
+        requires_ifs = [("jsonl", "output_path"), ("json-pretty", "output_path")]
+    )]
+    pub output_format: OutputFormat,
+    /// write the output here instead of `webapp/public/<os>/<date>_data.jsonl.gz`,
+    /// leaving the latest file and old data files of the webapp alone
+    #[clap(long, value_name = "FILE")]
+    pub output_path: Option<PathBuf>,
+    /// write a Markdown summary of the run, a table of statuses per source
+    #[clap(long, value_name = "PATH")]
+    pub markdown_out: Option<PathBuf>,
+    /// write a standalone HTML page with the matrix of the run
+    #[clap(long, value_name = "PATH")]
+    pub html_out: Option<PathBuf>,
+    /// proxy for downloads and git, overrides `HTTP_PROXY`/`HTTPS_PROXY`
+    #[clap(long, value_name = "URL")]
+    pub proxy: Option<String>,
+    /// when `moon update` cannot reach the network, warn and build against
+    /// the registry index already on disk
+    #[clap(long)]
+    pub allow_stale_registry: bool,
+    /// output marking a cell as built against another compiler version,
+    /// replaces the default patterns
+    #[clap(long = "compiler-mismatch-pattern", value_name = "PATTERN")]
+    pub compiler_mismatch_patterns: Vec<String>,
+    /// output marking a build as fully cached, case insensitive, replaces the
+    /// default patterns
+    #[clap(long = "fully-cached-pattern", value_name = "PATTERN")]
+    pub fully_cached_patterns: Vec<String>,
+    /// sources to leave out, everywhere or on some OSes, see `ExcludeConfig`
+    #[clap(long, value_name = "FILE")]
+    pub exclude: Option<PathBuf>,
+    /// retry a failed command whose stderr contains this text, case insensitive,
+    /// e.g. `--retry-on-pattern "address already in use"`
+    #[clap(long = "retry-on-pattern", value_name = "PATTERN")]
+    pub retry_on_patterns: Vec<String>,
+    /// how many times a command matching `--retry-on-pattern` is retried
+    #[clap(long, value_name = "N", default_value_t = 1)]
+    pub retries: u32,
+    /// register a backend unknown to this tool, e.g. `--extra-backend llvm=llvm`
+    #[clap(long = "extra-backend", value_name = "NAME=FLAG")]
+    pub extra_backends: Vec<DynamicBackend>,
+    /// which output streams of moon commands to keep in the results
+    #[clap(long, value_enum, default_value_t = Capture::Both)]
+    pub capture: Capture,
+    /// show moon output live and append it to `<source>_<command>_<backend>.{stdout,stderr}.log`
+    /// files in this directory as it is produced
+    #[clap(long, value_name = "DIR")]
+    pub logs_dir: Option<PathBuf>,
+    /// print a status grid of every cell at the end of the run
+    #[clap(long)]
+    pub grid: bool,
+    /// print success/failure counts per group at the end of the run
+    #[clap(long, value_enum)]
+    pub group_by: Option<GroupBy>,
+    /// build a throwaway package once per toolchain before the real builds
+    #[clap(long)]
+    pub warm_core: bool,
+    /// stream each completed source's results as JSON lines to viewers of this Unix socket
+    #[cfg(unix)]
+    #[clap(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+    /// exit non-zero when more than this fraction (0.0-1.0) of executed cells failed
+    #[clap(long, value_name = "FLOAT")]
+    pub max_failure_rate: Option<f64>,
+    /// exit non-zero when more cells than this fail on bleeding but pass on stable
+    #[clap(long, value_name = "N")]
+    pub max_regressions: Option<usize>,
+    /// exit non-zero when any source could not be fetched, checked out or
+    /// pre-built, the other sources are built and saved either way
+    #[clap(long)]
+    pub fail_on_error: bool,
+    /// print a one line regression digest of at most CHARS characters for a
+    /// commit status, 140 (the GitHub limit) by default
+    #[clap(
+        long,
+        value_name = "CHARS",
+        num_args = 0..=1,
+        default_missing_value = "140"
+    )]
+    pub commit_status_digest: Option<usize>,
+    /// only run entries of `--file` added or modified since this git ref
+    #[clap(
+        long,
+        value_name = "BASE_REF",
+        requires = "file",
+        num_args = 0..=1,
+        default_missing_value = "origin/main"
+    )]
+    pub only_changed: Option<String>,
+    /// run a single phase with the `moon`/`moonc` on `PATH`, without installing or updating
+    #[clap(long)]
+    pub use_local_toolchain: bool,
+    /// which toolchain phase runs first, the output is the same either way
+    #[clap(long, value_enum, default_value_t = PhaseOrder::StableFirst)]
+    pub phase_order: PhaseOrder,
+    /// run this script instead of `moon`/`moonc` and the installer, with the
+    /// replaced program as first argument, e.g. `SCRIPT moon build ...`
+    #[clap(long, value_name = "SCRIPT")]
+    pub mock_moon: Option<PathBuf>,
+    /// install the stable and bleeding toolchains recorded in this dashboard file
+    #[clap(long, value_name = "FILE", conflicts_with = "use_local_toolchain")]
+    pub select_toolchain_from: Option<PathBuf>,
+    /// run check and build with `--output-json` and count errors and warnings per file
+    #[clap(long)]
+    pub json_diagnostics: bool,
+    /// record the files each build emits under `target/<backend>/release`
+    #[clap(long)]
+    pub list_artifacts: bool,
+    /// record the dependency versions of each source, as resolved by the build
+    #[clap(long)]
+    pub record_deps: bool,
+    /// clone and download sources on N threads while building, 2 when N is omitted
+    #[clap(long, value_name = "N", num_args = 0..=1, default_missing_value = "2")]
+    pub pipeline: Option<usize>,
+    /// clone, download and build under this directory instead of the default temp
+    /// location, e.g. `/dev/shm` for a tmpfs
+    #[clap(long, value_name = "DIR")]
+    pub build_root: Option<PathBuf>,
+    /// keep downloaded mooncake archives in this directory and reuse them in
+    /// later runs instead of downloading them again; private packages are not kept
+    #[clap(long, value_name = "DIR")]
+    pub download_cache: Option<PathBuf>,
+    /// name prefix of the temp dirs of the run, distinct per concurrent variant
+    /// of the run when they sweep each other's dirs
+    #[clap(long, value_name = "PREFIX", default_value = crate::util::DEFAULT_TMP_PREFIX)]
+    pub tmp_prefix: String,
+    /// on startup, remove temp dirs with the `--tmp-prefix` left by crashed runs
+    /// and not modified for this many hours, under the temp location and the
+    /// `--build-root`
+    #[clap(long, value_name = "HOURS")]
+    pub sweep_stale: Option<u64>,
+    /// build one source at a time, with its backends in parallel, each in a copy
+    /// of the source
+    #[clap(long, conflicts_with = "pipeline")]
+    pub backend_parallel: bool,
+    /// build up to N backends of a source at once, each in a copy of the source;
+    /// implies `--backend-parallel`
+    #[clap(long, value_name = "N", conflicts_with = "pipeline")]
+    pub jobs: Option<usize>,
+    /// deadline of each backend's check, build and test together, counted from
+    /// when the backend starts; a backend past it is killed and its remaining
+    /// commands are recorded as timed out, the other backends carry on
+    #[clap(long, value_name = "SECS")]
+    pub backend_timeout_secs: Option<u64>,
+    /// run `moon build` and `moon test` with `-j N`, recorded with each result so
+    /// timings compare across machines; moon's default when unset
+    #[clap(long, value_name = "N")]
+    pub moon_jobs: Option<u32>,
+    /// kill a moon command, and what it spawned, after this many seconds
+    #[clap(long, value_name = "SECS")]
+    pub timeout_secs: Option<u64>,
+    /// `--timeout-secs` for `moon check` and `moon build`
+    #[clap(long, value_name = "SECS")]
+    pub build_timeout_secs: Option<u64>,
+    /// `--timeout-secs` for `moon test`, which may hang far longer than a build
+    #[clap(long, value_name = "SECS")]
+    pub test_timeout_secs: Option<u64>,
+    /// write progress events as JSON lines to this file, for tailing while the run goes
+    #[clap(long, value_name = "FILE")]
+    pub events_file: Option<PathBuf>,
+    /// append each source's results to this gzipped JSON lines file as soon
+    /// as it is built, removed once the dashboard is saved. Defaults to
+    /// `journal.jsonl.gz` next to the output, undated so a run resumes across midnight
+    #[clap(long, value_name = "FILE")]
+    pub journal: Option<PathBuf>,
+    /// do not write the journal, e.g. for a read-only output directory
+    #[clap(long, conflicts_with = "journal")]
+    pub no_journal: bool,
+    /// reuse the results in the journal of a run that did not finish, for the
+    /// phases whose toolchain is unchanged
+    #[clap(long, conflicts_with = "no_journal")]
+    pub resume: bool,
+    /// delete dated data files older than this many days from the output directory
+    #[clap(long, value_name = "N")]
+    pub retain_days: Option<u32>,
+    /// shell command given the dashboard as JSON on stdin, whose stdout, a
+    /// dashboard as JSON, is written instead; repeatable, run in order
+    #[clap(long = "processor", value_name = "CMD")]
+    pub processors: Vec<String>,
+    /// file name pointing at the newest data file of the output directory, e.g.
+    /// one per variant of the run on the same OS
+    #[clap(long, value_name = "FILE", default_value = "latest_data.jsonl.gz")]
+    pub latest_name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Capture {
+    Stdout,
+    Stderr,
+    Both,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// mooncake user or git org
+    User,
+    /// git host, `mooncakes.io` for mooncakes
+    Host,
+    Backend,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// the dashboard as one gzipped JSON line
+    JsonlGz,
+    /// the dashboard as one JSON line
+    Jsonl,
+    /// the dashboard as indented JSON, for reading it locally
+    JsonPretty,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PhaseOrder {
+    StableFirst,
+    BleedingFirst,
+}
+
+impl Capture {
+    /// The `(stdout, stderr)` to store, dropped streams become empty.
+    pub fn apply(self, stdout: String, stderr: String) -> (String, String) {
+        match self {
+            Capture::Stdout => (stdout, String::new()),
+            Capture::Stderr => (String::new(), stderr),
+            Capture::Both => (stdout, stderr),
+            Capture::None => (String::new(), String::new()),
+        }
+    }
+}
+
+impl StatSubcommand {
+    pub fn schedule(&self) -> Schedule {
+        match (self.backend_parallel || self.jobs.is_some(), self.pipeline) {
+            (true, _) => Schedule::BackendParallel(self.jobs.unwrap_or(usize::MAX)),
+            (false, Some(workers)) => Schedule::SourcePipeline(workers),
+            (false, None) => Schedule::Serial,
+        }
+    }
+
+    /// Where the journal goes: `--journal`, else next to `--output-path`, else
+    /// next to the dated files in `webapp/public/<os>`. `None` with `--no-journal`.
+    pub fn journal_path(&self) -> Option<PathBuf> {
+        if self.no_journal {
+            return None;
+        }
+        let path = match (&self.journal, &self.output_path) {
+            (Some(journal), _) => journal.clone(),
+            (None, Some(output)) => {
+                let mut name = output.file_name().unwrap_or_default().to_os_string();
+                name.push(".journal");
+                output.with_file_name(name)
+            }
+            (None, None) => {
+                PathBuf::from(format!("webapp/public/{}/journal.jsonl.gz", current_os()))
+            }
+        };
+        Some(path)
+    }
+
+    pub fn backend_timeout(&self) -> Option<Duration> {
+        self.backend_timeout_secs.map(Duration::from_secs)
+    }
+
+    /// The timeout of `cmd`, the one of its kind if set, else `--timeout-secs`.
+    pub fn timeout_for(&self, cmd: MoonCommand) -> Option<Duration> {
+        let secs = match cmd {
+            MoonCommand::Check | MoonCommand::Build => self.build_timeout_secs,
+            MoonCommand::Test => self.test_timeout_secs,
+        };
+        secs.or(self.timeout_secs).map(Duration::from_secs)
+    }
+
+    pub fn fully_cached_patterns(&self) -> Vec<String> {
+        if self.fully_cached_patterns.is_empty() {
+            DEFAULT_FULLY_CACHED_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        } else {
+            self.fully_cached_patterns.clone()
+        }
+    }
+
+    pub fn compiler_mismatch_patterns(&self) -> Vec<String> {
+        if self.compiler_mismatch_patterns.is_empty() {
+            DEFAULT_COMPILER_MISMATCH_PATTERNS
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        } else {
+            self.compiler_mismatch_patterns.clone()
+        }
+    }
+}
+
+/// `OS=FILE`, e.g. `linux=webapp/public/linux/latest_data.jsonl.gz`
+fn parse_merge_input(s: &str) -> Result<(String, PathBuf), String> {
+    match s.split_once('=') {
+        Some((os, file)) if !os.is_empty() && !file.is_empty() => {
+            Ok((os.to_string(), PathBuf::from(file)))
+        }
+        _ => Err(format!("invalid input `{}`, expected `OS=FILE`", s)),
+    }
+}
+
+#[derive(Debug, clap::Parser)]
+pub struct MergeSubcommand {
+    /// a per-OS dashboard file, e.g. `--input linux=linux/latest_data.jsonl.gz`
+    #[clap(long = "input", value_name = "OS=FILE", value_parser = parse_merge_input, required = true)]
+    pub inputs: Vec<(String, PathBuf)>,
+    #[clap(long)]
+    pub output: PathBuf,
+    /// fail when the dashboards disagree instead of recording the conflicts
+    #[clap(long)]
+    pub strict_merge: bool,
+}
+
+/// Renders reports from an existing dashboard file without running any build.
+#[derive(Debug, clap::Parser)]
+pub struct RenderSubcommand {
+    /// a dashboard file, e.g. `webapp/public/linux/latest_data.jsonl.gz`
+    pub file: PathBuf,
+    #[clap(long, value_name = "FILE")]
+    pub html: Option<PathBuf>,
+    #[clap(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+    #[clap(long, value_name = "FILE")]
+    pub csv: Option<PathBuf>,
+    #[clap(long, value_name = "FILE")]
+    pub prometheus: Option<PathBuf>,
+    #[clap(long, value_name = "FILE")]
+    pub markdown: Option<PathBuf>,
+    /// print success/failure counts per group
+    #[clap(long, value_enum)]
+    pub group_by: Option<GroupBy>,
+    /// another run of the same matrix, repeatable; print the cells that regress
+    /// on bleeding or pass only sometimes over `file` and these runs
+    #[clap(long = "repeat", value_name = "FILE")]
+    pub repeats: Vec<PathBuf>,
+}
+
+/// Checks that a dashboard file can be read and is consistent.
+#[derive(Debug, clap::Parser)]
+pub struct ValidateOutputSubcommand {
+    pub file: PathBuf,
+}
+
+/// Finds the first toolchain version a source fails on, installing each
+/// version probed.
+#[derive(Debug, clap::Parser)]
+pub struct BisectSubcommand {
+    /// installable toolchain versions, oldest first, e.g. `0.1.20241231+ba15a9a4`;
+    /// the first one is expected to pass and the last one to fail
+    #[clap(long = "candidate", value_name = "VERSION", required = true)]
+    pub candidates: Vec<String>,
+    /// the source to build, as for `stat`, e.g. `--file` with a single line
+    #[clap(flatten)]
+    pub stat: StatSubcommand,
+}
+
+/// Prints the cells whose status changed between two dashboard files and
+/// exits non-zero on any regression.
+#[derive(Debug, clap::Parser)]
+pub struct DiffSubcommand {
+    /// the older dashboard file, e.g. yesterday's `<date>_data.jsonl.gz`
+    pub before: PathBuf,
+    /// the newer dashboard file
+    pub after: PathBuf,
+}
+
+/// Prints success rates per command and backend, elapsed percentiles and the
+/// slowest sources over one or more dashboard files.
+#[derive(Debug, clap::Parser)]
+pub struct StatsSubcommand {
+    #[clap(required = true)]
+    pub files: Vec<PathBuf>,
+    /// how many of the slowest sources to list
+    #[clap(long, value_name = "N", default_value_t = 10)]
+    pub top: usize,
+    /// print the stats as JSON instead of a table
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Checks that moon, moonc and git can be found.
+#[derive(Debug, clap::Parser)]
+pub struct DoctorSubcommand {
+    /// print paths, versions, the moon home and relevant environment variables
+    /// as JSON, secrets redacted, for bug reports
+    #[clap(long)]
+    pub dump_env: bool,
+    /// print the probed tools and whether the environment is ready as JSON,
+    /// still failing when a tool is missing
+    #[clap(long, conflicts_with = "dump_env")]
+    pub json: bool,
+}
+
+#[test]
+fn timeout_per_command_kind() {
+    use clap::Parser;
+
+    let stat = |args: &[&str]| {
+        let args = ["moon_dashboard", "stat"].iter().chain(args);
+        match MoonBuildDashBoardCli::parse_from(args).subcommand {
+            MoonBuildDashBoardSubcommands::Stat(cmd) => cmd,
+            _ => unreachable!(),
+        }
+    };
+    let (check, build, test) = (MoonCommand::Check, MoonCommand::Build, MoonCommand::Test);
+    let secs = Duration::from_secs;
+
+    let cmd = stat(&[]);
+    assert_eq!(cmd.timeout_for(build), None);
+    let cmd = stat(&["--timeout-secs", "600", "--test-timeout-secs", "60"]);
+    assert_eq!(cmd.timeout_for(check), Some(secs(600)));
+    assert_eq!(cmd.timeout_for(build), Some(secs(600)));
+    assert_eq!(cmd.timeout_for(test), Some(secs(60)));
+    let cmd = stat(&["--build-timeout-secs", "900", "--test-timeout-secs", "60"]);
+    assert_eq!(cmd.timeout_for(check), Some(secs(900)));
+    assert_eq!(cmd.timeout_for(test), Some(secs(60)));
+    let cmd = stat(&["--build-timeout-secs", "900"]);
+    assert_eq!(cmd.timeout_for(test), None);
+}
+
+#[test]
+fn schedule_of_jobs() {
+    use clap::Parser;
+
+    let stat = |args: &[&str]| {
+        let args = ["moon_dashboard", "stat"].iter().chain(args);
+        match MoonBuildDashBoardCli::parse_from(args).subcommand {
+            MoonBuildDashBoardSubcommands::Stat(cmd) => cmd,
+            _ => unreachable!(),
+        }
+    };
+    assert_eq!(stat(&[]).schedule(), Schedule::Serial);
+    assert_eq!(
+        stat(&["--backend-parallel"]).schedule(),
+        Schedule::BackendParallel(usize::MAX)
+    );
+    assert_eq!(
+        stat(&["--jobs", "2"]).schedule(),
+        Schedule::BackendParallel(2)
+    );
+    assert_eq!(stat(&[]).backend_timeout(), None);
+    assert_eq!(
+        stat(&["--backend-timeout-secs", "60"]).backend_timeout(),
+        Some(Duration::from_secs(60))
+    );
+    assert!(MoonBuildDashBoardCli::try_parse_from([
+        "moon_dashboard",
+        "stat",
+        "--jobs",
+        "2",
+        "--pipeline"
+    ])
+    .is_err());
+}
+
+#[test]
+fn journal_by_default() {
+    use clap::Parser;
+
+    let stat = |args: &[&str]| {
+        let args = ["moon_dashboard", "stat"].iter().chain(args);
+        match MoonBuildDashBoardCli::try_parse_from(args).map(|cli| cli.subcommand) {
+            Ok(MoonBuildDashBoardSubcommands::Stat(cmd)) => Some(cmd.journal_path()),
+            _ => None,
+        }
+    };
+    assert_eq!(
+        stat(&[]),
+        Some(Some(PathBuf::from(format!(
+            "webapp/public/{}/journal.jsonl.gz",
+            current_os()
+        ))))
+    );
+    assert_eq!(
+        stat(&["--output-path", "out/data.json"]),
+        Some(Some(PathBuf::from("out/data.json.journal")))
+    );
+    assert_eq!(
+        stat(&["--journal", "j.gz", "--output-path", "out/data.json"]),
+        Some(Some(PathBuf::from("j.gz")))
+    );
+    assert_eq!(stat(&["--no-journal"]), Some(None));
+    assert_eq!(stat(&["--resume"]), stat(&[]));
+    assert_eq!(stat(&["--no-journal", "--resume"]), None);
+}
+
+#[test]
+fn capture_modes() {
+    let apply = |c: Capture| c.apply("out".to_string(), "err".to_string());
+    assert_eq!(apply(Capture::Stdout), ("out".to_string(), String::new()));
+    assert_eq!(apply(Capture::Stderr), (String::new(), "err".to_string()));
+    assert_eq!(apply(Capture::Both), ("out".to_string(), "err".to_string()));
+    assert_eq!(apply(Capture::None), (String::new(), String::new()));
+
+    use clap::Parser;
+    let cli = MoonBuildDashBoardCli::parse_from(["moon_dashboard", "stat", "--capture", "stderr"]);
+    let MoonBuildDashBoardSubcommands::Stat(stat) = cli.subcommand else {
+        panic!("expected stat");
+    };
+    assert_eq!(stat.capture, Capture::Stderr);
 }
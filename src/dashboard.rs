@@ -1,16 +1,27 @@
+use std::{collections::BTreeMap, io::Read, path::Path};
+
 use serde::{Deserialize, Serialize};
 
+use crate::repro::ReproInfo;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Hash, Eq, PartialEq)]
 pub enum MooncakeSource {
     MooncakesIO {
         name: String,
         version: Vec<String>,
         index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pre_build: Option<String>,
+        /// downloaded with the `MOONCAKES_TOKEN` bearer token
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        private: Option<bool>,
     },
     Git {
         url: String,
         rev: Vec<String>,
         index: usize,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pre_build: Option<String>,
     },
 }
 
@@ -21,64 +32,373 @@ impl MooncakeSource {
             MooncakeSource::Git { index, .. } => *index,
         }
     }
+
+    pub fn with_index(mut self, new_index: usize) -> Self {
+        match &mut self {
+            MooncakeSource::MooncakesIO { index, .. } => *index = new_index,
+            MooncakeSource::Git { index, .. } => *index = new_index,
+        }
+        self
+    }
+
+    /// git url or mooncakes.io package name
+    pub fn name(&self) -> &str {
+        match self {
+            MooncakeSource::MooncakesIO { name, .. } => name,
+            MooncakeSource::Git { url, .. } => url,
+        }
+    }
+
+    /// git revs or mooncakes.io versions
+    pub fn revs(&self) -> &[String] {
+        match self {
+            MooncakeSource::MooncakesIO { version, .. } => version,
+            MooncakeSource::Git { rev, .. } => rev,
+        }
+    }
+
+    /// Shell command run in the workdir before the matrix, e.g. for codegen.
+    pub fn pre_build(&self) -> Option<&str> {
+        match self {
+            MooncakeSource::MooncakesIO { pre_build, .. } => pre_build.as_deref(),
+            MooncakeSource::Git { pre_build, .. } => pre_build.as_deref(),
+        }
+    }
+
+    /// mooncake user or git org, e.g. `moonbitlang` for both
+    /// `moonbitlang/core` and `https://github.com/moonbitlang/core`
+    pub fn user(&self) -> &str {
+        match self {
+            MooncakeSource::MooncakesIO { name, .. } => {
+                name.split(['/', '\\']).next().unwrap_or_default()
+            }
+            MooncakeSource::Git { url, .. } => url
+                .split("://")
+                .nth(1)
+                .and_then(|rest| rest.split('/').nth(1))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// git host, `mooncakes.io` for mooncakes
+    pub fn host(&self) -> &str {
+        match self {
+            MooncakeSource::MooncakesIO { .. } => "mooncakes.io",
+            MooncakeSource::Git { url, .. } => url
+                .split("://")
+                .nth(1)
+                .and_then(|rest| rest.split('/').next())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Whether fetching the source goes over the network, not for a git repo on
+    /// a local path or `file://` url.
+    pub fn needs_network(&self) -> bool {
+        match self {
+            MooncakeSource::MooncakesIO { .. } => true,
+            MooncakeSource::Git { url, .. } => {
+                !url.starts_with("file://") && !Path::new(url).exists()
+            }
+        }
+    }
+
+    pub fn is_moonbit_community(&self) -> bool {
+        match self {
+            MooncakeSource::MooncakesIO { name, .. } => name.contains("moonbitlang"),
+            MooncakeSource::Git { url, .. } => {
+                url.contains("moonbitlang") || url.contains("moonbit-community")
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Backend {
     Wasm,
     WasmGC,
     Js,
+    Native,
 }
 
 impl Backend {
-    pub fn to_flag(&self) -> &str {
+    /// Every backend, in the order of the matrix columns.
+    pub fn all() -> Vec<Backend> {
+        vec![Backend::Wasm, Backend::WasmGC, Backend::Js, Backend::Native]
+    }
+
+    pub fn to_flag(&self) -> &'static str {
         match self {
             Backend::Wasm => "wasm",
             Backend::WasmGC => "wasm-gc",
             Backend::Js => "js",
+            Backend::Native => "native",
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// A backend that is not (yet) known to this crate, registered at runtime
+/// with `--extra-backend name=flag`. `flag` is passed to `moon --target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicBackend {
+    pub name: String,
+    pub flag: String,
+}
+
+impl std::str::FromStr for DynamicBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((name, _)) if Backend::all().iter().any(|b| b.to_flag() == name.trim()) => {
+                Err(format!("`{}` is a builtin backend", name.trim()))
+            }
+            Some((name, flag)) if !name.trim().is_empty() && !flag.trim().is_empty() => {
+                Ok(DynamicBackend {
+                    name: name.trim().to_string(),
+                    flag: flag.trim().to_string(),
+                })
+            }
+            _ => Err(format!("invalid backend `{}`, expected `name=flag`", s)),
+        }
+    }
+}
+
+/// A column of the matrix, one of `Backend::all` or a `--extra-backend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MatrixBackend {
+    Builtin(Backend),
+    Dynamic(DynamicBackend),
+}
+
+impl MatrixBackend {
+    /// The builtin backends, then `extra` in the order given.
+    pub fn all(extra: &[DynamicBackend]) -> Vec<MatrixBackend> {
+        Backend::all()
+            .into_iter()
+            .map(MatrixBackend::Builtin)
+            .chain(extra.iter().cloned().map(MatrixBackend::Dynamic))
+            .collect()
+    }
+
+    /// The `--target` passed to moon.
+    pub fn flag(&self) -> &str {
+        match self {
+            MatrixBackend::Builtin(backend) => backend.to_flag(),
+            MatrixBackend::Dynamic(backend) => &backend.flag,
+        }
+    }
+
+    /// The name its results are recorded under, the flag of a builtin backend.
+    pub fn name(&self) -> &str {
+        match self {
+            MatrixBackend::Builtin(backend) => backend.to_flag(),
+            MatrixBackend::Dynamic(backend) => &backend.name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MoonCommand {
-    Check(Backend),
-    Build(Backend),
-    Test(Backend),
+    Check,
+    Build,
+    Test,
 }
 
 impl MoonCommand {
-    pub fn args(&self, is_moonbit_community: bool) -> Vec<&str> {
-        match self {
-            MoonCommand::Check(backend) => vec!["check", "-q", "--target", backend.to_flag()],
-            MoonCommand::Build(backend) => vec!["build", "-q", "--target", backend.to_flag()],
-            MoonCommand::Test(backend) => {
+    /// Every command, in the order they run on a backend.
+    pub fn all() -> [MoonCommand; 3] {
+        [MoonCommand::Check, MoonCommand::Build, MoonCommand::Test]
+    }
+
+    /// Whether `--moon-jobs` applies, `moon check` only type checks.
+    pub fn takes_jobs(&self) -> bool {
+        matches!(self, MoonCommand::Build | MoonCommand::Test)
+    }
+
+    /// The moon arguments on `target`. With `jobs`, build and test run with
+    /// `-j <jobs>` instead of moon's default.
+    pub fn args<'a>(
+        &self,
+        target: &'a str,
+        is_moonbit_community: bool,
+        jobs: Option<&'a str>,
+    ) -> Vec<&'a str> {
+        let mut args = match self {
+            MoonCommand::Check => vec!["check", "-q", "--target", target],
+            MoonCommand::Build => vec!["build", "-q", "--target", target],
+            MoonCommand::Test => {
                 // only run test for moonbit community project
                 if is_moonbit_community {
-                    vec!["test", "-q", "--target", backend.to_flag()]
+                    vec!["test", "-q", "--target", target]
                 } else {
-                    vec!["test", "-q", "--build-only", "--target", backend.to_flag()]
+                    vec!["test", "-q", "--build-only", "--target", target]
                 }
             }
+        };
+        if let Some(jobs) = jobs.filter(|_| self.takes_jobs()) {
+            args.extend(["-j", jobs]);
         }
+        args
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The commands `run_matrix` executes for each rev/version of a source, paired
+/// with the backend they run on.
+pub fn plan_matrix(extra_backends: &[DynamicBackend]) -> Vec<(MoonCommand, MatrixBackend)> {
+    let mut plan = vec![];
+    for cmd in MoonCommand::all() {
+        for backend in MatrixBackend::all(extra_backends) {
+            plan.push((cmd, backend));
+        }
+    }
+    plan
+}
+
+/// A single cell of the matrix, with everything needed to execute it on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkItem {
+    pub os: String,
+    pub source: usize,
+    /// git url or mooncakes.io package name
+    pub name: String,
+    /// git rev or mooncakes.io version
+    pub rev: String,
+    pub backend: String,
+    pub args: Vec<String>,
+}
+
+pub fn plan_work_items(
+    sources: &[MooncakeSource],
+    os: &str,
+    extra_backends: &[DynamicBackend],
+    jobs: Option<&str>,
+) -> Vec<WorkItem> {
+    let plan = plan_matrix(extra_backends);
+    let mut items = vec![];
+    for source in sources {
+        for rev in source.revs() {
+            for (cmd, backend) in plan.iter() {
+                items.push(WorkItem {
+                    os: os.to_string(),
+                    source: source.get_index(),
+                    name: source.name().to_string(),
+                    rev: rev.clone(),
+                    backend: backend.name().to_string(),
+                    args: cmd
+                        .args(backend.flag(), source.is_moonbit_community(), jobs)
+                        .into_iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                });
+            }
+        }
+    }
+    items
+}
+
+/// The command invocations of a planned run, and their duration expected from
+/// a prior run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MatrixCost {
+    /// work items times toolchain phases
+    pub commands: usize,
+    /// summed mean elapsed of each command in the history, `None` without one
+    pub estimated_ms: Option<u64>,
+    /// commands the history has no timing for, left out of `estimated_ms`
+    pub unknown: usize,
+}
+
+/// The cost of running `items` once per toolchain phase. A command is timed
+/// by its mean elapsed in `history` for the same source, falling back to the
+/// mean of the same command and backend over every source.
+pub fn matrix_cost(
+    items: &[WorkItem],
+    phases: usize,
+    history: Option<&MoonBuildDashboard>,
+) -> MatrixCost {
+    let mut cost = MatrixCost {
+        commands: items.len() * phases,
+        ..Default::default()
+    };
+    let Some(history) = history else {
+        return cost;
+    };
+    let mut by_source: BTreeMap<(&str, &str, &str), Vec<u64>> = BTreeMap::new();
+    let mut by_command: BTreeMap<(&str, &str), Vec<u64>> = BTreeMap::new();
+    for cell in history.cells() {
+        let elapsed = cell.result.elapsed;
+        by_source
+            .entry((cell.source, cell.command, cell.backend))
+            .or_default()
+            .push(elapsed);
+        by_command
+            .entry((cell.command, cell.backend))
+            .or_default()
+            .push(elapsed);
+    }
+    let mean = |v: &Vec<u64>| v.iter().sum::<u64>() / v.len() as u64;
+    let mut estimated = 0;
+    for item in items {
+        let command = item.args[0].as_str();
+        let backend = item.backend.as_str();
+        let timing = by_source
+            .get(&(item.name.as_str(), command, backend))
+            .or_else(|| by_command.get(&(command, backend)))
+            .map(mean);
+        match timing {
+            Some(ms) => estimated += ms * phases as u64,
+            None => cost.unknown += phases,
+        }
+    }
+    cost.estimated_ms = Some(estimated);
+    cost
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ToolChainLabel {
     Stable,
     Bleeding,
+    /// a toolchain already on `PATH`, with `--use-local-toolchain`
+    Local,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ToolChainLabel {
+    pub fn key(&self) -> &'static str {
+        match self {
+            ToolChainLabel::Stable => "stable",
+            ToolChainLabel::Bleeding => "bleeding",
+            ToolChainLabel::Local => "local",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolChainVersion {
     pub label: ToolChainLabel,
     pub moon_version: String,
     pub moonc_version: String,
+    /// version of the core library, when `moon version --all` reports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub core_version: Option<String>,
+}
+
+impl ToolChainVersion {
+    /// Same `moon` and `moonc` versions, whatever the labels.
+    pub fn same_versions(&self, other: &ToolChainVersion) -> bool {
+        self.moon_version == other.moon_version && self.moonc_version == other.moonc_version
+    }
 }
 
+/// Version of the dashboard JSON format, bump it on incompatible changes.
+/// Files written before the field existed are read as version 0.
+/// Version 2 added `Status::Timeout`.
+pub const SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MoonBuildDashboard {
+    #[serde(default)]
+    pub schema_version: u32,
     pub run_id: String,
     pub run_number: String,
     pub start_time: String,
@@ -90,12 +410,310 @@ pub struct MoonBuildDashboard {
 
     pub bleeding_toolchain_version: ToolChainVersion,
     pub bleeding_release_data: Vec<BuildState>,
+
+    #[serde(default)]
+    pub reproducibility: ReproInfo,
+
+    /// what was dropped to fit `--max-output-file-bytes`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pruned: Vec<String>,
+
+    /// `--warm-core` builds, keyed by toolchain label
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub warmup: BTreeMap<String, ExecuteResult>,
+
+    /// whether the bleeding toolchain differs from the one of the previous
+    /// `latest` file, `None` when there was none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bleeding_changed_since_latest: Option<bool>,
+
+    /// packages with new versions according to `moon update`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub updated_packages: Vec<String>,
+
+    /// free-form labels, e.g. set by a `--processor`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// Sources that appeared or disappeared since a prior run, sorted by name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SourceChurn {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// A source of one phase that could not be fetched, checked out or pre-built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceError<'a> {
+    /// `stable` or `bleeding`
+    pub toolchain: &'static str,
+    pub source: &'a str,
+    pub reason: &'a str,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DashboardReadError {
+    #[error("io error")]
+    IOError(#[from] std::io::Error),
+    #[error("serde")]
+    Serde(#[from] serde_json::Error),
+    #[error("unsupported schema version {found}, expected at most {SCHEMA_VERSION}")]
+    UnsupportedSchemaVersion { found: u32 },
+}
+
+impl MoonBuildDashboard {
+    /// Parses a dashboard, rejecting files written by a newer schema. Older
+    /// schemas are read as is, missing fields fall back to their serde defaults,
+    /// and a failure with only `timed_out` set reads as `Status::Timeout`.
+    pub fn from_json(s: &str) -> Result<Self, DashboardReadError> {
+        let mut dashboard: MoonBuildDashboard = serde_json::from_str(s)?;
+        if dashboard.schema_version > SCHEMA_VERSION {
+            return Err(DashboardReadError::UnsupportedSchemaVersion {
+                found: dashboard.schema_version,
+            });
+        }
+        for result in dashboard.results_mut() {
+            if result.timed_out && matches!(result.status, Status::Failure) {
+                result.status = Status::Timeout;
+            }
+        }
+        Ok(dashboard)
+    }
+
+    /// The stable and bleeding phases ran different toolchains.
+    pub fn toolchains_differ(&self) -> bool {
+        !self
+            .stable_toolchain_version
+            .same_versions(&self.bleeding_toolchain_version)
+    }
+
+    /// Records whether bleeding moved since `prior`, the previous `latest` file.
+    pub fn compare_bleeding_with(&mut self, prior: Option<&MoonBuildDashboard>) {
+        self.bleeding_changed_since_latest = prior.map(|prior| {
+            !self
+                .bleeding_toolchain_version
+                .same_versions(&prior.bleeding_toolchain_version)
+        });
+    }
+
+    /// Sources of this run missing in `prior` and the other way around, matched
+    /// by name, i.e. git url or mooncakes.io package name.
+    pub fn source_churn(&self, prior: &MoonBuildDashboard) -> SourceChurn {
+        let names = |dashboard: &MoonBuildDashboard| -> std::collections::BTreeSet<String> {
+            dashboard
+                .sources
+                .iter()
+                .map(|s| s.name().to_string())
+                .collect()
+        };
+        let (now, before) = (names(self), names(prior));
+        SourceChurn {
+            added: now.difference(&before).cloned().collect(),
+            removed: before.difference(&now).cloned().collect(),
+        }
+    }
+
+    /// Counts and elapsed percentiles over the stable and bleeding data.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary::default();
+        for state in self
+            .stable_release_data
+            .iter()
+            .chain(self.bleeding_release_data.iter())
+        {
+            summary.add(state);
+        }
+        let mut all = vec![];
+        let mut by_backend: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        // skipped commands are recorded with zero elapsed
+        for cell in self.cells().filter(|c| c.result.elapsed > 0) {
+            all.push(cell.result.elapsed);
+            by_backend
+                .entry(cell.backend.to_string())
+                .or_default()
+                .push(cell.result.elapsed);
+        }
+        summary.elapsed = Percentiles::of(all);
+        // excluded sources are never fetched
+        summary.fetch_ms = Percentiles::of(
+            self.stable_release_data
+                .iter()
+                .chain(self.bleeding_release_data.iter())
+                .map(|state| state.fetch_ms)
+                .filter(|ms| *ms > 0)
+                .collect(),
+        );
+        summary.elapsed_by_backend = by_backend
+            .into_iter()
+            .filter_map(|(backend, v)| Some((backend, Percentiles::of(v)?)))
+            .collect();
+        summary
+    }
+
+    /// Bleeding cells that failed or timed out while the same stable cell succeeded.
+    pub fn regressions(&self) -> Vec<Cell<'_>> {
+        let stable_ok: std::collections::BTreeSet<_> = self
+            .cells()
+            .filter(|c| c.toolchain == "stable" && matches!(c.result.status, Status::Success))
+            .map(|c| (c.source, c.rev, c.command, c.backend))
+            .collect();
+        self.cells()
+            .filter(|c| c.toolchain == "bleeding" && !matches!(c.result.status, Status::Success))
+            .filter(|c| stable_ok.contains(&(c.source, c.rev, c.command, c.backend)))
+            .collect()
+    }
+
+    /// A one line summary of `regressions` for a commit status, e.g.
+    /// `bleeding: 3 regressions (user/a wasm-test, user/b js-build)`, at most
+    /// `max_chars` long. Cells that don't fit are elided as `… +N more`.
+    pub fn regression_digest(&self, max_chars: usize) -> String {
+        let cells: Vec<String> = self
+            .regressions()
+            .iter()
+            .map(|c| format!("{} {}-{}", c.source, c.backend, c.command))
+            .collect();
+        let head = match cells.len() {
+            0 => "bleeding: no regressions".to_string(),
+            1 => "bleeding: 1 regression".to_string(),
+            n => format!("bleeding: {} regressions", n),
+        };
+        let fits = |s: &String| s.chars().count() <= max_chars;
+        if cells.is_empty() {
+            return head.chars().take(max_chars).collect();
+        }
+        let full = format!("{} ({})", head, cells.join(", "));
+        if fits(&full) {
+            return full;
+        }
+        for shown in (0..cells.len()).rev() {
+            let more = format!("… +{} more", cells.len() - shown);
+            let listed: Vec<&str> = cells[..shown]
+                .iter()
+                .map(|c| c.as_str())
+                .chain([more.as_str()])
+                .collect();
+            let digest = format!("{} ({})", head, listed.join(", "));
+            if fits(&digest) {
+                return digest;
+            }
+        }
+        head.chars().take(max_chars).collect()
+    }
+
+    /// Every distinct error a source ran into, stable first. The other sources
+    /// of the run were still built.
+    pub fn source_errors(&self) -> Vec<SourceError<'_>> {
+        let mut errors = vec![];
+        for (toolchain, data) in [
+            ("stable", &self.stable_release_data),
+            ("bleeding", &self.bleeding_release_data),
+        ] {
+            for state in data.iter() {
+                let source = self.source_of(state).map(|s| s.name()).unwrap_or_default();
+                // a failed fetch records the same reason for every rev
+                let reasons: std::collections::BTreeSet<&str> = state
+                    .skipped
+                    .values()
+                    .filter(|skip| skip.kind == SkipKind::Error)
+                    .map(|skip| skip.message.as_str())
+                    .collect();
+                errors.extend(reasons.into_iter().map(|reason| SourceError {
+                    toolchain,
+                    source,
+                    reason,
+                }));
+            }
+        }
+        errors
+    }
+
+    /// The source a `BuildState` refers to.
+    pub fn source_of(&self, state: &BuildState) -> Option<&MooncakeSource> {
+        self.sources.iter().find(|s| s.get_index() == state.source)
+    }
+
+    /// Every executed cell of the matrix, stable first.
+    pub fn cells(&self) -> impl Iterator<Item = Cell<'_>> {
+        [
+            ("stable", &self.stable_release_data),
+            ("bleeding", &self.bleeding_release_data),
+        ]
+        .into_iter()
+        .flat_map(move |(toolchain, data)| {
+            data.iter().flat_map(move |state| {
+                let source = self.source_of(state);
+                state
+                    .cbts
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, cbt)| cbt.as_ref().map(|cbt| (i, cbt)))
+                    .flat_map(move |(i, cbt)| {
+                        let name = source.map(|s| s.name()).unwrap_or_default();
+                        let rev = source
+                            .and_then(|s| s.revs().get(i))
+                            .map(|r| r.as_str())
+                            .unwrap_or_default();
+                        cbt.results().map(move |(command, backend, result)| Cell {
+                            toolchain,
+                            source: name,
+                            rev,
+                            command,
+                            backend,
+                            result,
+                        })
+                    })
+            })
+        })
+    }
+
+    /// Every result of the run, including pre-build commands.
+    pub fn results_mut(&mut self) -> impl Iterator<Item = &mut ExecuteResult> {
+        self.stable_release_data
+            .iter_mut()
+            .chain(self.bleeding_release_data.iter_mut())
+            .flat_map(|state| {
+                state.pre_build.values_mut().chain(
+                    state
+                        .cbts
+                        .iter_mut()
+                        .flatten()
+                        .flat_map(|cbt| cbt.results_mut()),
+                )
+            })
+    }
+
+    /// Reads a dashboard file as written by `stat`, gzipped when the name ends with `.gz`.
+    pub fn from_file(path: &Path) -> Result<Self, DashboardReadError> {
+        let fp = std::fs::File::open(path)?;
+        let mut content = String::new();
+        if path.extension().and_then(|ext| ext.to_str()) == Some("gz") {
+            flate2::read::GzDecoder::new(fp).read_to_string(&mut content)?;
+        } else {
+            std::io::BufReader::new(fp).read_to_string(&mut content)?;
+        }
+        Self::from_json(content.trim())
+    }
+}
+
+/// One executed cell of the matrix, see `MoonBuildDashboard::cells`.
+#[derive(Debug, Clone, Copy)]
+pub struct Cell<'a> {
+    /// `stable` or `bleeding`
+    pub toolchain: &'static str,
+    pub source: &'a str,
+    pub rev: &'a str,
+    pub command: &'static str,
+    pub backend: &'a str,
+    pub result: &'a ExecuteResult,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Status {
     Success,
     Failure,
+    /// killed after its timeout, or not run past the deadline of its backend
+    Timeout,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -105,6 +723,106 @@ pub struct ExecuteResult {
     pub elapsed: u64,
     pub stdout: String,
     pub stderr: String,
+    /// parsed from the summary of `moon test`, when available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tests_passed: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tests_failed: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tests_total: Option<u32>,
+    /// the negated signal number for a process killed by a signal on Unix,
+    /// e.g. `-11` for a segfault, `None` for old data
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// the output warns about artifacts of another compiler version
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub compiler_mismatch: bool,
+    /// a build that recompiled nothing, which explains a near zero `elapsed`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fully_cached: bool,
+    /// the `-j` of `--moon-jobs`, `None` for moon's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<u32>,
+    /// killed after the `--timeout-secs` of its kind, set along with
+    /// `Status::Timeout` for readers of older schemas
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// failed test cases parsed from the output of `moon test`, best-effort
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_tests: Vec<FailedTest>,
+    /// parsed from `--output-json`, only with `--json-diagnostics`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    /// files under `target/<backend>/release` after a build, with `--list-artifacts`.
+    /// Empty for a successful build is suspicious.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifacts: Option<Vec<String>>,
+    /// how `stdout` and `stderr` are encoded, `None` for plain text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_encoding: Option<LogEncoding>,
+    /// CPU time of the command in user and kernel mode, Unix only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sys_ms: Option<u64>,
+}
+
+/// A failure with no output, for results that set only what they know.
+impl Default for ExecuteResult {
+    fn default() -> Self {
+        ExecuteResult {
+            status: Status::Failure,
+            start_time: String::new(),
+            elapsed: 0,
+            stdout: String::new(),
+            stderr: String::new(),
+            tests_passed: None,
+            tests_failed: None,
+            tests_total: None,
+            exit_code: None,
+            compiler_mismatch: false,
+            fully_cached: false,
+            jobs: None,
+            timed_out: false,
+            failed_tests: vec![],
+            diagnostics: vec![],
+            artifacts: None,
+            log_encoding: None,
+            user_ms: None,
+            sys_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogEncoding {
+    /// gzipped, then base64 encoded, see `output::decode_logs`
+    #[serde(rename = "gzip+base64")]
+    GzipBase64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedTest {
+    /// test file as printed by moon, possibly with a line number
+    pub file: String,
+    /// test name or index, empty when moon didn't print one
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DiagnosticLevel {
+    Error,
+    Warning,
+}
+
+/// An error or warning reported by moon for a source file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: u32,
+    pub level: DiagnosticLevel,
+    pub message: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -112,6 +830,12 @@ pub struct BackendState {
     pub wasm: ExecuteResult,
     pub wasm_gc: ExecuteResult,
     pub js: ExecuteResult,
+    /// `None` in files written before the native backend was run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub native: Option<ExecuteResult>,
+    /// results of backends registered with `--extra-backend`, keyed by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dynamic: BTreeMap<String, ExecuteResult>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -119,10 +843,923 @@ pub struct CBT {
     pub check: BackendState,
     pub build: BackendState,
     pub test: BackendState,
+    /// `(errors, warnings)` per file, only with `--json-diagnostics`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics_by_file: Option<BTreeMap<String, (u32, u32)>>,
+}
+
+impl BackendState {
+    /// The results of a command on each backend of the matrix. Errors with a
+    /// builtin backend that has no result.
+    pub fn collect(
+        results: impl IntoIterator<Item = (MatrixBackend, ExecuteResult)>,
+    ) -> Result<BackendState, Backend> {
+        let mut builtin = BTreeMap::new();
+        let mut dynamic = BTreeMap::new();
+        for (backend, result) in results {
+            match backend {
+                MatrixBackend::Builtin(backend) => builtin.insert(backend, result),
+                MatrixBackend::Dynamic(backend) => dynamic.insert(backend.name, result),
+            };
+        }
+        let mut take = |backend| builtin.remove(&backend).ok_or(backend);
+        Ok(BackendState {
+            wasm: take(Backend::Wasm)?,
+            wasm_gc: take(Backend::WasmGC)?,
+            js: take(Backend::Js)?,
+            native: Some(take(Backend::Native)?),
+            dynamic,
+        })
+    }
+
+    /// The result of a builtin backend, `None` if it was not run.
+    pub fn get(&self, backend: Backend) -> Option<&ExecuteResult> {
+        match backend {
+            Backend::Wasm => Some(&self.wasm),
+            Backend::WasmGC => Some(&self.wasm_gc),
+            Backend::Js => Some(&self.js),
+            Backend::Native => self.native.as_ref(),
+        }
+    }
+
+    /// All results with their backend name, in the order of `Backend::all`,
+    /// then the dynamic backends.
+    pub fn results(&self) -> impl Iterator<Item = (&str, &ExecuteResult)> {
+        Backend::all()
+            .into_iter()
+            .filter_map(|backend| Some((backend.to_flag(), self.get(backend)?)))
+            .chain(self.dynamic.iter().map(|(k, v)| (k.as_str(), v)))
+    }
+
+    pub fn results_mut(&mut self) -> impl Iterator<Item = &mut ExecuteResult> {
+        let BackendState {
+            wasm,
+            wasm_gc,
+            js,
+            native,
+            dynamic,
+        } = self;
+        let (mut wasm, mut wasm_gc, mut js) = (Some(wasm), Some(wasm_gc), Some(js));
+        let mut native = native.as_mut();
+        Backend::all()
+            .into_iter()
+            .filter_map(move |backend| match backend {
+                Backend::Wasm => wasm.take(),
+                Backend::WasmGC => wasm_gc.take(),
+                Backend::Js => js.take(),
+                Backend::Native => native.take(),
+            })
+            .chain(dynamic.values_mut())
+    }
+}
+
+impl CBT {
+    /// All results as `(command, backend, result)`.
+    pub fn results(&self) -> impl Iterator<Item = (&'static str, &str, &ExecuteResult)> {
+        [
+            ("check", &self.check),
+            ("build", &self.build),
+            ("test", &self.test),
+        ]
+        .into_iter()
+        .flat_map(|(cmd, state)| state.results().map(move |(b, r)| (cmd, b, r)))
+    }
+
+    pub fn results_mut(&mut self) -> impl Iterator<Item = &mut ExecuteResult> {
+        [&mut self.check, &mut self.build, &mut self.test]
+            .into_iter()
+            .flat_map(|state| state.results_mut())
+    }
+
+    /// Counts `(errors, warnings)` per file over all results. The same diagnostic
+    /// reported by several commands or backends is counted once.
+    pub fn count_diagnostics_by_file(&self) -> BTreeMap<String, (u32, u32)> {
+        let unique: std::collections::BTreeSet<&Diagnostic> = self
+            .results()
+            .flat_map(|(_, _, r)| r.diagnostics.iter())
+            .collect();
+        let mut counts: BTreeMap<String, (u32, u32)> = BTreeMap::new();
+        for d in unique {
+            let (errors, warnings) = counts.entry(d.file.clone()).or_default();
+            match d.level {
+                DiagnosticLevel::Error => *errors += 1,
+                DiagnosticLevel::Warning => *warnings += 1,
+            }
+        }
+        counts
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BuildState {
     pub source: usize,
     pub cbts: Vec<Option<CBT>>,
+    /// output of the source's pre-build command, keyed by position in `cbts`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pre_build: BTreeMap<usize, ExecuteResult>,
+    /// why the matrix was not run, keyed by position in `cbts`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub skipped: BTreeMap<usize, SkipReason>,
+    /// one per rev of a git source, in the order of `cbts`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revs: Vec<RevStatus>,
+    /// time spent cloning or downloading the source, before any build
+    #[serde(default)]
+    pub fetch_ms: u64,
+    /// whether the source was cloned or downloaded over the network
+    #[serde(default)]
+    pub network_used: bool,
+    /// why the source could not be cloned or downloaded, the `None` cbts of a
+    /// source with a fetch error are infra failures rather than build failures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetch_error: Option<String>,
+    /// dependency versions of the last built rev or version with `--record-deps`,
+    /// to tell which dependency bump broke a build
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub deps: BTreeMap<String, String>,
+}
+
+/// How a rev of a git source was checked out before its matrix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RevStatus {
+    /// `sha` is the commit the rev resolved to
+    CheckedOut { rev: String, sha: String },
+    /// a stale branch or tag in the config, or a failed checkout
+    CheckoutFailed { rev: String, reason: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipKind {
+    /// not meant to run on this OS
+    Os,
+    /// left out by a command line filter
+    Filtered,
+    /// not supported by the toolchain or backend
+    Unsupported,
+    /// excluded by the config
+    Excluded,
+    /// checkout, download or pre-build failed
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkipReason {
+    pub kind: SkipKind,
+    pub message: String,
+}
+
+/// Counts of a run. Successes and failures count cells, skips count
+/// revs/versions whose whole matrix was not run.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Summary {
+    pub success: usize,
+    pub failure: usize,
+    /// not counted in `failure`
+    #[serde(default)]
+    pub timeout: usize,
+    pub skipped_os: usize,
+    pub skipped_filtered: usize,
+    pub skipped_unsupported: usize,
+    pub skipped_excluded: usize,
+    pub skipped_error: usize,
+    /// Percentiles of `elapsed` over all executed commands.
+    #[serde(default)]
+    pub elapsed: Option<Percentiles>,
+    #[serde(default)]
+    pub elapsed_by_backend: BTreeMap<String, Percentiles>,
+    /// Percentiles of the clone/download time of the fetched sources.
+    #[serde(default)]
+    pub fetch_ms: Option<Percentiles>,
+}
+
+/// Nearest-rank percentiles of elapsed milliseconds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+/// The nearest-rank `p`th percentile of `sorted`, which must not be empty.
+pub fn nearest_rank(sorted: &[u64], p: usize) -> u64 {
+    sorted[(sorted.len() * p).div_ceil(100).max(1) - 1]
+}
+
+impl Percentiles {
+    pub fn of(mut values: Vec<u64>) -> Option<Percentiles> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_unstable();
+        let rank = |p: usize| nearest_rank(&values, p);
+        Some(Percentiles {
+            p50: rank(50),
+            p90: rank(90),
+            p99: rank(99),
+        })
+    }
+}
+
+/// Limits a run must stay within to exit successfully.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Thresholds {
+    /// fraction of executed cells, 0.0 to 1.0
+    pub max_failure_rate: Option<f64>,
+    pub max_regressions: Option<usize>,
+    /// any source that could not be fetched, checked out or pre-built
+    pub fail_on_error: bool,
+}
+
+impl Thresholds {
+    /// One message per exceeded threshold.
+    pub fn check(&self, dashboard: &MoonBuildDashboard) -> Vec<String> {
+        let mut exceeded = vec![];
+        if let Some(max) = self.max_failure_rate {
+            let rate = dashboard.summary().failure_rate();
+            if rate > max {
+                exceeded.push(format!("failure rate {:.3} exceeds {:.3}", rate, max));
+            }
+        }
+        if let Some(max) = self.max_regressions {
+            let regressions = dashboard.regressions().len();
+            if regressions > max {
+                exceeded.push(format!("{} regressions exceed {}", regressions, max));
+            }
+        }
+        if self.fail_on_error {
+            let errors = dashboard.source_errors();
+            if !errors.is_empty() {
+                exceeded.push(format!("{} source errors", errors.len()));
+            }
+        }
+        exceeded
+    }
+}
+
+impl Summary {
+    /// Failed or timed out cells over executed cells, 0 when nothing ran.
+    pub fn failure_rate(&self) -> f64 {
+        let failed = self.failure + self.timeout;
+        let total = self.success + failed;
+        if total == 0 {
+            0.0
+        } else {
+            failed as f64 / total as f64
+        }
+    }
+
+    pub fn add(&mut self, state: &BuildState) {
+        for (i, cbt) in state.cbts.iter().enumerate() {
+            let Some(cbt) = cbt else {
+                let kind = state.skipped.get(&i).map(|r| r.kind);
+                match kind.unwrap_or(SkipKind::Error) {
+                    SkipKind::Os => self.skipped_os += 1,
+                    SkipKind::Filtered => self.skipped_filtered += 1,
+                    SkipKind::Unsupported => self.skipped_unsupported += 1,
+                    SkipKind::Excluded => self.skipped_excluded += 1,
+                    SkipKind::Error => self.skipped_error += 1,
+                }
+                continue;
+            };
+            for (_, _, r) in cbt.results() {
+                match r.status {
+                    Status::Success => self.success += 1,
+                    Status::Failure => self.failure += 1,
+                    Status::Timeout => self.timeout += 1,
+                }
+            }
+        }
+    }
+}
+
+impl BuildState {
+    /// Whether every rev or version was built and every command succeeded.
+    pub fn all_succeeded(&self) -> bool {
+        !self.cbts.is_empty()
+            && self.cbts.iter().all(|cbt| {
+                cbt.as_ref().is_some_and(|cbt| {
+                    cbt.results()
+                        .all(|(_, _, r)| matches!(r.status, Status::Success))
+                })
+            })
+    }
+
+    pub fn new(source: usize) -> Self {
+        BuildState {
+            source,
+            cbts: vec![],
+            pre_build: BTreeMap::new(),
+            skipped: BTreeMap::new(),
+            revs: vec![],
+            fetch_ms: 0,
+            network_used: false,
+            fetch_error: None,
+            deps: BTreeMap::new(),
+        }
+    }
+
+    /// A source none of whose revs/versions are run, for `kind`.
+    pub fn skip_all(source: &MooncakeSource, kind: SkipKind, message: String) -> Self {
+        let mut state = BuildState::new(source.get_index());
+        for _ in source.revs() {
+            state.skip(kind, message.clone());
+        }
+        state
+    }
+
+    /// Records a source that could not be cloned, none of its `revs` are run.
+    pub fn fetch_failed(&mut self, revs: usize, error: String) {
+        for _ in 0..revs {
+            self.skip(SkipKind::Error, format!("failed to fetch: {}", error));
+        }
+        self.fetch_error = Some(error);
+    }
+
+    /// Records a rev/version whose matrix was not run.
+    pub fn skip(&mut self, kind: SkipKind, message: String) {
+        self.skipped
+            .insert(self.cbts.len(), SkipReason { kind, message });
+        self.cbts.push(None);
+    }
+}
+
+#[test]
+fn every_backend_result_is_visited() {
+    let extra: Vec<DynamicBackend> = vec!["llvm=llvm".parse().unwrap()];
+    let backends = MatrixBackend::all(&extra);
+    let mut state = BackendState::collect(
+        backends
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (b.clone(), sample_result(Status::Success, i as u64))),
+    )
+    .unwrap();
+    // every result serialized, but `dynamic`, is one of `Backend::all`
+    let json = serde_json::to_value(&state).unwrap();
+    assert_eq!(json.as_object().unwrap().len() - 1, Backend::all().len());
+
+    assert_eq!(
+        state
+            .results()
+            .map(|(name, r)| (name, r.elapsed))
+            .collect::<Vec<_>>(),
+        backends
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (b.name(), i as u64))
+            .collect::<Vec<_>>()
+    );
+    for result in state.results_mut() {
+        result.elapsed += 100;
+    }
+    let json = serde_json::to_value(&state).unwrap();
+    let fields = json.as_object().unwrap();
+    for (key, result) in fields.iter().filter(|(k, _)| *k != "dynamic") {
+        assert!(result["elapsed"].as_u64().unwrap() >= 100, "{}", key);
+    }
+    assert!(fields["dynamic"]["llvm"]["elapsed"].as_u64().unwrap() >= 100);
+
+    // a builtin backend without a result
+    assert_eq!(
+        BackendState::collect(
+            backends[1..]
+                .iter()
+                .map(|b| (b.clone(), sample_result(Status::Success, 0)))
+        )
+        .unwrap_err(),
+        Backend::Wasm
+    );
+}
+
+#[test]
+fn dynamic_backend_args_and_serialize() {
+    let backend: DynamicBackend = "llvm=llvm".parse().unwrap();
+    assert_eq!(backend.name, "llvm");
+    assert!("llvm".parse::<DynamicBackend>().is_err());
+    assert!("native=native".parse::<DynamicBackend>().is_err());
+    assert_eq!(
+        MoonCommand::Build.args(&backend.flag, true, None),
+        vec!["build", "-q", "--target", "llvm"]
+    );
+    assert_eq!(
+        MoonCommand::Test.args("js", false, Some("4")),
+        vec!["test", "-q", "--build-only", "--target", "js", "-j", "4"]
+    );
+    assert_eq!(
+        MoonCommand::Check.args("js", false, Some("4")),
+        vec!["check", "-q", "--target", "js"]
+    );
+    // a dynamic backend is not attributed to a builtin one
+    let plan = plan_matrix(std::slice::from_ref(&backend));
+    assert_eq!(plan.len(), 3 * (Backend::all().len() + 1));
+    assert_eq!(
+        plan.iter()
+            .filter(|(_, b)| *b == MatrixBackend::Dynamic(backend.clone()))
+            .map(|(cmd, b)| (*cmd, b.name(), b.flag()))
+            .collect::<Vec<_>>(),
+        MoonCommand::all().map(|cmd| (cmd, "llvm", "llvm"))
+    );
+
+    let result = || sample_result(Status::Success, 0);
+    let mut state = BackendState {
+        wasm: result(),
+        wasm_gc: result(),
+        js: result(),
+        native: None,
+        dynamic: BTreeMap::new(),
+    };
+    let json = serde_json::to_value(&state).unwrap();
+    assert!(json.get("dynamic").is_none());
+    // written before the native backend was run
+    assert!(json.get("native").is_none());
+    let old: BackendState = serde_json::from_value(json).unwrap();
+    assert!(old.native.is_none());
+    assert_eq!(old.results().count(), Backend::all().len() - 1);
+
+    state.native = Some(result());
+    state.dynamic.insert(backend.name.clone(), result());
+    let json = serde_json::to_string(&state).unwrap();
+    let state: BackendState = serde_json::from_str(&json).unwrap();
+    assert!(matches!(state.dynamic["llvm"].status, Status::Success));
+    assert_eq!(
+        state.results().map(|(name, _)| name).collect::<Vec<_>>(),
+        ["wasm", "wasm-gc", "js", "native", "llvm"]
+    );
+}
+
+#[test]
+fn work_items_match_matrix_size() {
+    let sources = vec![
+        MooncakeSource::Git {
+            url: "https://github.com/moonbitlang/core".to_string(),
+            rev: vec!["a".to_string(), "b".to_string()],
+            index: 0,
+            pre_build: None,
+        },
+        MooncakeSource::MooncakesIO {
+            name: "alice/pkg".to_string(),
+            version: vec!["0.1.0".to_string()],
+            index: 1,
+            pre_build: None,
+            private: None,
+        },
+    ];
+    assert_eq!(plan_work_items(&sources, "linux", &[], None).len(), 3 * 12);
+
+    let extra = vec!["llvm=llvm".parse().unwrap()];
+    let items = plan_work_items(&sources, "linux", &extra, None);
+    assert_eq!(items.len(), 3 * 15);
+    assert!(items.iter().any(|item| item.source == 1
+        && item.args == ["test", "-q", "--build-only", "--target", "native"]));
+    assert!(items
+        .iter()
+        .any(|item| item.source == 1
+            && item.args == ["test", "-q", "--build-only", "--target", "llvm"]));
+}
+
+#[test]
+fn matrix_cost_of_plan() {
+    let history = sample_dashboard();
+    let sources = vec![
+        history.sources[1].clone(),
+        MooncakeSource::MooncakesIO {
+            name: "bob/new".to_string(),
+            version: vec!["0.1.0".to_string()],
+            index: 1,
+            pre_build: None,
+            private: None,
+        },
+    ];
+    let extra = vec!["llvm=llvm".parse().unwrap()];
+    let items = plan_work_items(&sources, "linux", &extra, None);
+    assert_eq!(
+        matrix_cost(&items, 2, None),
+        MatrixCost {
+            commands: 2 * 15 * 2,
+            ..Default::default()
+        }
+    );
+
+    // wasm 100ms, wasm-gc 200ms, js 300ms for all 3 commands, for both
+    // sources, native and llvm were never run
+    let cost = matrix_cost(&items, 2, Some(&history));
+    assert_eq!(cost.commands, 60);
+    assert_eq!(cost.unknown, 2 * 3 * 2 * 2);
+    assert_eq!(cost.estimated_ms, Some(2 * 3 * (100 + 200 + 300) * 2));
+    let cost = matrix_cost(&items, 1, Some(&history));
+    assert_eq!(cost.estimated_ms, Some(2 * 3 * (100 + 200 + 300)));
+}
+
+#[test]
+fn status_round_trip() {
+    for status in [Status::Success, Status::Failure, Status::Timeout] {
+        let json = serde_json::to_string(&sample_result(status, 1)).unwrap();
+        let result: ExecuteResult = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            json,
+            "{:?}",
+            result.status
+        );
+    }
+
+    // written before `Status::Timeout`, with only the bool
+    let mut dashboard = sample_dashboard();
+    dashboard.schema_version = 1;
+    let cbt = dashboard.stable_release_data[0].cbts[0].as_mut().unwrap();
+    cbt.check.js.status = Status::Failure;
+    cbt.check.js.timed_out = true;
+    let json = serde_json::to_string(&dashboard).unwrap();
+    let dashboard = MoonBuildDashboard::from_json(&json).unwrap();
+    let cbt = dashboard.stable_release_data[0].cbts[0].as_ref().unwrap();
+    assert!(matches!(cbt.check.js.status, Status::Timeout));
+    assert_eq!(dashboard.summary().timeout, 1);
+}
+
+#[test]
+fn schema_version_policy() {
+    let v0 = r#"{
+        "run_id": "1",
+        "run_number": "1",
+        "start_time": "2024-01-01T00:00:00+08:00",
+        "sources": [],
+        "stable_toolchain_version": {"label": "Stable", "moon_version": "", "moonc_version": ""},
+        "stable_release_data": [],
+        "bleeding_toolchain_version": {"label": "Bleeding", "moon_version": "", "moonc_version": ""},
+        "bleeding_release_data": []
+    }"#;
+    let mut dashboard = MoonBuildDashboard::from_json(v0).unwrap();
+    assert_eq!(dashboard.schema_version, 0);
+
+    dashboard.schema_version = SCHEMA_VERSION;
+    let json = serde_json::to_string(&dashboard).unwrap();
+    assert!(json.contains(&format!("\"schema_version\":{}", SCHEMA_VERSION)));
+    assert!(MoonBuildDashboard::from_json(&json).is_ok());
+
+    dashboard.schema_version = SCHEMA_VERSION + 1;
+    let json = serde_json::to_string(&dashboard).unwrap();
+    assert!(matches!(
+        MoonBuildDashboard::from_json(&json),
+        Err(DashboardReadError::UnsupportedSchemaVersion { .. })
+    ));
+}
+
+#[test]
+fn summary_classifies_skips() {
+    let mut state = BuildState::new(0);
+    for kind in [
+        SkipKind::Os,
+        SkipKind::Filtered,
+        SkipKind::Unsupported,
+        SkipKind::Excluded,
+        SkipKind::Error,
+        SkipKind::Os,
+    ] {
+        state.skip(kind, format!("{:?}", kind));
+    }
+    // a failed matrix without recorded reason
+    state.cbts.push(None);
+    let json = serde_json::to_string(&state).unwrap();
+
+    let mut summary = Summary::default();
+    summary.add(&serde_json::from_str(&json).unwrap());
+    assert_eq!(
+        summary,
+        Summary {
+            success: 0,
+            failure: 0,
+            skipped_os: 2,
+            skipped_filtered: 1,
+            skipped_unsupported: 1,
+            skipped_excluded: 1,
+            skipped_error: 2,
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn elapsed_percentiles() {
+    let p = Percentiles::of((1..=100).rev().collect()).unwrap();
+    assert_eq!((p.p50, p.p90, p.p99), (50, 90, 99));
+    assert_eq!(
+        Percentiles::of(vec![7]),
+        Some(Percentiles {
+            p50: 7,
+            p90: 7,
+            p99: 7
+        })
+    );
+    assert_eq!(Percentiles::of(vec![]), None);
+
+    let mut dashboard = sample_dashboard();
+    // a skipped command does not count
+    dashboard.stable_release_data[0].cbts[0]
+        .as_mut()
+        .unwrap()
+        .check
+        .js
+        .elapsed = 0;
+    let summary = dashboard.summary();
+    let elapsed = summary.elapsed.unwrap();
+    assert_eq!((elapsed.p50, elapsed.p90, elapsed.p99), (200, 300, 300));
+    assert_eq!(summary.elapsed_by_backend.len(), 3);
+    assert_eq!(summary.elapsed_by_backend["wasm"].p99, 100);
+    // nothing was fetched
+    assert_eq!(summary.fetch_ms, None);
+    dashboard.stable_release_data[0].fetch_ms = 40;
+    assert_eq!(dashboard.summary().fetch_ms.unwrap().p50, 40);
+}
+
+#[cfg(test)]
+pub(crate) fn sample_result(status: Status, elapsed: u64) -> ExecuteResult {
+    ExecuteResult {
+        status,
+        elapsed,
+        ..Default::default()
+    }
+}
+
+/// Two sources: a git repo with a built rev and a skipped one, and a mooncake
+/// whose `js` check fails. The bleeding data is the same as the stable one.
+#[cfg(test)]
+pub(crate) fn sample_dashboard() -> MoonBuildDashboard {
+    let state = |js_check: Status| BackendState {
+        wasm: sample_result(Status::Success, 100),
+        wasm_gc: sample_result(Status::Success, 200),
+        js: sample_result(js_check, 300),
+        native: None,
+        dynamic: BTreeMap::new(),
+    };
+    let cbt = |js_check| CBT {
+        check: state(js_check),
+        build: state(Status::Success),
+        test: state(Status::Success),
+        diagnostics_by_file: None,
+    };
+    let data = || {
+        let mut git = BuildState::new(0);
+        git.cbts.push(Some(cbt(Status::Success)));
+        git.skip(SkipKind::Error, "failed to checkout".to_string());
+        let mut mooncake = BuildState::new(1);
+        mooncake.cbts.push(Some(cbt(Status::Failure)));
+        vec![git, mooncake]
+    };
+    let toolchain = |label| ToolChainVersion {
+        label,
+        moon_version: "moon 0.1.0".to_string(),
+        moonc_version: "v0.1.0".to_string(),
+        core_version: None,
+    };
+    MoonBuildDashboard {
+        schema_version: SCHEMA_VERSION,
+        run_id: "1".to_string(),
+        run_number: "1".to_string(),
+        start_time: "2024-01-01T00:00:00+08:00".to_string(),
+        sources: vec![
+            MooncakeSource::Git {
+                url: "https://github.com/moonbitlang/core".to_string(),
+                rev: vec!["main".to_string(), "missing".to_string()],
+                index: 0,
+                pre_build: None,
+            },
+            MooncakeSource::MooncakesIO {
+                name: "alice/pkg".to_string(),
+                version: vec!["0.1.0".to_string()],
+                index: 1,
+                pre_build: None,
+                private: None,
+            },
+        ],
+        stable_toolchain_version: toolchain(ToolChainLabel::Stable),
+        stable_release_data: data(),
+        bleeding_toolchain_version: toolchain(ToolChainLabel::Bleeding),
+        bleeding_release_data: data(),
+        reproducibility: ReproInfo::default(),
+        pruned: vec![],
+        warmup: BTreeMap::new(),
+        bleeding_changed_since_latest: None,
+        updated_packages: vec![],
+        labels: BTreeMap::new(),
+    }
+}
+
+#[test]
+fn cells_of_sample_dashboard() {
+    let dashboard = sample_dashboard();
+    let cells: Vec<Cell> = dashboard.cells().collect();
+    assert_eq!(cells.len(), 2 * 2 * 9);
+    assert_eq!(cells[0].source, "https://github.com/moonbitlang/core");
+    assert_eq!(cells[0].rev, "main");
+    let failed: Vec<&Cell> = cells
+        .iter()
+        .filter(|c| matches!(c.result.status, Status::Failure))
+        .collect();
+    assert_eq!(failed.len(), 2);
+    assert_eq!(
+        (failed[0].source, failed[0].command, failed[0].backend),
+        ("alice/pkg", "check", "js")
+    );
+}
+
+#[test]
+fn failure_thresholds() {
+    let mut dashboard = sample_dashboard();
+    // 2 of 36 cells fail, none regressed
+    let below = Thresholds {
+        max_failure_rate: Some(0.1),
+        max_regressions: Some(0),
+        fail_on_error: false,
+    };
+    assert!(below.check(&dashboard).is_empty());
+    assert!(Thresholds::default().check(&dashboard).is_empty());
+
+    dashboard.bleeding_release_data[0].cbts[0]
+        .as_mut()
+        .unwrap()
+        .test
+        .wasm
+        .status = Status::Failure;
+    assert_eq!(dashboard.regressions().len(), 1);
+    let above = Thresholds {
+        max_failure_rate: Some(0.05),
+        max_regressions: Some(0),
+        fail_on_error: false,
+    };
+    assert_eq!(above.check(&dashboard).len(), 2);
+}
+
+#[test]
+fn source_errors_of_run() {
+    let mut dashboard = sample_dashboard();
+    let checkout = |toolchain| SourceError {
+        toolchain,
+        source: "https://github.com/moonbitlang/core",
+        reason: "failed to checkout",
+    };
+    assert_eq!(
+        dashboard.source_errors(),
+        vec![checkout("stable"), checkout("bleeding")]
+    );
+
+    let revs = dashboard.sources[1].revs().len();
+    let state = &mut dashboard.bleeding_release_data[1];
+    state.cbts.clear();
+    state.fetch_failed(revs, "404 Not Found".to_string());
+    assert_eq!(
+        dashboard.source_errors()[2],
+        SourceError {
+            toolchain: "bleeding",
+            source: "alice/pkg",
+            reason: "failed to fetch: 404 Not Found",
+        }
+    );
+
+    // only a non-zero exit with --fail-on-error
+    assert!(Thresholds::default().check(&dashboard).is_empty());
+    let fail_on_error = Thresholds {
+        fail_on_error: true,
+        ..Default::default()
+    };
+    assert_eq!(fail_on_error.check(&dashboard), vec!["3 source errors"]);
+}
+
+#[test]
+fn regression_digest_is_capped() {
+    let mut dashboard = sample_dashboard();
+    assert_eq!(dashboard.regression_digest(140), "bleeding: no regressions");
+
+    for state in dashboard.bleeding_release_data.iter_mut() {
+        for cbt in state.cbts.iter_mut().flatten() {
+            for result in cbt.results_mut() {
+                result.status = Status::Failure;
+            }
+        }
+    }
+    let regressions = dashboard.regressions().len();
+    assert!(regressions > 3);
+
+    let full = dashboard.regression_digest(usize::MAX);
+    assert!(full.starts_with(&format!(
+        "bleeding: {} regressions (https://github.com/moonbitlang/core wasm-check, ",
+        regressions
+    )));
+    assert!(!full.contains("more"));
+
+    for max in [40, 80, 140] {
+        let digest = dashboard.regression_digest(max);
+        assert!(digest.chars().count() <= max, "{}", digest);
+        assert!(digest.starts_with(&format!("bleeding: {} regressions (", regressions)));
+        // the listed cells and the elided count add up
+        let listed = digest.matches(", ").count();
+        let more: usize = digest
+            .rsplit_once("… +")
+            .and_then(|(_, rest)| rest.strip_suffix(" more)"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(listed + more, regressions, "{}", digest);
+    }
+    assert_eq!(dashboard.regression_digest(10), "bleeding: ");
+}
+
+#[test]
+fn diagnostics_grouped_by_file() {
+    let diagnostic = |file: &str, line, level| Diagnostic {
+        file: file.to_string(),
+        line,
+        level,
+        message: "unused variable".to_string(),
+    };
+    let state = || BackendState {
+        wasm: sample_result(Status::Success, 1),
+        wasm_gc: sample_result(Status::Success, 1),
+        js: sample_result(Status::Success, 1),
+        native: Some(sample_result(Status::Success, 1)),
+        dynamic: BTreeMap::new(),
+    };
+    let mut cbt = CBT {
+        check: state(),
+        build: state(),
+        test: state(),
+        diagnostics_by_file: None,
+    };
+    cbt.check.wasm.diagnostics = vec![
+        diagnostic("lib/a.mbt", 1, DiagnosticLevel::Warning),
+        diagnostic("lib/a.mbt", 2, DiagnosticLevel::Warning),
+        diagnostic("lib/b.mbt", 1, DiagnosticLevel::Error),
+    ];
+    // reported again by another backend
+    cbt.check.js.diagnostics = vec![diagnostic("lib/a.mbt", 1, DiagnosticLevel::Warning)];
+    cbt.build.js.diagnostics = vec![
+        diagnostic("lib/a.mbt", 3, DiagnosticLevel::Error),
+        diagnostic("main/main.mbt", 7, DiagnosticLevel::Warning),
+    ];
+    assert_eq!(
+        cbt.count_diagnostics_by_file(),
+        BTreeMap::from([
+            ("lib/a.mbt".to_string(), (1, 2)),
+            ("lib/b.mbt".to_string(), (1, 0)),
+            ("main/main.mbt".to_string(), (0, 1)),
+        ])
+    );
+}
+
+#[test]
+fn toolchain_version_changes() {
+    let mut dashboard = sample_dashboard();
+    assert!(!dashboard.toolchains_differ());
+    dashboard.bleeding_toolchain_version.moonc_version = "v0.1.1".to_string();
+    assert!(dashboard.toolchains_differ());
+
+    dashboard.compare_bleeding_with(None);
+    assert_eq!(dashboard.bleeding_changed_since_latest, None);
+    let mut prior = sample_dashboard();
+    dashboard.compare_bleeding_with(Some(&prior));
+    assert_eq!(dashboard.bleeding_changed_since_latest, Some(true));
+    prior.bleeding_toolchain_version.moonc_version = "v0.1.1".to_string();
+    dashboard.compare_bleeding_with(Some(&prior));
+    assert_eq!(dashboard.bleeding_changed_since_latest, Some(false));
+}
+
+#[test]
+fn network_of_sources() {
+    let tmp = tempfile::tempdir().unwrap();
+    let git = |url: String| MooncakeSource::Git {
+        url,
+        rev: vec![],
+        index: 0,
+        pre_build: None,
+    };
+    assert!(git("https://github.com/moonbitlang/core".to_string()).needs_network());
+    assert!(!git(tmp.path().to_str().unwrap().to_string()).needs_network());
+    assert!(!git(format!("file://{}", tmp.path().display())).needs_network());
+    assert!(sample_dashboard().sources[1].needs_network());
+}
+
+#[test]
+fn source_churn_since_prior() {
+    let dashboard = sample_dashboard();
+    assert_eq!(
+        dashboard.source_churn(&sample_dashboard()),
+        SourceChurn::default()
+    );
+
+    // the prior run had another mooncake instead of alice/pkg
+    let mut prior = sample_dashboard();
+    prior.sources[1] = MooncakeSource::MooncakesIO {
+        name: "bob/gone".to_string(),
+        version: vec!["0.1.0".to_string()],
+        index: 1,
+        pre_build: None,
+        private: None,
+    };
+    assert_eq!(
+        dashboard.source_churn(&prior),
+        SourceChurn {
+            added: vec!["alice/pkg".to_string()],
+            removed: vec!["bob/gone".to_string()],
+        }
+    );
 }
@@ -0,0 +1,65 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Package dependency graph built from the registry index.
+#[derive(Debug, Default)]
+pub struct DepGraph {
+    /// package -> packages depending on it directly
+    dependents: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DepGraph {
+    /// Builds the graph from `package -> direct dependencies`.
+    pub fn from_deps(deps: &BTreeMap<String, Vec<String>>) -> Self {
+        let mut graph = DepGraph::default();
+        for (pkg, pkg_deps) in deps {
+            for dep in pkg_deps {
+                graph.add_dep(pkg, dep);
+            }
+        }
+        graph
+    }
+
+    pub fn add_dep(&mut self, pkg: &str, dep: &str) {
+        self.dependents
+            .entry(dep.to_string())
+            .or_default()
+            .insert(pkg.to_string());
+    }
+
+    /// All packages that depend on `name`, directly or transitively.
+    pub fn reverse_deps_of(&self, name: &str) -> BTreeSet<String> {
+        let mut result = BTreeSet::new();
+        let mut queue = VecDeque::from([name.to_string()]);
+        while let Some(pkg) = queue.pop_front() {
+            for dependent in self.dependents.get(&pkg).into_iter().flatten() {
+                if dependent != name && result.insert(dependent.clone()) {
+                    queue.push_back(dependent.clone());
+                }
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn reverse_deps_transitive() {
+    let deps: BTreeMap<String, Vec<String>> = [
+        ("a/app", vec!["a/lib", "b/util"]),
+        ("a/lib", vec!["moonbitlang/x"]),
+        ("b/util", vec![]),
+        ("c/other", vec!["b/util"]),
+        // cycles must not loop forever
+        ("d/x", vec!["d/y", "a/app"]),
+        ("d/y", vec!["d/x"]),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.into_iter().map(String::from).collect()))
+    .collect();
+    let graph = DepGraph::from_deps(&deps);
+
+    let rdeps: Vec<String> = graph.reverse_deps_of("moonbitlang/x").into_iter().collect();
+    assert_eq!(rdeps, ["a/app", "a/lib", "d/x", "d/y"]);
+    let rdeps: Vec<String> = graph.reverse_deps_of("b/util").into_iter().collect();
+    assert_eq!(rdeps, ["a/app", "c/other", "d/x", "d/y"]);
+    assert!(graph.reverse_deps_of("c/other").is_empty());
+}
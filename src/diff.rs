@@ -0,0 +1,148 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::dashboard::{MoonBuildDashboard, Status};
+
+/// How a cell changed from the older dashboard to the newer one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// succeeded before, fails now
+    Regression,
+    /// failed before, succeeds now
+    Fix,
+    Unchanged,
+}
+
+/// A cell run in both dashboards, identified by toolchain, source name,
+/// rev or version, command and backend.
+#[derive(Debug, Clone, Copy)]
+pub struct CellChange<'a> {
+    pub toolchain: &'static str,
+    pub source: &'a str,
+    pub rev: &'a str,
+    pub command: &'static str,
+    pub backend: &'a str,
+    pub before: &'a Status,
+    pub after: &'a Status,
+}
+
+impl CellChange<'_> {
+    pub fn change(&self) -> Change {
+        match (self.before, self.after) {
+            (Status::Success, Status::Failure | Status::Timeout) => Change::Regression,
+            (Status::Failure | Status::Timeout, Status::Success) => Change::Fix,
+            _ => Change::Unchanged,
+        }
+    }
+}
+
+/// The cells of `after` that were also run in `before`, in the order of
+/// `after`. Sources are aligned by name and rev, not by index, so adding or
+/// removing a source between the runs does not shift the others.
+pub fn diff<'a>(
+    before: &'a MoonBuildDashboard,
+    after: &'a MoonBuildDashboard,
+) -> Vec<CellChange<'a>> {
+    let before: BTreeMap<_, _> = before
+        .cells()
+        .map(|c| {
+            (
+                (c.toolchain, c.source, c.rev, c.command, c.backend),
+                &c.result.status,
+            )
+        })
+        .collect();
+    after
+        .cells()
+        .filter_map(|c| {
+            let status = before.get(&(c.toolchain, c.source, c.rev, c.command, c.backend))?;
+            Some(CellChange {
+                toolchain: c.toolchain,
+                source: c.source,
+                rev: c.rev,
+                command: c.command,
+                backend: c.backend,
+                before: status,
+                after: &c.result.status,
+            })
+        })
+        .collect()
+}
+
+/// One line per regression and fix, regressions first.
+pub fn render_diff(changes: &[CellChange]) -> String {
+    let mut out = String::new();
+    for (change, label) in [(Change::Regression, "regression"), (Change::Fix, "fix")] {
+        for c in changes.iter().filter(|c| c.change() == change) {
+            let _ = writeln!(
+                out,
+                "{} {} {}@{} {} {}: {:?} -> {:?}",
+                label, c.toolchain, c.source, c.rev, c.command, c.backend, c.before, c.after
+            );
+        }
+    }
+    out
+}
+
+#[test]
+fn diff_of_two_runs() {
+    use crate::dashboard::{sample_dashboard, MooncakeSource};
+
+    let before = sample_dashboard();
+    let mut after = sample_dashboard();
+    // alice/pkg is first now, with a source new in this run in between
+    after.sources = vec![
+        MooncakeSource::MooncakesIO {
+            name: "alice/pkg".to_string(),
+            version: vec!["0.1.0".to_string()],
+            index: 0,
+            pre_build: None,
+            private: None,
+        },
+        MooncakeSource::MooncakesIO {
+            name: "bob/new".to_string(),
+            version: vec!["0.1.0".to_string()],
+            index: 2,
+            pre_build: None,
+            private: None,
+        },
+        MooncakeSource::Git {
+            url: "https://github.com/moonbitlang/core".to_string(),
+            rev: vec!["main".to_string(), "missing".to_string()],
+            index: 1,
+            pre_build: None,
+        },
+    ];
+    for data in [
+        &mut after.stable_release_data,
+        &mut after.bleeding_release_data,
+    ] {
+        data[0].source = 1;
+        data[1].source = 0;
+    }
+    let bleeding = &mut after.bleeding_release_data;
+    bleeding[0].cbts[0].as_mut().unwrap().test.js.status = Status::Failure;
+    bleeding[1].cbts[0].as_mut().unwrap().check.js.status = Status::Success;
+
+    let changes = diff(&before, &after);
+    assert_eq!(changes.len(), before.cells().count());
+    let of = |change| {
+        changes
+            .iter()
+            .filter(|c| c.change() == change)
+            .map(|c| (c.source, c.command, c.backend))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        of(Change::Regression),
+        [("https://github.com/moonbitlang/core", "test", "js")]
+    );
+    assert_eq!(of(Change::Fix), [("alice/pkg", "check", "js")]);
+    assert_eq!(
+        render_diff(&changes),
+        "regression bleeding https://github.com/moonbitlang/core@main test js: Success -> Failure\n\
+         fix bleeding alice/pkg@0.1.0 check js: Failure -> Success\n"
+    );
+    assert!(diff(&before, &before)
+        .iter()
+        .all(|c| c.change() == Change::Unchanged));
+}
@@ -0,0 +1,88 @@
+use std::{fs::File, io::Write, path::Path, sync::Mutex};
+
+use serde::Serialize;
+
+/// A progress event, one JSON object per line of `--events-file`. `source` is
+/// the index of the source, `elapsed` is in milliseconds.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    SourceStarted {
+        source: usize,
+        name: &'a str,
+    },
+    CommandFinished {
+        source: usize,
+        command: &'a str,
+        target: &'a str,
+        success: bool,
+        elapsed: u64,
+    },
+    SourceFinished {
+        source: usize,
+        elapsed: u64,
+    },
+}
+
+/// Writes events to `--events-file`, does nothing without it.
+#[derive(Debug, Default)]
+pub struct Events {
+    file: Option<Mutex<File>>,
+}
+
+impl Events {
+    /// Truncates `path` if given.
+    pub fn open(path: Option<&Path>) -> std::io::Result<Self> {
+        let file = path.map(File::create).transpose()?;
+        Ok(Events {
+            file: file.map(Mutex::new),
+        })
+    }
+
+    pub fn emit(&self, event: Event) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let Ok(mut line) = serde_json::to_string(&event) else {
+            return;
+        };
+        line.push('\n');
+        // a single write per line, so a tailing reader never sees half an event
+        if let Err(e) = file.lock().unwrap().write_all(line.as_bytes()) {
+            eprintln!("failed to write event: {}", e);
+        }
+    }
+}
+
+#[test]
+fn events_are_json_lines() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("events.ndjson");
+    let events = Events::open(Some(&path)).unwrap();
+    events.emit(Event::SourceStarted {
+        source: 0,
+        name: "a/b",
+    });
+    events.emit(Event::CommandFinished {
+        source: 0,
+        command: "build",
+        target: "js",
+        success: false,
+        elapsed: 12,
+    });
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        concat!(
+            r#"{"event":"source_started","source":0,"name":"a/b"}"#,
+            "\n",
+            r#"{"event":"command_finished","source":0,"command":"build","target":"js","success":false,"elapsed":12}"#,
+            "\n"
+        )
+    );
+
+    // no file, nothing to do
+    Events::default().emit(Event::SourceFinished {
+        source: 0,
+        elapsed: 0,
+    });
+}
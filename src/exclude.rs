@@ -0,0 +1,132 @@
+use crate::dashboard::MooncakeSource;
+
+/// The OS names of `util::current_os`.
+pub const OS_NAMES: &[&str] = &["linux", "mac", "windows"];
+
+/// Sources left out of the matrix, read from `--exclude FILE`. Each line is a
+/// git url or mooncake name, excluded everywhere, or followed by `os=` and the
+/// OSes it is excluded on:
+///
+/// ```text
+/// # fails to link on Windows
+/// alice/pkg os=windows
+/// https://github.com/bob/repo
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExcludeConfig {
+    pub entries: Vec<ExcludeEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcludeEntry {
+    pub name: String,
+    /// empty for every OS
+    pub os: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExcludeConfigError {
+    #[error("line {line}: unknown OS `{os}`, expected one of linux, mac, windows")]
+    UnknownOs { line: usize, os: String },
+    #[error("line {line}: unexpected `{word}`, expected `os=<os>,...`")]
+    Unexpected { line: usize, word: String },
+}
+
+impl ExcludeConfig {
+    pub fn parse(content: &str) -> Result<Self, ExcludeConfigError> {
+        let mut entries = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or_default().to_string();
+            let mut os = vec![];
+            for word in words {
+                let Some(list) = word.strip_prefix("os=") else {
+                    return Err(ExcludeConfigError::Unexpected {
+                        line: line_no,
+                        word: word.to_string(),
+                    });
+                };
+                for o in list.split(',').filter(|o| !o.is_empty()) {
+                    if !OS_NAMES.contains(&o) {
+                        return Err(ExcludeConfigError::UnknownOs {
+                            line: line_no,
+                            os: o.to_string(),
+                        });
+                    }
+                    os.push(o.to_string());
+                }
+            }
+            entries.push(ExcludeEntry { name, os });
+        }
+        Ok(ExcludeConfig { entries })
+    }
+
+    /// Why `source` is excluded on `os`, `None` when it is built.
+    pub fn reason(&self, source: &MooncakeSource, os: &str) -> Option<String> {
+        let entry = self.entries.iter().find(|e| {
+            e.name.trim_end_matches('/') == source.name().trim_end_matches('/')
+                && (e.os.is_empty() || e.os.iter().any(|o| o == os))
+        })?;
+        Some(match entry.os.is_empty() {
+            true => "excluded by the config".to_string(),
+            false => format!("excluded on {} by the config", os),
+        })
+    }
+}
+
+#[test]
+fn os_scoped_exclude() {
+    let config = ExcludeConfig::parse(
+        "# comment\n\
+         alice/pkg os=windows\n\
+         https://github.com/bob/repo\n\
+         carol/lib os=mac,windows\n",
+    )
+    .unwrap();
+    assert_eq!(
+        config.entries[0],
+        ExcludeEntry {
+            name: "alice/pkg".to_string(),
+            os: vec!["windows".to_string()],
+        }
+    );
+
+    let mooncake = |name: &str| MooncakeSource::MooncakesIO {
+        name: name.to_string(),
+        version: vec!["0.1.0".to_string()],
+        index: 0,
+        pre_build: None,
+        private: None,
+    };
+    let git = MooncakeSource::Git {
+        url: "https://github.com/bob/repo".to_string(),
+        rev: vec!["main".to_string()],
+        index: 1,
+        pre_build: None,
+    };
+    assert_eq!(
+        config.reason(&mooncake("alice/pkg"), "windows").as_deref(),
+        Some("excluded on windows by the config")
+    );
+    assert_eq!(config.reason(&mooncake("alice/pkg"), "linux"), None);
+    assert_eq!(config.reason(&mooncake("alice/pkg"), "mac"), None);
+    assert!(config.reason(&mooncake("carol/lib"), "mac").is_some());
+    assert_eq!(config.reason(&mooncake("dave/x"), "windows"), None);
+    for os in OS_NAMES {
+        assert!(config.reason(&git, os).is_some());
+    }
+
+    assert!(matches!(
+        ExcludeConfig::parse("alice/pkg os=freebsd"),
+        Err(ExcludeConfigError::UnknownOs { line: 1, .. })
+    ));
+    assert!(matches!(
+        ExcludeConfig::parse("\nalice/pkg windows"),
+        Err(ExcludeConfigError::Unexpected { line: 2, .. })
+    ));
+}
@@ -1,4 +1,6 @@
-use std::path::Path;
+use std::{collections::BTreeSet, path::Path};
+
+use crate::util::command_with_proxy;
 
 #[derive(Debug, thiserror::Error)]
 pub enum GitOpsError {
@@ -19,6 +21,9 @@ pub enum GitOpsError {
 
     #[error("failed to checkout")]
     CheckoutError,
+
+    #[error("no default branch of origin")]
+    DefaultBranchNotFound,
 }
 
 pub fn get_branch_name(workdir: &Path) -> Result<String, GitOpsError> {
@@ -26,9 +31,9 @@ pub fn get_branch_name(workdir: &Path) -> Result<String, GitOpsError> {
         .current_dir(workdir)
         .args(["rev-parse", "--abbrev-ref", "HEAD"])
         .output()
-        .map_err(|e| GitOpsError::IOError(e.into()))?;
+        .map_err(GitOpsError::IOError)?;
     let branch_name = String::from_utf8(output.stdout)
-        .map_err(|e| GitOpsError::Utf8Error(e.into()))?
+        .map_err(GitOpsError::Utf8Error)?
         .trim()
         .to_string();
     Ok(branch_name)
@@ -39,36 +44,184 @@ pub fn get_git_short_hash(workdir: &Path) -> Result<String, GitOpsError> {
         .current_dir(workdir)
         .args(["rev-parse", "--short", "HEAD"])
         .output()
-        .map_err(|e| GitOpsError::IOError(e.into()))?;
+        .map_err(GitOpsError::IOError)?;
     let hash = String::from_utf8(output.stdout)
-        .map_err(|e| GitOpsError::Utf8Error(e.into()))?
+        .map_err(GitOpsError::Utf8Error)?
         .trim()
         .to_string();
     Ok(hash)
 }
 
+/// The rev of a git source that stands for the default branch of its remote,
+/// also used for a source listed without revs.
+pub const DEFAULT_REV: &str = "default";
+
+/// The default branch of the remote the clone at `workdir` was made from, read
+/// from `origin/HEAD` without going over the network.
+pub fn get_default_branch(workdir: &Path) -> Result<String, GitOpsError> {
+    let output = std::process::Command::new("git")
+        .current_dir(workdir)
+        .args(["symbolic-ref", "--short", "refs/remotes/origin/HEAD"])
+        .output()
+        .map_err(GitOpsError::IOError)?;
+    if !output.status.success() {
+        return Err(GitOpsError::ReturnNonZero(output.status));
+    }
+    let stdout = String::from_utf8(output.stdout).map_err(GitOpsError::Utf8Error)?;
+    stdout
+        .trim()
+        .strip_prefix("origin/")
+        .map(|branch| branch.to_string())
+        .ok_or(GitOpsError::DefaultBranchNotFound)
+}
+
+/// Content of `file` at `rev` of the repo containing it, via `git show`.
+pub fn show_file_at(rev: &str, file: &Path) -> Result<String, GitOpsError> {
+    let dir = file.parent().filter(|d| !d.as_os_str().is_empty());
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let output = std::process::Command::new("git")
+        .current_dir(dir.unwrap_or(Path::new(".")))
+        .arg("show")
+        .arg(format!("{}:./{}", rev, name))
+        .output()
+        .map_err(GitOpsError::IOError)?;
+    if !output.status.success() {
+        return Err(GitOpsError::ReturnNonZero(output.status));
+    }
+    String::from_utf8(output.stdout).map_err(GitOpsError::Utf8Error)
+}
+
+/// A config line with whitespace normalized, so reformatting isn't a change.
+pub fn config_line_key(line: &str) -> String {
+    line.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Keys of the entries of `head` that are added or modified compared to `base`.
+pub fn changed_config_lines(base: &str, head: &str) -> BTreeSet<String> {
+    let keys = |content: &str| -> BTreeSet<String> {
+        content
+            .lines()
+            .map(config_line_key)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .collect()
+    };
+    let base = keys(base);
+    keys(head).difference(&base).cloned().collect()
+}
+
+/// Whether `repo` answers `git ls-remote`, without cloning it. Credential
+/// prompts are disabled, so a private or mistyped url fails instead of hanging.
+pub fn check_reachable(repo: &str) -> Result<(), GitOpsError> {
+    let output = command_with_proxy("git")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .args(["ls-remote", "--quiet", repo, "HEAD"])
+        .output()
+        .map_err(GitOpsError::IOError)?;
+    if !output.status.success() {
+        return Err(GitOpsError::ReturnNonZero(output.status));
+    }
+    Ok(())
+}
+
 pub fn git_clone_to(repo: &str, workdir: &Path, dst: &str) -> Result<(), GitOpsError> {
-    let mut cmd = std::process::Command::new("git")
+    let mut cmd = command_with_proxy("git")
         .current_dir(workdir)
         .args(["clone", repo, dst])
         .spawn()
-        .map_err(|e| GitOpsError::IOError(e.into()))?;
-    let result = cmd.wait().map_err(|e| GitOpsError::IOError(e.into()))?;
+        .map_err(GitOpsError::IOError)?;
+    let result = cmd.wait().map_err(GitOpsError::IOError)?;
     if !result.success() {
         return Err(GitOpsError::ReturnNonZero(result));
     }
     Ok(())
 }
 
+/// The full hash of `HEAD` in `workdir`.
+pub fn get_git_hash(workdir: &Path) -> Result<String, GitOpsError> {
+    let output = std::process::Command::new("git")
+        .current_dir(workdir)
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .map_err(GitOpsError::IOError)?;
+    if !output.status.success() {
+        return Err(GitOpsError::ReturnNonZero(output.status));
+    }
+    Ok(String::from_utf8(output.stdout)
+        .map_err(GitOpsError::Utf8Error)?
+        .trim()
+        .to_string())
+}
+
+/// Whether `rev` names a commit in the clone at `workdir`, locally or as a
+/// branch of `origin`.
+pub fn rev_exists(workdir: &Path, rev: &str) -> bool {
+    [
+        format!("{}^{{commit}}", rev),
+        format!("origin/{}^{{commit}}", rev),
+    ]
+    .iter()
+    .any(|spec| {
+        std::process::Command::new("git")
+            .current_dir(workdir)
+            .args(["rev-parse", "--verify", "--quiet", spec])
+            .output()
+            .is_ok_and(|output| output.status.success())
+    })
+}
+
 pub fn git_checkout(workdir: &Path, rev: &str) -> Result<(), GitOpsError> {
     let mut cmd = std::process::Command::new("git")
         .current_dir(workdir)
         .args(["checkout", rev])
         .spawn()
-        .map_err(|e| GitOpsError::IOError(e.into()))?;
-    let result = cmd.wait().map_err(|e| GitOpsError::IOError(e.into()))?;
+        .map_err(GitOpsError::IOError)?;
+    let result = cmd.wait().map_err(GitOpsError::IOError)?;
     if !result.success() {
         return Err(GitOpsError::ReturnNonZero(result));
     }
     Ok(())
 }
+
+#[test]
+fn default_branch_of_clone() {
+    let tmp = tempfile::tempdir().unwrap();
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .current_dir(tmp.path())
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?}", args);
+    };
+    git(&["init", "-q", "work"]);
+    git(&["-C", "work", "checkout", "-q", "-b", "trunk"]);
+    git(&["-C", "work", "commit", "-q", "--allow-empty", "-m", "init"]);
+    git(&["-C", "work", "branch", "-q", "other"]);
+    git(&["clone", "-q", "--bare", "work", "bare.git"]);
+    git(&["clone", "-q", "bare.git", "clone"]);
+    // checking out another rev does not change the default branch
+    git(&["-C", "clone", "checkout", "-q", "other"]);
+
+    let clone = tmp.path().join("clone");
+    assert_eq!(get_default_branch(&clone).unwrap(), "trunk");
+
+    // no `origin`, e.g. a clone whose remote HEAD was unknown
+    assert!(matches!(
+        get_default_branch(&tmp.path().join("work")),
+        Err(GitOpsError::ReturnNonZero(_))
+    ));
+}
+
+#[test]
+fn changed_config_entries() {
+    let base = "# sources\nhttps://github.com/a/b\nalice/x 0.1.0\nbob/y\n";
+    let head = "# sources, edited\nhttps://github.com/a/b\nalice/x  0.1.0 0.2.0\nbob/y\ncarol/z\n";
+    let changed = changed_config_lines(base, head);
+    assert_eq!(
+        changed.into_iter().collect::<Vec<_>>(),
+        vec!["alice/x 0.1.0 0.2.0", "carol/z"]
+    );
+    assert!(changed_config_lines(head, "https://github.com/a/b\n  bob/y ").is_empty());
+}
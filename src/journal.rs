@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::dashboard::{BuildState, MooncakeSource, ToolChainLabel, ToolChainVersion};
+
+/// A line of `--journal`: the toolchain of a phase, written when the phase
+/// starts, then one line per source as soon as it is built.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+pub enum Entry<S> {
+    Phase {
+        toolchain: ToolChainVersion,
+    },
+    Source {
+        label: ToolChainLabel,
+        name: String,
+        state: S,
+    },
+}
+
+/// Appends the results of `stat` to the journal as they complete, does nothing
+/// with `--no-journal`. Each line is a gzip member of its own, so the file stays
+/// readable up to the last complete line after a crash.
+#[derive(Debug, Default)]
+pub struct Journal {
+    file: Option<File>,
+}
+
+impl Journal {
+    /// Truncates `path` if given, or appends to it when `resume` is set.
+    pub fn open(path: Option<&Path>, resume: bool) -> std::io::Result<Self> {
+        let file = path
+            .map(|path| {
+                File::options()
+                    .create(true)
+                    .write(true)
+                    .append(resume)
+                    .truncate(!resume)
+                    .open(path)
+            })
+            .transpose()?;
+        Ok(Journal { file })
+    }
+
+    pub fn phase(&mut self, toolchain: &ToolChainVersion) {
+        self.write(&Entry::<&BuildState>::Phase {
+            toolchain: toolchain.clone(),
+        });
+    }
+
+    pub fn source(&mut self, label: ToolChainLabel, name: &str, state: &BuildState) {
+        self.write(&Entry::Source {
+            label,
+            name: name.to_string(),
+            state,
+        });
+    }
+
+    fn write(&mut self, entry: &Entry<&BuildState>) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let result = serde_json::to_string(entry)
+            .map_err(std::io::Error::from)
+            .and_then(|line| {
+                let mut gz = GzEncoder::new(vec![], Compression::default());
+                writeln!(gz, "{}", line)?;
+                // a single write per line, a crash leaves at most one partial member
+                file.write_all(&gz.finish()?)?;
+                file.flush()
+            });
+        if let Err(e) = result {
+            eprintln!("failed to write journal: {}", e);
+        }
+    }
+}
+
+/// The sources already built in a journal, by phase.
+#[derive(Debug, Default)]
+pub struct Resumed {
+    phases: BTreeMap<&'static str, (ToolChainVersion, BTreeMap<String, BuildState>)>,
+}
+
+impl Resumed {
+    /// Reads `path` up to its last complete line. A phase restarted with
+    /// another toolchain drops what was built with the previous one.
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let reader = std::io::BufReader::new(MultiGzDecoder::new(File::open(path)?));
+        let mut resumed = Resumed::default();
+        for line in reader.lines() {
+            // the end of a file cut off by a crash
+            let Ok(line) = line else {
+                break;
+            };
+            let Ok(entry) = serde_json::from_str::<Entry<BuildState>>(&line) else {
+                break;
+            };
+            match entry {
+                Entry::Phase { toolchain } => {
+                    let phase = resumed
+                        .phases
+                        .entry(toolchain.label.key())
+                        .or_insert_with(|| (toolchain.clone(), BTreeMap::new()));
+                    if !phase.0.same_versions(&toolchain) {
+                        *phase = (toolchain, BTreeMap::new());
+                    }
+                }
+                Entry::Source { label, name, state } => {
+                    if let Some((_, states)) = resumed.phases.get_mut(label.key()) {
+                        states.insert(name, state);
+                    }
+                }
+            }
+        }
+        Ok(resumed)
+    }
+
+    /// Takes the states of `sources` built with `toolchain`, keyed and
+    /// reindexed by the index of the source in this run.
+    pub fn take_phase(
+        &mut self,
+        toolchain: &ToolChainVersion,
+        sources: &[MooncakeSource],
+    ) -> BTreeMap<usize, BuildState> {
+        let Some((recorded, mut states)) = self.phases.remove(toolchain.label.key()) else {
+            return BTreeMap::new();
+        };
+        if !recorded.same_versions(toolchain) {
+            eprintln!(
+                "not resuming {}, the journal was written with {}",
+                toolchain.label.key(),
+                recorded.moonc_version
+            );
+            return BTreeMap::new();
+        }
+        sources
+            .iter()
+            .filter_map(|source| {
+                let mut state = states.remove(source.name())?;
+                state.source = source.get_index();
+                Some((source.get_index(), state))
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn resume_from_truncated_journal() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("journal.jsonl.gz");
+    let toolchain = |label, moonc: &str| ToolChainVersion {
+        label,
+        moon_version: "moon 0.1.0".to_string(),
+        moonc_version: moonc.to_string(),
+        core_version: None,
+    };
+    let source = |name: &str, index| MooncakeSource::MooncakesIO {
+        name: name.to_string(),
+        version: vec!["0.1.0".to_string()],
+        index,
+        pre_build: None,
+        private: None,
+    };
+    let stable = toolchain(ToolChainLabel::Stable, "v0.1.0");
+    let bleeding = toolchain(ToolChainLabel::Bleeding, "v0.2.0");
+
+    let mut journal = Journal::open(Some(&path), false).unwrap();
+    journal.phase(&stable);
+    journal.source(ToolChainLabel::Stable, "alice/a", &BuildState::new(0));
+    journal.source(ToolChainLabel::Stable, "alice/b", &BuildState::new(1));
+    journal.phase(&bleeding);
+    journal.source(ToolChainLabel::Bleeding, "alice/a", &BuildState::new(0));
+    drop(journal);
+    // a crash in the middle of the next line
+    let len = std::fs::metadata(&path).unwrap().len();
+    let mut journal = Journal::open(Some(&path), true).unwrap();
+    journal.source(ToolChainLabel::Bleeding, "alice/b", &BuildState::new(1));
+    drop(journal);
+    let file = File::options().write(true).open(&path).unwrap();
+    file.set_len(len + 20).unwrap();
+
+    // the sources were reordered since
+    let sources = vec![
+        source("alice/b", 0),
+        source("alice/c", 1),
+        source("alice/a", 2),
+    ];
+    let mut resumed = Resumed::read(&path).unwrap();
+    let done = resumed.take_phase(&stable, &sources);
+    assert_eq!(done.keys().collect::<Vec<_>>(), [&0, &2]);
+    assert_eq!(done[&0].source, 0);
+    assert_eq!(done[&2].source, 2);
+    // bleeding moved since, nothing to resume
+    let moved = toolchain(ToolChainLabel::Bleeding, "v0.3.0");
+    assert!(resumed.take_phase(&moved, &sources).is_empty());
+
+    let mut resumed = Resumed::read(&path).unwrap();
+    let done = resumed.take_phase(&bleeding, &sources);
+    assert_eq!(done.keys().collect::<Vec<_>>(), [&2]);
+
+    assert!(Journal::open(None, false).is_ok());
+}
@@ -1,8 +1,27 @@
+pub mod bisect;
 pub mod cli;
 pub mod dashboard;
+pub mod depgraph;
+pub mod diff;
+pub mod events;
+pub mod exclude;
 pub mod git;
+pub mod journal;
+pub mod merge;
 pub mod mooncakesio;
+pub mod output;
+pub mod parse;
+pub mod pipeline;
+pub mod preflight;
+pub mod report;
+pub mod repro;
+#[cfg(unix)]
+pub mod socket;
+pub mod stats;
+pub mod tee;
 pub mod util;
+pub mod validate;
+pub mod watch;
 
 #[cfg(test)]
 pub mod auto_update;
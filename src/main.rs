@@ -1,27 +1,46 @@
 use std::{
-    io::Write,
-    path::Path,
+    collections::{BTreeMap, BTreeSet},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
     time::{Duration, Instant},
 };
 
-use chrono::{FixedOffset, Local};
+use anyhow::Context;
+use chrono::Local;
 
 use clap::Parser;
 use colored::Colorize;
-use flate2::{write::GzEncoder, Compression};
+use moon_dashboard::git;
 use moon_dashboard::{
+    bisect::Bisection,
     cli,
     dashboard::{
-        Backend, BackendState, BuildState, ExecuteResult, MoonBuildDashboard, MoonCommand,
-        MooncakeSource, Status, ToolChainLabel, ToolChainVersion, CBT,
+        matrix_cost, plan_work_items, Backend, BackendState, BuildState, ExecuteResult,
+        MatrixBackend, MoonBuildDashboard, MoonCommand, MooncakeSource, RevStatus, SkipKind,
+        Status, Thresholds, ToolChainLabel, ToolChainVersion, CBT, SCHEMA_VERSION,
     },
-    mooncakesio,
+    depgraph::DepGraph,
+    events::{Event, Events},
+    exclude::{ExcludeConfig, ExcludeConfigError},
+    journal::{Journal, Resumed},
+    mooncakesio, output,
+    parse::{
+        detect_compiler_mismatch, matches_any, parse_failed_tests, parse_json_diagnostics,
+        parse_test_summary, parse_updated_packages,
+    },
+    pipeline::{self, Schedule},
+    report,
+    repro::ReproInfo,
+    tee,
     util::{
-        get_moon_version, get_moonc_version, install_bleeding_release, install_stable_release,
-        MoonOpsError,
+        copy_dir, current_os, exit_code_of, get_core_version, get_moon_version_with,
+        get_moonc_version_with, list_artifacts, pin_phases, plan_phases, resolved_deps,
+        run_pre_build, set_allow_stale_registry, set_mock_moon, set_proxy, set_tmp_prefix,
+        start_time_now, sweep_stale, tempdir_under, toolchain_command_with, warm_core,
+        warm_core_once, CpuTime, MoonOpsError, Phase,
     },
+    validate,
 };
-use moon_dashboard::{git, util::moon_update};
 
 #[derive(Debug, thiserror::Error)]
 pub enum RunMoonError {
@@ -41,39 +60,93 @@ struct CommandOutput {
     stdout: String,
     stderr: String,
     success: bool,
+    /// the negated signal number when killed by a signal on Unix
+    exit_code: Option<i32>,
+    cpu: Option<CpuTime>,
+    /// killed after its timeout
+    timed_out: bool,
 }
 
+/// Runs `moon`, or the `--mock-moon` script of the run when `mock` is set.
 fn run_moon(
+    mock: Option<&Path>,
     workdir: &Path,
     source: &MooncakeSource,
     args: &[&str],
+    log: Option<&Path>,
+    timeout: Option<Duration>,
 ) -> Result<CommandOutput, RunMoonError> {
+    run_command(
+        toolchain_command_with(mock, "moon"),
+        workdir,
+        source,
+        args,
+        log,
+        timeout,
+    )
+}
+
+/// Runs `command` with `args`, with `log` its output is also shown live and
+/// appended to `<log>.stdout.log`/`<log>.stderr.log` as it is produced. After
+/// `timeout`, the command is killed along with the processes it spawned.
+fn run_command(
+    mut command: std::process::Command,
+    workdir: &Path,
+    source: &MooncakeSource,
+    args: &[&str],
+    log: Option<&Path>,
+    timeout: Option<Duration>,
+) -> Result<CommandOutput, RunMoonError> {
+    let program = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|s| s.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
     let start = Instant::now();
     eprintln!(
         "{}",
-        format!("RUN moon {} for {:?}", args.join(" "), source)
+        format!("RUN {} {} for {:?}", program, args.join(" "), source)
             .blue()
             .bold()
     );
 
-    let output = std::process::Command::new("moon")
-        .current_dir(workdir)
-        .args(args)
-        .output()
-        .map_err(|e| RunMoonError::IOError(e))?;
+    command.current_dir(workdir).args(args);
+    let cpu_before = CpuTime::children();
+    let (output, timed_out) = match (log, timeout) {
+        (None, None) => (command.output().map_err(RunMoonError::IOError)?, false),
+        _ => output_teed(
+            command,
+            &format!("{} {}", program, args.join(" ")),
+            log,
+            timeout,
+        )?,
+    };
 
     let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
-    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    let mut stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if let Some(timeout) = timeout.filter(|_| timed_out) {
+        stderr.push_str(&format!(
+            "\nkilled after a timeout of {}s\n",
+            timeout.as_secs()
+        ));
+    }
 
     let elapsed = start.elapsed();
 
     eprintln!(
         "{}",
         format!(
-            "moon {}, elapsed: {}ms, {}",
+            "{} {}, elapsed: {}ms, {}",
+            program,
             args.join(" ").blue().bold(),
             elapsed.as_millis(),
-            if output.status.success() { "success" } else { "failed" }
+            if timed_out {
+                "timed out"
+            } else if output.status.success() {
+                "success"
+            } else {
+                "failed"
+            }
         )
         .green()
         .bold()
@@ -84,9 +157,489 @@ fn run_moon(
         stdout,
         stderr,
         success: output.status.success(),
+        exit_code: exit_code_of(&output.status),
+        cpu: cpu_before
+            .zip(CpuTime::children())
+            .map(|(before, after)| after.since(before)),
+        timed_out,
     })
 }
 
+/// Captures the output of `command`, teeing it to the logs at `log` if given,
+/// and returns whether it was killed after `timeout`.
+fn output_teed(
+    mut command: std::process::Command,
+    header: &str,
+    log: Option<&Path>,
+    timeout: Option<Duration>,
+) -> Result<(std::process::Output, bool), RunMoonError> {
+    // its own process group, so a timeout also kills moonc and node
+    #[cfg(unix)]
+    if timeout.is_some() {
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    }
+    let mut child = command
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().expect("piped stdout");
+    let stderr = child.stderr.take().expect("piped stderr");
+    let logs = match log {
+        Some(log) => Some((
+            tee::open_log(log, "stdout", header)?,
+            tee::open_log(log, "stderr", header)?,
+        )),
+        None => None,
+    };
+    let (stdout_log, stderr_log) = logs.unzip();
+    let stdout = std::thread::spawn(move || tee_live(stdout, stdout_log));
+    let stderr = std::thread::spawn(move || tee_live(stderr, stderr_log));
+    let (status, timed_out) = wait_with_timeout(&mut child, timeout)?;
+    Ok((
+        std::process::Output {
+            status,
+            stdout: stdout.join().expect("stdout tee panicked")?,
+            stderr: stderr.join().expect("stderr tee panicked")?,
+        },
+        timed_out,
+    ))
+}
+
+/// Captures `reader`, shown live and appended to `log` when there is one.
+fn tee_live(reader: impl std::io::Read, log: Option<std::fs::File>) -> std::io::Result<Vec<u8>> {
+    match log {
+        Some(mut log) => tee::tee(reader, &mut [&mut log, &mut std::io::stderr()]),
+        None => tee::tee(reader, &mut []),
+    }
+}
+
+/// Waits for `child`, killing it and its process group once `timeout` elapsed.
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: Option<Duration>,
+) -> std::io::Result<(std::process::ExitStatus, bool)> {
+    let Some(timeout) = timeout else {
+        return Ok((child.wait()?, false));
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if Instant::now() >= deadline {
+            #[cfg(unix)]
+            // SAFETY: signals the process group created for the child in `output_teed`
+            unsafe {
+                libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+            }
+            let _ = child.kill();
+            return Ok((child.wait()?, true));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// `stat` with `args`, as parsed from the command line.
+#[cfg(test)]
+fn parse_stat(args: &[&str]) -> cli::StatSubcommand {
+    let args = ["moon_dashboard", "stat"].iter().chain(args);
+    match cli::MoonBuildDashBoardCli::parse_from(args).subcommand {
+        cli::MoonBuildDashBoardSubcommands::Stat(opts) => opts,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+fn git_source(url: &str, revs: &[&str], index: usize) -> MooncakeSource {
+    MooncakeSource::Git {
+        url: url.to_string(),
+        rev: revs.iter().map(|rev| rev.to_string()).collect(),
+        index,
+        pre_build: None,
+    }
+}
+
+/// Runs the shell `script` in `dir`.
+#[cfg(all(unix, test))]
+fn run_sh(
+    dir: &Path,
+    script: &str,
+    log: Option<&Path>,
+    timeout: Option<Duration>,
+) -> CommandOutput {
+    let cmd = std::process::Command::new("sh");
+    run_command(
+        cmd,
+        dir,
+        &git_source("", &[], 0),
+        &["-c", script],
+        log,
+        timeout,
+    )
+    .unwrap()
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_code_of_failed_command() {
+    let tmp = tempfile::tempdir().unwrap();
+    let output = run_sh(tmp.path(), "exit 3", None, None);
+    assert!(!output.success);
+    assert_eq!(output.exit_code, Some(3));
+    let output = run_sh(tmp.path(), "kill -9 $$", None, None);
+    assert_eq!(output.exit_code, Some(-9));
+}
+
+#[cfg(unix)]
+#[test]
+fn command_output_is_logged() {
+    let tmp = tempfile::tempdir().unwrap();
+    let log = tmp.path().join("0_check_js");
+    let output = run_sh(
+        tmp.path(),
+        "echo out; echo err >&2; exit 1",
+        Some(&log),
+        None,
+    );
+    assert_eq!(
+        (output.stdout.as_str(), output.exit_code),
+        ("out\n", Some(1))
+    );
+    let stderr_log = std::fs::read_to_string(tmp.path().join("0_check_js.stderr.log")).unwrap();
+    assert!(stderr_log.ends_with("\nerr\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn timed_out_command_is_killed() {
+    let tmp = tempfile::tempdir().unwrap();
+    // the background sleep holds the pipes open unless the whole group is killed
+    let output = run_sh(
+        tmp.path(),
+        "echo started; sleep 30 & sleep 30",
+        None,
+        Some(Duration::from_millis(200)),
+    );
+    assert!(output.timed_out);
+    assert!(!output.success);
+    assert!(output.duration < Duration::from_secs(10));
+    assert_eq!(output.stdout, "started\n");
+    assert!(output.stderr.contains("killed after a timeout"));
+    let output = run_sh(tmp.path(), "exit 0", None, Some(Duration::from_secs(30)));
+    assert!(output.success && !output.timed_out);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn cpu_time_of_command() {
+    let tmp = tempfile::tempdir().unwrap();
+    let busy = "i=0; while [ $i -lt 300000 ]; do i=$((i+1)); done";
+    let output = run_sh(tmp.path(), busy, None, None);
+    let cpu = output.cpu.expect("getrusage on linux");
+    assert!(cpu.user_ms + cpu.sys_ms > 0);
+}
+
+#[test]
+fn missing_rev_is_checkout_failed() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = tmp.path().join("repo");
+    let status = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .arg(&repo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let source = git_source(repo.to_str().unwrap(), &["v9.9.9"], 0);
+    let state = build(&source, &parse_stat(&[]), &Events::default());
+    assert!(state.fetch_error.is_none());
+    // the local clone took some time, without the network
+    assert!(state.fetch_ms > 0);
+    assert!(!state.network_used);
+    assert_eq!(
+        state.revs,
+        [RevStatus::CheckoutFailed {
+            rev: "v9.9.9".to_string(),
+            reason: "rev not found".to_string(),
+        }]
+    );
+    assert!(state.cbts[0].is_none());
+}
+
+#[test]
+fn clone_failure_sets_fetch_error() {
+    let tmp = tempfile::tempdir().unwrap();
+    let missing = tmp.path().join("missing");
+    let source = git_source(missing.to_str().unwrap(), &["a", "b"], 0);
+    let state = build(&source, &parse_stat(&[]), &Events::default());
+    assert!(state.fetch_error.is_some());
+    assert_eq!(state.cbts.len(), 2);
+    assert!(state.cbts.iter().all(|cbt| cbt.is_none()));
+    assert_eq!(state.skipped[&1].kind, SkipKind::Error);
+}
+
+/// A mock toolchain and a local git repo: the `good` branch passes, `bad`
+/// fails `moon build --target js`. A `hang` file makes `moon check --target js`
+/// hang. The script logs its calls to `calls.log` next to it.
+#[cfg(all(unix, test))]
+struct MockMoon {
+    tmp: tempfile::TempDir,
+    script: PathBuf,
+    repo: PathBuf,
+}
+
+#[cfg(all(unix, test))]
+impl MockMoon {
+    fn new() -> Self {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let script = tmp.path().join("mock-moon.sh");
+        std::fs::write(
+            &script,
+            r#"#!/bin/sh
+echo "$@" >> "$(dirname "$0")/calls.log"
+case "$*" in
+  "moon version") echo "moon 0.0.0-mock" ;;
+  "moonc -v") echo "v0.0.0-mock" ;;
+  "moon build -q --target js")
+    if [ -f bad ]; then echo "error: mock failure" >&2; exit 2; fi ;;
+  "moon check -q --target js")
+    if [ -f hang ]; then sleep 60; fi ;;
+  "moon test"*) echo "Total tests: 2, passed: 2, failed: 0." ;;
+esac
+exit 0
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let repo = tmp.path().join("repo");
+        std::fs::create_dir(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .current_dir(&repo)
+                .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q", "-b", "good"]);
+        std::fs::write(repo.join("moon.mod.json"), "{}").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "good"]);
+        git(&["checkout", "-q", "-b", "bad"]);
+        std::fs::write(repo.join("bad"), "").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "bad"]);
+        MockMoon { tmp, script, repo }
+    }
+
+    /// `stat` with `args`, running the mock. The script is passed along rather
+    /// than set for the whole process, which other tests running in parallel share.
+    fn opts(&self, args: &[&str]) -> cli::StatSubcommand {
+        let mock = ["--mock-moon", self.script.to_str().unwrap()];
+        parse_stat(&[&mock[..], args].concat())
+    }
+
+    fn source(&self, revs: &[&str], index: usize) -> MooncakeSource {
+        git_source(self.repo.to_str().unwrap(), revs, index)
+    }
+
+    fn calls(&self) -> String {
+        std::fs::read_to_string(self.tmp.path().join("calls.log")).unwrap()
+    }
+}
+
+/// The commands of `cbt` that failed, as `<command> <backend>`.
+#[cfg(test)]
+fn failures(cbt: &CBT) -> Vec<String> {
+    cbt.results()
+        .filter(|(_, _, r)| matches!(r.status, Status::Failure))
+        .map(|(cmd, backend, _)| format!("{} {}", cmd, backend))
+        .collect()
+}
+
+#[cfg(unix)]
+#[test]
+fn mock_toolchain_is_prepared() {
+    let mock = MockMoon::new();
+    for phase in plan_phases(false, false, false, mock.opts(&[]).phase_order) {
+        phase.prepare_mocked(Some(&mock.script)).unwrap();
+    }
+    assert_eq!(
+        get_moon_version_with(Some(&mock.script)).unwrap(),
+        "moon 0.0.0-mock"
+    );
+    assert_eq!(
+        get_moonc_version_with(Some(&mock.script)).unwrap(),
+        "v0.0.0-mock"
+    );
+    assert!(mock
+        .calls()
+        .starts_with("install stable\nmoon update\ninstall bleeding\nmoon update\n"));
+}
+
+/// `stat` against the mock: `good` passes, `bad` fails to build on js,
+/// `missing` is skipped.
+#[cfg(unix)]
+#[test]
+fn mock_moon_end_to_end() {
+    let mock = MockMoon::new();
+    let state = build(
+        &mock.source(&["good", "bad", "missing"], 0),
+        &mock.opts(&[]),
+        &Events::default(),
+    );
+    assert_eq!(state.cbts.len(), 3);
+    let good = state.cbts[0].as_ref().unwrap();
+    assert!(failures(good).is_empty());
+    assert_eq!(good.test.js.tests_passed, Some(2));
+    assert_eq!(failures(state.cbts[1].as_ref().unwrap()), ["build js"]);
+    assert!(state.cbts[2].is_none());
+    assert_eq!(state.skipped[&2].kind, SkipKind::Error);
+    // a missing rev is not an infra failure
+    assert!(state.fetch_error.is_none());
+    assert!(matches!(&state.revs[0], RevStatus::CheckedOut { sha, .. } if sha.len() == 40));
+    assert!(matches!(&state.revs[2], RevStatus::CheckoutFailed { rev, .. } if rev == "missing"));
+}
+
+#[cfg(unix)]
+#[test]
+fn events_of_built_sources() {
+    let mock = MockMoon::new();
+    let opts = mock.opts(&[]);
+    let events_file = mock.tmp.path().join("events.ndjson");
+    let events = Events::open(Some(&events_file)).unwrap();
+    build(&mock.source(&["good", "bad"], 0), &opts, &events);
+    build(&mock.source(&["good"], 1), &opts, &events);
+    let events: Vec<serde_json::Value> = std::fs::read_to_string(&events_file)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let finished: Vec<_> = events
+        .iter()
+        .filter(|e| e["event"] == "source_finished")
+        .map(|e| e["source"].as_u64().unwrap())
+        .collect();
+    assert_eq!(finished, [0, 1]);
+    // the failing js build of the bad rev
+    assert!(events
+        .iter()
+        .any(|e| e["event"] == "command_finished" && e["source"] == 0 && e["success"] == false));
+}
+
+/// Backends built in parallel, in copies of the checkout, agree with the serial run.
+#[cfg(unix)]
+#[test]
+fn parallel_backends_agree_with_serial() {
+    let mock = MockMoon::new();
+    let source = mock.source(&["good", "bad", "missing"], 0);
+    let serial = build(&source, &mock.opts(&[]), &Events::default());
+    let parallel = build(
+        &source,
+        &mock.opts(&["--backend-parallel"]),
+        &Events::default(),
+    );
+    for state in [serial, parallel] {
+        assert!(failures(state.cbts[0].as_ref().unwrap()).is_empty());
+        assert_eq!(failures(state.cbts[1].as_ref().unwrap()), ["build js"]);
+        assert!(state.cbts[2].is_none());
+    }
+}
+
+/// An explicit job count is passed to build and test, and recorded.
+#[cfg(unix)]
+#[test]
+fn moon_jobs_are_passed_and_recorded() {
+    let mock = MockMoon::new();
+    let opts = mock.opts(&["--backend-parallel", "--moon-jobs", "2"]);
+    let state = build(&mock.source(&["good"], 0), &opts, &Events::default());
+    let good = state.cbts[0].as_ref().unwrap();
+    assert_eq!(
+        (good.build.js.jobs, good.test.wasm.jobs),
+        (Some(2), Some(2))
+    );
+    assert_eq!(good.check.js.jobs, None);
+    let calls = mock.calls();
+    assert!(calls.contains("moon build -q --target js -j 2\n"));
+    assert!(!calls.contains("moon check -q --target js -j"));
+}
+
+/// `moon check --target js` hangs: with backends in parallel, js is killed at
+/// its deadline and the other backends still report.
+#[cfg(unix)]
+#[test]
+fn hung_backend_times_out_alone() {
+    let mock = MockMoon::new();
+    let hang = mock.tmp.path().join("hang");
+    std::fs::create_dir(&hang).unwrap();
+    std::fs::write(hang.join("moon.mod.json"), "{}").unwrap();
+    std::fs::write(hang.join("hang"), "").unwrap();
+    let opts = mock.opts(&["--jobs", "2", "--backend-timeout-secs", "1"]);
+    let started = Instant::now();
+    let cbt = run_matrix(&hang, &mock.source(&[], 0), &opts, &Events::default()).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(30));
+    for (cmd, backend, result) in cbt.results() {
+        match backend {
+            "js" => assert!(
+                result.timed_out && matches!(result.status, Status::Timeout),
+                "{} js",
+                cmd
+            ),
+            _ => assert!(
+                matches!(result.status, Status::Success),
+                "{} {}",
+                cmd,
+                backend
+            ),
+        }
+    }
+    // killed while checking, build and test never started
+    assert!(cbt.check.js.stderr.contains("killed after a timeout"));
+    assert!(cbt.build.js.stderr.starts_with("not run"));
+    assert!(cbt.test.js.stderr.starts_with("not run"));
+}
+
+#[test]
+fn retry_only_on_matching_failures() {
+    let failure = |stderr: &str| CommandOutput {
+        duration: Duration::ZERO,
+        stdout: String::new(),
+        stderr: stderr.to_string(),
+        success: false,
+        exit_code: Some(1),
+        cpu: None,
+        timed_out: false,
+    };
+    let patterns = vec!["Address already in use".to_string()];
+    let runs = |stderr: &str, retries| {
+        let mut runs = 0;
+        let output = retry_on_patterns(retries, &patterns, || {
+            runs += 1;
+            Ok(failure(stderr))
+        });
+        assert!(!output.unwrap().success);
+        runs
+    };
+    assert_eq!(runs("bind: address already in use", 2), 3);
+    assert_eq!(runs("error: type mismatch", 2), 1);
+    assert_eq!(runs("bind: address already in use", 0), 1);
+
+    // a retry that succeeds is kept
+    let mut runs = 0;
+    let output = retry_on_patterns(3, &patterns, || {
+        runs += 1;
+        Ok(CommandOutput {
+            success: runs > 1,
+            ..failure("address already in use")
+        })
+    });
+    assert!(output.unwrap().success);
+    assert_eq!(runs, 2);
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("get mooncake sources error")]
 struct GetMooncakeSourcesError {
@@ -103,20 +656,42 @@ enum GetMooncakeSourcesErrorKind {
 
     #[error("failed on mooncakesdb")]
     MooncakesDB(#[from] mooncakesio::MooncakesDBError),
+
+    #[error("failed to read the config at {0}")]
+    BaseConfig(String, #[source] git::GitOpsError),
 }
 
 fn get_mooncake_sources(
     cmd: &cli::StatSubcommand,
 ) -> Result<Vec<MooncakeSource>, GetMooncakeSourcesError> {
-    let db = mooncakesio::get_all_mooncakes().map_err(|e| GetMooncakeSourcesError {
+    let scan = mooncakesio::ScanOptions {
+        strict_index: cmd.strict_index,
+        follow_symlinks: cmd.follow_symlinks,
+    };
+    let db = match &cmd.db_snapshot {
+        Some(path) => mooncakesio::load_or_scan(
+            path,
+            std::time::Duration::from_secs(cmd.db_max_age),
+            std::time::SystemTime::now(),
+            || mooncakesio::get_all_mooncakes_with(scan),
+        ),
+        None => mooncakesio::get_all_mooncakes_with(scan),
+    }
+    .map_err(|e| GetMooncakeSourcesError {
         kind: GetMooncakeSourcesErrorKind::MooncakesIO(e),
     })?;
+    let reverse_deps = cmd.reverse_deps_of.as_ref().map(|name| {
+        #[cfg(target_os = "windows")]
+        let name = &name.replace('/', "\\");
+        DepGraph::from_deps(&db.deps).reverse_deps_of(name)
+    });
     let mut repo_list = vec![];
     if let Some(r) = &cmd.repo_url {
         repo_list.push(MooncakeSource::Git {
             url: r.clone(),
             rev: vec![],
             index: 0,
+            pre_build: None,
         });
     }
 
@@ -124,30 +699,68 @@ fn get_mooncake_sources(
         let content = std::fs::read_to_string(file).map_err(|e| GetMooncakeSourcesError {
             kind: GetMooncakeSourcesErrorKind::IOError(e),
         })?;
+        let changed = match &cmd.only_changed {
+            Some(base) => {
+                let base_content =
+                    git::show_file_at(base, file).map_err(|e| GetMooncakeSourcesError {
+                        kind: GetMooncakeSourcesErrorKind::BaseConfig(base.clone(), e),
+                    })?;
+                Some(git::changed_config_lines(&base_content, &content))
+            }
+            None => None,
+        };
         for line in content.lines() {
             let s = line.trim();
             if s.starts_with("#") || s.trim().is_empty() {
                 continue;
+            }
+            if let Some(changed) = &changed {
+                if !changed.contains(&git::config_line_key(s)) {
+                    continue;
+                }
+            }
+            // moonbitlang/core 0.1.0; ./scripts/codegen.sh
+            let (s, pre_build) = match s.split_once(';') {
+                Some((s, pre_build)) => (s.trim(), Some(pre_build.trim().to_string())),
+                None => (s, None),
+            };
+            if s.is_empty() {
+                continue;
             } else if s.starts_with("https://") {
+                if reverse_deps.is_some() {
+                    // dependencies of git sources are unknown before cloning
+                    eprintln!("skip {} for --reverse-deps-of", s);
+                    continue;
+                }
+                if cmd.keyword.is_some() {
+                    eprintln!("skip {} for --keyword", s);
+                    continue;
+                }
                 // https://github.com/moonbitlang/core
+                // https://github.com/moonbitlang/core default
                 // https://github.com/moonbitlang/core hash1 hash2 hash3
                 let parts: Vec<&str> = s.split(' ').collect();
-                if parts.len() == 1 {
-                    repo_list.push(MooncakeSource::Git {
-                        url: parts[0].to_string(),
-                        rev: vec!["HEAD".to_string()],
-                        index: repo_list.len(),
-                    });
-                } else {
-                    repo_list.push(MooncakeSource::Git {
-                        url: parts[0].to_string(),
-                        rev: parts[1..].iter().copied().map(|s| s.to_string()).collect(),
-                        index: repo_list.len(),
-                    });
+                let url = parts[0].to_string();
+                let mut rev: Vec<String> =
+                    parts[1..].iter().copied().map(|s| s.to_string()).collect();
+                // resolved once cloned, see `build_fetched`
+                if rev.is_empty() {
+                    rev.push(git::DEFAULT_REV.to_string());
                 }
+                repo_list.push(MooncakeSource::Git {
+                    url,
+                    rev,
+                    index: repo_list.len(),
+                    pre_build,
+                });
             } else {
                 // moonbitlang/core
                 // moonbitlang/core 0.1.0 0.2.0
+                // private alice/pkg 0.1.0
+                let (s, private) = match s.strip_prefix("private ") {
+                    Some(s) => (s.trim_start(), Some(true)),
+                    None => (s, None),
+                };
                 let parts: Vec<&str> = s.split(' ').collect();
                 let name = parts[0].to_string();
                 #[cfg(target_os = "windows")]
@@ -161,11 +774,21 @@ fn get_mooncake_sources(
                     eprintln!("{} not found", name);
                     continue;
                 }
+                if let Some(reverse_deps) = &reverse_deps {
+                    if !reverse_deps.contains(&name) {
+                        continue;
+                    }
+                }
+                if let Some(keyword) = &cmd.keyword {
+                    if !db.has_keyword(&name, keyword) {
+                        continue;
+                    }
+                }
                 let mut version: Vec<String> = xs
                     .iter()
                     .map(|s| {
                         if s == "latest" {
-                            db.get_latest_version(&name)
+                            db.get_latest_version_with(&name, !cmd.exclude_prerelease)
                                 .map_err(|e| GetMooncakeSourcesError {
                                     kind: GetMooncakeSourcesErrorKind::MooncakesDB(e),
                                 })
@@ -180,6 +803,8 @@ fn get_mooncake_sources(
                     name,
                     version,
                     index: repo_list.len(),
+                    pre_build,
+                    private,
                 });
             }
         }
@@ -193,44 +818,164 @@ enum StatMooncakeError {
     RunMoon(#[from] RunMoonError),
 }
 
-fn stat_mooncake(
+/// Runs `run` again, up to `retries` times, while it fails with a stderr
+/// matching one of `patterns`.
+fn retry_on_patterns(
+    retries: u32,
+    patterns: &[String],
+    mut run: impl FnMut() -> Result<CommandOutput, RunMoonError>,
+) -> Result<CommandOutput, RunMoonError> {
+    let mut output = run();
+    for attempt in 1..=retries {
+        match &output {
+            Ok(o) if !o.success && matches_any(&o.stderr, patterns) => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "retrying ({}/{}) after a transient failure",
+                        attempt, retries
+                    )
+                    .yellow()
+                );
+                output = run();
+            }
+            _ => break,
+        }
+    }
+    output
+}
+
+/// Runs `cmd` on `target`, killed at `deadline`, the one of its backend, if
+/// that comes before its own timeout. Past the deadline, it is not run at all.
+fn stat_mooncake_on_target(
     workdir: &Path,
     source: &MooncakeSource,
     cmd: MoonCommand,
+    target: &str,
+    deadline: Option<Instant>,
+    opts: &cli::StatSubcommand,
+    events: &Events,
 ) -> Result<ExecuteResult, StatMooncakeError> {
-    let _ = run_moon(workdir, source, &["clean"]);
-
-    let is_moonbit_community = match source {
-        MooncakeSource::MooncakesIO { name, .. } => name.contains("moonbitlang"),
-        MooncakeSource::Git { url, .. } => url.contains("moonbitlang") || url.contains("moonbit-community"),
+    let left = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+    if left == Some(Duration::ZERO) {
+        return Ok(ExecuteResult {
+            status: Status::Timeout,
+            start_time: start_time_now(),
+            stderr: "not run, the backend was past its deadline\n".to_string(),
+            timed_out: true,
+            ..Default::default()
+        });
+    }
+    let timeout = match (opts.timeout_for(cmd), left) {
+        (Some(timeout), Some(left)) => Some(timeout.min(left)),
+        (timeout, left) => timeout.or(left),
     };
+    let mock = opts.mock_moon.as_deref();
+    let _ = run_moon(mock, workdir, source, &["clean"], None, left);
 
-    let r = run_moon(workdir, source, &cmd.args(is_moonbit_community)).map_err(|e| StatMooncakeError::RunMoon(e));
+    let jobs = opts.moon_jobs.map(|jobs| jobs.to_string());
+    let mut args = cmd.args(target, source.is_moonbit_community(), jobs.as_deref());
+    let json_diagnostics =
+        opts.json_diagnostics && matches!(cmd, MoonCommand::Check | MoonCommand::Build);
+    if json_diagnostics {
+        args.push("--output-json");
+    }
+    let log = opts.logs_dir.as_ref().map(|dir| {
+        dir.join(format!(
+            "{}_{}_{}",
+            source.get_index(),
+            args[0],
+            target.replace(['/', '\\'], "_")
+        ))
+    });
+    let r = retry_on_patterns(opts.retries, &opts.retry_on_patterns, || {
+        run_moon(mock, workdir, source, &args, log.as_deref(), timeout)
+    })
+    .map_err(StatMooncakeError::RunMoon);
     let status = match r.as_ref() {
         Ok(output) if output.success => Status::Success,
+        Ok(output) if output.timed_out => Status::Timeout,
         _ => Status::Failure,
     };
     let output = r.ok();
-    let start_time = Local::now()
-        .with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
-        .format("%Y-%m-%d %H:%M:%S.%3f")
-        .to_string();
+    let start_time = start_time_now();
     let elapsed = output
         .as_ref()
         .map(|d| d.duration.as_millis() as u64)
         .unwrap_or(0);
+    events.emit(Event::CommandFinished {
+        source: source.get_index(),
+        command: args[0],
+        target,
+        success: matches!(status, Status::Success),
+        elapsed,
+    });
+    let (tests, failed_tests) = match (cmd, output.as_ref()) {
+        (MoonCommand::Test, Some(d)) => {
+            let all = format!("{}\n{}", d.stdout, d.stderr);
+            (parse_test_summary(&all), parse_failed_tests(&all))
+        }
+        _ => (None, vec![]),
+    };
+    let compiler_mismatch = output.as_ref().is_some_and(|d| {
+        detect_compiler_mismatch(
+            &format!("{}\n{}", d.stdout, d.stderr),
+            &opts.compiler_mismatch_patterns(),
+        )
+    });
+    let fully_cached = matches!(cmd, MoonCommand::Build)
+        && output.as_ref().is_some_and(|d| {
+            d.success
+                && matches_any(
+                    &format!("{}\n{}", d.stdout, d.stderr),
+                    &opts.fully_cached_patterns(),
+                )
+        });
+    let diagnostics = match output.as_ref() {
+        Some(d) if json_diagnostics => {
+            parse_json_diagnostics(&format!("{}\n{}", d.stdout, d.stderr))
+        }
+        _ => vec![],
+    };
+    let artifacts = match cmd {
+        MoonCommand::Build if opts.list_artifacts => Some(list_artifacts(workdir, target)),
+        _ => None,
+    };
+    if matches!(status, Status::Success) && artifacts.as_ref().is_some_and(|a| a.is_empty()) {
+        eprintln!(
+            "{}",
+            format!(
+                "moon build --target {} emitted no artifacts for {:?}",
+                target, source
+            )
+            .red()
+        );
+    }
+    // status is decided by the exit code above, capture only affects what is stored
+    let (stdout, stderr) = output
+        .as_ref()
+        .map(|d| opts.capture.apply(d.stdout.clone(), d.stderr.clone()))
+        .unwrap_or_default();
     let execute_result = ExecuteResult {
         status,
         start_time,
         elapsed,
-        stdout: output
-            .as_ref()
-            .map(|d| d.stdout.clone())
-            .unwrap_or_default(),
-        stderr: output
-            .as_ref()
-            .map(|d| d.stderr.clone())
-            .unwrap_or_default(),
+        stdout,
+        stderr,
+        tests_passed: tests.map(|t| t.passed),
+        tests_failed: tests.map(|t| t.failed),
+        tests_total: tests.map(|t| t.total),
+        exit_code: output.as_ref().and_then(|d| d.exit_code),
+        compiler_mismatch,
+        fully_cached,
+        jobs: opts.moon_jobs.filter(|_| cmd.takes_jobs()),
+        timed_out: output.as_ref().is_some_and(|d| d.timed_out),
+        failed_tests,
+        diagnostics,
+        artifacts,
+        log_encoding: None,
+        user_ms: output.as_ref().and_then(|d| d.cpu).map(|c| c.user_ms),
+        sys_ms: output.as_ref().and_then(|d| d.cpu).map(|c| c.sys_ms),
     };
     Ok(execute_result)
 }
@@ -247,91 +992,281 @@ pub enum BuildError {
     GitError(git::GitOpsError),
 }
 
-pub fn build(source: &MooncakeSource) -> Result<BuildState, BuildError> {
-    let tmp = tempfile::tempdir().map_err(|e| BuildError::IOError(e))?;
-    let mut cbts = vec![];
+pub fn build(source: &MooncakeSource, opts: &cli::StatSubcommand, events: &Events) -> BuildState {
+    build_fetched(fetch(source, opts, events), opts, events)
+}
+
+/// A source cloned or downloaded to a temp dir, ready to be built.
+pub struct Fetched<'a> {
+    source: &'a MooncakeSource,
+    /// an error when the source could not be cloned
+    tmp: Result<tempfile::TempDir, BuildError>,
+    /// one per version of a mooncake, empty for git
+    downloads: Downloads,
+    started: Instant,
+    fetch_ms: u64,
+    network_used: bool,
+}
+
+type Downloads = Vec<Result<(), mooncakesio::MooncakesIOError>>;
+
+/// Clones or downloads `source` to a temp dir under `--build-root`.
+pub fn fetch<'a>(
+    source: &'a MooncakeSource,
+    opts: &cli::StatSubcommand,
+    events: &Events,
+) -> Fetched<'a> {
+    let started = Instant::now();
+    events.emit(Event::SourceStarted {
+        source: source.get_index(),
+        name: source.name(),
+    });
+    // a failed clone of a remote still went over the network
+    let (tmp, downloads, network_used) = match fetch_to_tempdir(source, opts) {
+        Ok((tmp, downloads, network_used)) => (Ok(tmp), downloads, network_used),
+        Err(e) => (Err(e), vec![], source.needs_network()),
+    };
+    Fetched {
+        source,
+        tmp,
+        downloads,
+        started,
+        fetch_ms: started.elapsed().as_millis() as u64,
+        network_used,
+    }
+}
+
+/// The temp dir with the fetched source, and whether fetching it went over the
+/// network rather than copying a local repo or cached archives.
+fn fetch_to_tempdir(
+    source: &MooncakeSource,
+    opts: &cli::StatSubcommand,
+) -> Result<(tempfile::TempDir, Downloads, bool), BuildError> {
+    let tmp = tempdir_under(opts.build_root.as_deref()).map_err(BuildError::IOError)?;
+    let (downloads, network_used) = match source {
+        MooncakeSource::Git { url, .. } => {
+            git::git_clone_to(url, tmp.path(), "test").map_err(BuildError::GitError)?;
+            (vec![], source.needs_network())
+        }
+        MooncakeSource::MooncakesIO {
+            name,
+            version,
+            private,
+            ..
+        } => {
+            let token = mooncakesio::auth_token(private.unwrap_or(false));
+            mooncakesio::fetch_versions_to(
+                name,
+                version,
+                tmp.path(),
+                token.as_deref(),
+                opts.download_cache.as_deref(),
+            )
+        }
+    };
+    Ok((tmp, downloads, network_used))
+}
+
+fn build_fetched(fetched: Fetched, opts: &cli::StatSubcommand, events: &Events) -> BuildState {
+    let Fetched {
+        source,
+        tmp,
+        downloads,
+        started,
+        fetch_ms,
+        network_used,
+    } = fetched;
+    let mut state = BuildState::new(source.get_index());
+    state.fetch_ms = fetch_ms;
+    state.network_used = network_used;
+    let finish = |state: BuildState| {
+        events.emit(Event::SourceFinished {
+            source: source.get_index(),
+            elapsed: started.elapsed().as_millis() as u64,
+        });
+        state
+    };
+    let tmp = match tmp {
+        Ok(tmp) => tmp,
+        Err(e) => {
+            let error = format!("{:#}", anyhow::Error::from(e));
+            eprintln!("Failed to fetch {:?}: {}", source, error);
+            state.fetch_failed(source.revs().len(), error);
+            return finish(state);
+        }
+    };
 
     match source {
-        MooncakeSource::Git { url, rev, index: _ } => {
-            git::git_clone_to(url, tmp.path(), "test").map_err(|e| BuildError::GitError(e))?;
+        MooncakeSource::Git { url, rev, .. } => {
             let workdir = tmp.path().join("test");
+            // resolved before any checkout, while HEAD is still the default branch
+            let default_branch = rev.iter().any(|h| h == git::DEFAULT_REV).then(|| {
+                git::get_default_branch(&workdir).unwrap_or_else(|e| {
+                    eprintln!(
+                        "failed to resolve the default branch of {}, using HEAD: {:#}",
+                        url,
+                        anyhow::Error::from(e)
+                    );
+                    git::get_git_hash(&workdir).unwrap_or_else(|_| "HEAD".to_string())
+                })
+            });
             for h in rev {
-                if let Err(e) = git::git_checkout(&workdir, h) {
-                    eprintln!("Failed to checkout {}: {}", h, e);
-                    cbts.push(None);
-                    continue;
+                // the checked out branch is recorded in `revs`
+                let h = match (h.as_str(), &default_branch) {
+                    (git::DEFAULT_REV, Some(branch)) => branch,
+                    _ => h,
+                };
+                let checkout = git::git_checkout(&workdir, h)
+                    .and_then(|()| git::get_git_hash(&workdir))
+                    .map_err(|e| match git::rev_exists(&workdir, h) {
+                        true => format!("{:#}", anyhow::Error::from(e)),
+                        false => "rev not found".to_string(),
+                    });
+                match checkout {
+                    Ok(sha) => state.revs.push(RevStatus::CheckedOut {
+                        rev: h.clone(),
+                        sha,
+                    }),
+                    Err(reason) => {
+                        eprintln!("Failed to checkout {}: {}", h, reason);
+                        state.skip(
+                            SkipKind::Error,
+                            format!("failed to checkout {}: {}", h, reason),
+                        );
+                        state.revs.push(RevStatus::CheckoutFailed {
+                            rev: h.clone(),
+                            reason,
+                        });
+                        continue;
+                    }
                 }
-                cbts.push(run_matrix(&workdir, source).ok());
+                build_workdir(&mut state, &workdir, source, opts, events);
             }
         }
-        MooncakeSource::MooncakesIO {
-            name,
-            version,
-            index: _,
-        } => {
-            for v in version {
-                if let Err(e) = mooncakesio::download_to(name, v, tmp.path()) {
-                    eprintln!("Failed to download {}/{}: {}", name, v, e);
-                    cbts.push(None);
+        MooncakeSource::MooncakesIO { name, version, .. } => {
+            for (v, download) in version.iter().zip(downloads) {
+                if let Err(e) = download {
+                    let error = format!(
+                        "failed to download {}/{}: {:#}",
+                        name,
+                        v,
+                        anyhow::Error::from(e)
+                    );
+                    eprintln!("{}", error);
+                    state.fetch_error.get_or_insert(error.clone());
+                    state.skip(SkipKind::Error, error);
                     continue;
                 }
-                let workdir = tmp.path().join(v);
-                cbts.push(run_matrix(&workdir, source).ok());
+                let workdir = tmp.path().join(mooncakesio::safe_dir_name(v));
+                build_workdir(&mut state, &workdir, source, opts, events);
             }
         }
     }
 
-    Ok(BuildState {
-        source: source.get_index(),
-        cbts,
-    })
+    finish(state)
+}
+
+fn build_workdir(
+    state: &mut BuildState,
+    workdir: &Path,
+    source: &MooncakeSource,
+    opts: &cli::StatSubcommand,
+    events: &Events,
+) {
+    if let Some(command) = source.pre_build() {
+        let result = run_pre_build(workdir, command);
+        let failed = !matches!(result.status, Status::Success);
+        state.pre_build.insert(state.cbts.len(), result);
+        if failed {
+            eprintln!("Pre-build `{}` failed for {:?}", command, source);
+            state.skip(SkipKind::Error, format!("pre-build `{}` failed", command));
+            return;
+        }
+    }
+    state
+        .cbts
+        .push(run_matrix(workdir, source, opts, events).ok());
+    if opts.record_deps {
+        state.deps = resolved_deps(workdir);
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 enum RunMatrixError {
     #[error("stat mooncake")]
     StatMooncake(#[from] StatMooncakeError),
+    #[error("failed to copy the workdir")]
+    Workdir(#[source] std::io::Error),
+    #[error("no result for backend {0:?}")]
+    MissingBackend(Backend),
 }
 
-fn run_matrix(workdir: &Path, source: &MooncakeSource) -> Result<CBT, RunMatrixError> {
-    let check_wasm = stat_mooncake(workdir, source, MoonCommand::Check(Backend::Wasm))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-    let check_wasm_gc = stat_mooncake(workdir, source, MoonCommand::Check(Backend::WasmGC))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-    let check_js = stat_mooncake(workdir, source, MoonCommand::Check(Backend::Js))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-
-    let build_wasm = stat_mooncake(workdir, source, MoonCommand::Build(Backend::Wasm))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-    let build_wasm_gc = stat_mooncake(workdir, source, MoonCommand::Build(Backend::WasmGC))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-    let build_js = stat_mooncake(workdir, source, MoonCommand::Build(Backend::Js))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-
-    let test_wasm = stat_mooncake(workdir, source, MoonCommand::Test(Backend::Wasm))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-    let test_wasm_gc = stat_mooncake(workdir, source, MoonCommand::Test(Backend::WasmGC))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-    let test_js = stat_mooncake(workdir, source, MoonCommand::Test(Backend::Js))
-        .map_err(|e| RunMatrixError::StatMooncake(e))?;
-
-    Ok(CBT {
-        check: BackendState {
-            wasm: check_wasm,
-            wasm_gc: check_wasm_gc,
-            js: check_js,
-        },
-        build: BackendState {
-            wasm: build_wasm,
-            wasm_gc: build_wasm_gc,
-            js: build_js,
-        },
-        test: BackendState {
-            wasm: test_wasm,
-            wasm_gc: test_wasm_gc,
-            js: test_js,
-        },
-    })
+fn run_matrix(
+    workdir: &Path,
+    source: &MooncakeSource,
+    opts: &cli::StatSubcommand,
+    events: &Events,
+) -> Result<CBT, RunMatrixError> {
+    let backends = MatrixBackend::all(&opts.extra_backends);
+
+    let schedule = opts.schedule();
+    // parallel targets build in copies of the workdir, their `moon clean`s would race otherwise
+    let copies = match schedule {
+        Schedule::BackendParallel(_) => {
+            Some(tempdir_under(opts.build_root.as_deref()).map_err(RunMatrixError::Workdir)?)
+        }
+        _ => None,
+    };
+    let columns = schedule.run_backends(backends.iter().enumerate().collect(), |(i, backend)| {
+        let workdir = match &copies {
+            Some(tmp) => {
+                let copy = tmp.path().join(i.to_string());
+                copy_dir(workdir, &copy).map_err(RunMatrixError::Workdir)?;
+                copy
+            }
+            None => workdir.to_path_buf(),
+        };
+        // a backend that hangs only times out its own commands
+        let deadline = opts
+            .backend_timeout()
+            .map(|timeout| Instant::now() + timeout);
+        let run = |cmd| {
+            stat_mooncake_on_target(
+                &workdir,
+                source,
+                cmd,
+                backend.flag(),
+                deadline,
+                opts,
+                events,
+            )
+        };
+        MoonCommand::all()
+            .into_iter()
+            .map(|cmd| Ok(run(cmd)?))
+            .collect::<Result<Vec<_>, RunMatrixError>>()
+    });
+    // a column of results per backend, into a row of results per command
+    let mut rows: [Vec<(MatrixBackend, ExecuteResult)>; 3] = Default::default();
+    for (backend, column) in backends.iter().zip(columns) {
+        for (row, result) in rows.iter_mut().zip(column?) {
+            row.push((backend.clone(), result));
+        }
+    }
+    let [check, build, test] =
+        rows.map(|row| BackendState::collect(row).map_err(RunMatrixError::MissingBackend));
+    let mut cbt = CBT {
+        check: check?,
+        build: build?,
+        test: test?,
+        diagnostics_by_file: None,
+    };
+
+    if opts.json_diagnostics {
+        cbt.diagnostics_by_file = Some(cbt.count_diagnostics_by_file());
+    }
+
+    Ok(cbt)
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -342,6 +1277,7 @@ struct StatError {
 }
 
 #[derive(Debug, thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 enum StatErrorKind {
     #[error("failed on moon operations")]
     MoonOpsError(#[from] MoonOpsError),
@@ -349,83 +1285,228 @@ enum StatErrorKind {
     #[error("failed on get mooncake sources")]
     GetMooncakeSourcesError(#[from] GetMooncakeSourcesError),
 
-    #[error("failed on build")]
-    BuildError(#[from] BuildError),
+    #[error("failed to warm up moon core")]
+    WarmCore(#[source] std::io::Error),
+
+    #[error("failed to bind result socket")]
+    Socket(#[source] std::io::Error),
+
+    #[error("failed to create the logs directory")]
+    LogsDir(#[source] std::io::Error),
+
+    #[error("failed to open the events file")]
+    EventsFile(#[source] std::io::Error),
+
+    #[error("failed to open the journal")]
+    Journal(#[source] std::io::Error),
+
+    #[error("failed to read the exclude config")]
+    ReadExclude(#[source] std::io::Error),
+
+    #[error("invalid exclude config")]
+    ParseExclude(#[source] ExcludeConfigError),
+
+    #[error("failed to read the toolchains to select")]
+    SelectToolchain(#[source] moon_dashboard::dashboard::DashboardReadError),
+
+    #[error("no installable {0:?} toolchain recorded in the selected dashboard")]
+    NoRecordedToolchain(ToolChainLabel),
+
+    #[error("failed to install pinned toolchain {version}, it may no longer be available")]
+    PinnedInstall {
+        version: String,
+        #[source]
+        source: MoonOpsError,
+    },
 }
 
 fn stat(cmd: cli::StatSubcommand) -> Result<MoonBuildDashboard, StatError> {
     let run_id = std::env::var("GITHUB_ACTION_RUN_ID").unwrap_or("0".into());
     let run_number = std::env::var("GITHUB_ACTION_RUN_NUMBER").unwrap_or("0".into());
-
-    if !cmd.skip_install {
-        install_stable_release().map_err(|e| StatError {
-            kind: StatErrorKind::MoonOpsError(e),
+    let reproducibility = ReproInfo::capture();
+    let mut warmup = BTreeMap::new();
+    if let Some(dir) = &cmd.logs_dir {
+        std::fs::create_dir_all(dir).map_err(|e| StatError {
+            kind: StatErrorKind::LogsDir(e),
         })?;
     }
-    if !cmd.skip_update {
-        moon_update().map_err(|e| StatError {
-            kind: StatErrorKind::MoonOpsError(e),
-        })?;
-    }
-    let moon_version = get_moon_version().map_err(|e| StatError {
-        kind: StatErrorKind::MoonOpsError(e),
-    })?;
-    let moonc_version = get_moonc_version().map_err(|e| StatError {
-        kind: StatErrorKind::MoonOpsError(e),
+    let events = Events::open(cmd.events_file.as_deref()).map_err(|e| StatError {
+        kind: StatErrorKind::EventsFile(e),
     })?;
-    let stable_toolchain_version = ToolChainVersion {
-        label: ToolChainLabel::Stable,
-        moon_version,
-        moonc_version,
+    let journal_path = cmd.journal_path();
+    let mut resumed = match (&journal_path, cmd.resume) {
+        (Some(path), true) if path.exists() => Resumed::read(path).map_err(|e| StatError {
+            kind: StatErrorKind::Journal(e),
+        })?,
+        _ => Resumed::default(),
     };
-
-    let mooncake_sources = get_mooncake_sources(&cmd).map_err(|e| StatError {
-        kind: StatErrorKind::GetMooncakeSourcesError(e),
-    })?;
-    let mut stable_release_data = vec![];
-
-    for source in mooncake_sources {
-        let build_state = build(&source).map_err(|e| StatError {
-            kind: StatErrorKind::BuildError(e),
+    let mut journal =
+        Journal::open(journal_path.as_deref(), cmd.resume).map_err(|e| StatError {
+            kind: StatErrorKind::Journal(e),
         })?;
-        stable_release_data.push(build_state);
-    }
-
-    if !cmd.skip_install {
-        install_bleeding_release().map_err(|e| StatError {
-            kind: StatErrorKind::MoonOpsError(e),
+    let excludes = match &cmd.exclude {
+        Some(file) => {
+            let content = std::fs::read_to_string(file).map_err(|e| StatError {
+                kind: StatErrorKind::ReadExclude(e),
+            })?;
+            ExcludeConfig::parse(&content).map_err(|e| StatError {
+                kind: StatErrorKind::ParseExclude(e),
+            })?
+        }
+        None => ExcludeConfig::default(),
+    };
+    let excluded = |source: &MooncakeSource| {
+        let reason = excludes.reason(source, current_os())?;
+        eprintln!("skip {}: {}", source.name(), reason);
+        Some(reason)
+    };
+    #[cfg(unix)]
+    let mut socket = cmd
+        .socket
+        .as_deref()
+        .map(moon_dashboard::socket::ResultSocket::bind)
+        .transpose()
+        .map_err(|e| StatError {
+            kind: StatErrorKind::Socket(e),
         })?;
-    }
-    if !cmd.skip_update {
-        moon_update().map_err(|e| StatError {
-            kind: StatErrorKind::MoonOpsError(e),
+
+    let mut phases = vec![];
+    let mut mooncake_sources = vec![];
+    // packages of the first `moon update`, later phases build the same sources
+    let mut updated_packages: Option<BTreeSet<String>> = None;
+    let mut planned = plan_phases(
+        cmd.skip_install,
+        cmd.skip_update,
+        cmd.use_local_toolchain,
+        cmd.phase_order,
+    );
+    if let Some(file) = &cmd.select_toolchain_from {
+        let prior = MoonBuildDashboard::from_file(file).map_err(|e| StatError {
+            kind: StatErrorKind::SelectToolchain(e),
         })?;
+        if let Some(label) = pin_phases(&mut planned, &prior).first() {
+            return Err(StatError {
+                kind: StatErrorKind::NoRecordedToolchain(*label),
+            });
+        }
     }
-    let moon_version = get_moon_version().map_err(|e| StatError {
-        kind: StatErrorKind::MoonOpsError(e),
-    })?;
-    let moonc_version = get_moonc_version().map_err(|e| StatError {
-        kind: StatErrorKind::MoonOpsError(e),
-    })?;
-    let bleeding_toolchain_version = ToolChainVersion {
-        label: ToolChainLabel::Bleeding,
-        moon_version,
-        moonc_version,
-    };
-
-    let mooncake_sources = get_mooncake_sources(&cmd).map_err(|e| StatError {
-        kind: StatErrorKind::GetMooncakeSourcesError(e),
-    })?;
-    let mut bleeding_release_data = vec![];
+    for phase in planned {
+        let update_output = phase
+            .prepare_mocked(cmd.mock_moon.as_deref())
+            .map_err(|e| StatError {
+                kind: match &phase.pin {
+                    Some(version) => StatErrorKind::PinnedInstall {
+                        version: version.clone(),
+                        source: e,
+                    },
+                    None => StatErrorKind::MoonOpsError(e),
+                },
+            })?;
+        if updated_packages.is_none() {
+            updated_packages = update_output.map(|output| parse_updated_packages(&output));
+        }
+        let moon_version =
+            get_moon_version_with(cmd.mock_moon.as_deref()).map_err(|e| StatError {
+                kind: StatErrorKind::MoonOpsError(e),
+            })?;
+        let moonc_version =
+            get_moonc_version_with(cmd.mock_moon.as_deref()).map_err(|e| StatError {
+                kind: StatErrorKind::MoonOpsError(e),
+            })?;
+        let toolchain_version = ToolChainVersion {
+            label: phase.label,
+            moon_version,
+            moonc_version,
+            core_version: get_core_version(),
+        };
+        if cmd.warm_core {
+            warm_core_once(&mut warmup, phase.label.key(), warm_core).map_err(|e| StatError {
+                kind: StatErrorKind::WarmCore(e),
+            })?;
+        }
 
-    for source in mooncake_sources.iter() {
-        let build_state = build(source).map_err(|e| StatError {
-            kind: StatErrorKind::BuildError(e),
+        mooncake_sources = get_mooncake_sources(&cmd).map_err(|e| StatError {
+            kind: StatErrorKind::GetMooncakeSourcesError(e),
         })?;
-        bleeding_release_data.push(build_state);
+        if cmd.build_updated_only {
+            let updated = updated_packages.clone().unwrap_or_default();
+            mooncake_sources = only_updated(mooncake_sources, &updated);
+        }
+        journal.phase(&toolchain_version);
+        let mut done = resumed.take_phase(&toolchain_version, &mooncake_sources);
+        if !done.is_empty() {
+            eprintln!("resuming {} sources of {}", done.len(), phase.label.key());
+        }
+        let mut send = |source: &MooncakeSource, build_state: &BuildState| {
+            #[cfg(unix)]
+            if let Some(socket) = socket.as_mut() {
+                socket.send(build_state);
+            }
+            journal.source(phase.label, source.name(), build_state);
+        };
+        let mut release_data = vec![];
+        match cmd.schedule() {
+            // clone and download the next sources while building the current one
+            Schedule::SourcePipeline(workers) => {
+                let built = pipeline::run_pipeline(
+                    mooncake_sources
+                        .iter()
+                        .map(|source| (source, done.remove(&source.get_index())))
+                        .collect(),
+                    workers,
+                    |(source, resumed)| {
+                        let ready = resumed.or_else(|| {
+                            let reason = excluded(source)?;
+                            Some(BuildState::skip_all(source, SkipKind::Excluded, reason))
+                        });
+                        match ready {
+                            Some(build_state) => (source, Err(build_state)),
+                            None => (source, Ok(fetch(source, &cmd, &events))),
+                        }
+                    },
+                    |(source, fetched)| {
+                        let build_state = match fetched {
+                            Ok(fetched) => build_fetched(fetched, &cmd, &events),
+                            Err(build_state) => build_state,
+                        };
+                        send(source, &build_state);
+                        build_state
+                    },
+                );
+                release_data.extend(built);
+            }
+            Schedule::Serial | Schedule::BackendParallel(_) => {
+                for source in mooncake_sources.iter() {
+                    let build_state = match done.remove(&source.get_index()) {
+                        Some(build_state) => build_state,
+                        None => match excluded(source) {
+                            Some(reason) => {
+                                BuildState::skip_all(source, SkipKind::Excluded, reason)
+                            }
+                            None => build(source, &cmd, &events),
+                        },
+                    };
+                    send(source, &build_state);
+                    release_data.push(build_state);
+                }
+            }
+        }
+        phases.push((toolchain_version, release_data));
     }
 
+    // a local toolchain runs a single phase, recorded as stable with no bleeding data
+    let (bleeding, stable): (Vec<_>, Vec<_>) = phases
+        .into_iter()
+        .partition(|(version, _)| version.label == ToolChainLabel::Bleeding);
+    let (stable_toolchain_version, stable_release_data) =
+        stable.into_iter().next().expect("a stable or local phase");
+    let (bleeding_toolchain_version, bleeding_release_data) = bleeding
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| (stable_toolchain_version.clone(), vec![]));
+
     let result = MoonBuildDashboard {
+        schema_version: SCHEMA_VERSION,
         run_id,
         run_number,
         sources: mooncake_sources,
@@ -434,40 +1515,549 @@ fn stat(cmd: cli::StatSubcommand) -> Result<MoonBuildDashboard, StatError> {
         stable_release_data,
         bleeding_toolchain_version,
         bleeding_release_data,
+        reproducibility,
+        pruned: vec![],
+        warmup,
+        bleeding_changed_since_latest: None,
+        updated_packages: updated_packages
+            .map(|p| p.into_iter().collect())
+            .unwrap_or_default(),
+        labels: BTreeMap::new(),
     };
     Ok(result)
 }
 
+/// The mooncakes among `updated`, reindexed. Git sources are not in the registry
+/// and are dropped.
+fn only_updated(sources: Vec<MooncakeSource>, updated: &BTreeSet<String>) -> Vec<MooncakeSource> {
+    sources
+        .into_iter()
+        .filter(|s| {
+            matches!(s, MooncakeSource::MooncakesIO { .. })
+                && updated.contains(&s.name().replace('\\', "/"))
+        })
+        .enumerate()
+        .map(|(i, s)| s.with_index(i))
+        .collect()
+}
+
+fn print_summary(sources: &[MooncakeSource], states: &[BuildState]) {
+    for (source, state) in sources.iter().zip(states) {
+        let results: Vec<&ExecuteResult> = state
+            .cbts
+            .iter()
+            .flatten()
+            .flat_map(|cbt| cbt.results().map(|(_, _, r)| r))
+            .collect();
+        let success = results
+            .iter()
+            .filter(|r| matches!(r.status, Status::Success))
+            .count();
+        println!(
+            "{} {}: {}/{} succeeded",
+            source.name(),
+            source.revs().join(" "),
+            success,
+            results.len()
+        );
+    }
+}
+
+/// Re-runs only the sources that are new or changed since the last save of `--file`,
+/// with the toolchain currently installed.
+fn watch(cmd: cli::StatSubcommand) -> anyhow::Result<()> {
+    let file = cmd.file.clone().expect("--watch requires --file");
+    let mut seen: Vec<MooncakeSource> = vec![];
+    let events = Events::open(cmd.events_file.as_deref())?;
+    let mut run = || -> anyhow::Result<()> {
+        let sources = get_mooncake_sources(&cmd)?;
+        let changed: Vec<MooncakeSource> = sources
+            .into_iter()
+            .map(|s| s.with_index(0))
+            .filter(|s| !seen.contains(s))
+            .collect();
+        let mut states = vec![];
+        for source in changed.iter() {
+            states.push(build(source, &cmd, &events));
+        }
+        print_summary(&changed, &states);
+        seen.extend(changed);
+        Ok(())
+    };
+    run()?;
+    eprintln!("watching {} for changes", file.display());
+    moon_dashboard::watch::watch_file(&file, Duration::from_millis(500), || {
+        if let Err(e) = run() {
+            eprintln!("{:?}", e);
+        }
+        ControlFlow::Continue(())
+    })?;
+    Ok(())
+}
+
+fn print_matrix_cost(cmd: &cli::StatSubcommand) -> anyhow::Result<()> {
+    let sources = get_mooncake_sources(cmd)?;
+    let items = plan_work_items(&sources, current_os(), &cmd.extra_backends, None);
+    let phases = plan_phases(
+        cmd.skip_install,
+        cmd.skip_update,
+        cmd.use_local_toolchain,
+        cmd.phase_order,
+    )
+    .len();
+    let history = cmd
+        .cost_history
+        .as_deref()
+        .map(MoonBuildDashboard::from_file)
+        .transpose()?;
+    let cost = matrix_cost(&items, phases, history.as_ref());
+    println!(
+        "{} commands: {} sources, {} work items on {}, {} toolchains",
+        cost.commands,
+        sources.len(),
+        items.len(),
+        current_os(),
+        phases
+    );
+    if let Some(ms) = cost.estimated_ms {
+        println!(
+            "estimated {:.1} minutes, {} commands without a recorded timing",
+            ms as f64 / 60_000.0,
+            cost.unknown
+        );
+    }
+    Ok(())
+}
+
+fn print_work_items(cmd: &cli::StatSubcommand) -> anyhow::Result<()> {
+    let sources = get_mooncake_sources(cmd)?;
+    let jobs = cmd.moon_jobs.map(|jobs| jobs.to_string());
+    for item in plan_work_items(&sources, current_os(), &cmd.extra_backends, jobs.as_deref()) {
+        println!("{}", serde_json::to_string(&item)?);
+    }
+    Ok(())
+}
+
+/// Checks the sources of `cmd` are reachable, failing with the ones that are not.
+fn preflight(cmd: cli::StatSubcommand) -> anyhow::Result<()> {
+    let sources = get_mooncake_sources(&cmd)?;
+    let unreachable = moon_dashboard::preflight::preflight(
+        &sources,
+        8,
+        |url| git::check_reachable(url).map_err(|e| e.to_string()),
+        |name, version, private| {
+            let token = mooncakesio::auth_token(private);
+            mooncakesio::check_version_exists(name, version, token.as_deref())
+                .map_err(|e| e.to_string())
+        },
+    );
+    for u in unreachable.iter() {
+        let name = match &u.version {
+            Some(version) => format!("{}/{}", u.name, version),
+            None => u.name.clone(),
+        };
+        eprintln!("{}", format!("unreachable: {}: {}", name, u.error).red());
+    }
+    if !unreachable.is_empty() {
+        anyhow::bail!("{} of the sources are unreachable", unreachable.len());
+    }
+    eprintln!("all {} sources are reachable", sources.len());
+    Ok(())
+}
+
+/// Installs candidates of `cmd` until the first one the source fails on is found.
+fn bisect(cmd: cli::BisectSubcommand) -> anyhow::Result<()> {
+    let opts = &cmd.stat;
+    if let Some(proxy) = &opts.proxy {
+        set_proxy(proxy.clone());
+    }
+    set_allow_stale_registry(opts.allow_stale_registry);
+    if let Some(script) = &opts.mock_moon {
+        set_mock_moon(script.clone());
+    }
+    let sources = get_mooncake_sources(opts)?;
+    let source = sources.first().context("no source to bisect")?;
+    if sources.len() > 1 {
+        eprintln!(
+            "bisecting {}, the first of {} sources",
+            source.name(),
+            sources.len()
+        );
+    }
+    let candidates = &cmd.candidates;
+    let result = moon_dashboard::bisect::bisect(candidates.len(), |i| {
+        let version = &candidates[i];
+        let phase = Phase {
+            label: ToolChainLabel::Bleeding,
+            install: true,
+            update: !opts.skip_update,
+            pin: Some(version.clone()),
+        };
+        phase
+            .prepare_mocked(opts.mock_moon.as_deref())
+            .with_context(|| format!("failed to install {}", version))?;
+        let passes = build(source, opts, &Events::default()).all_succeeded();
+        eprintln!(
+            "{}: {}",
+            version,
+            if passes {
+                "passes".green()
+            } else {
+                "fails".red()
+            }
+        );
+        anyhow::Ok(passes)
+    })?;
+    match result {
+        Bisection::FirstFailing(i) => {
+            println!(
+                "{} first fails on {}, it passes on {}",
+                source.name(),
+                candidates[i],
+                candidates[i - 1]
+            );
+            Ok(())
+        }
+        Bisection::FailsFromStart => {
+            anyhow::bail!("{} already fails on {}", source.name(), candidates[0])
+        }
+        Bisection::NoFailure => anyhow::bail!("{} passes on every candidate", source.name()),
+    }
+}
+
+fn doctor(cmd: cli::DoctorSubcommand) -> anyhow::Result<()> {
+    let env = ReproInfo::capture();
+    if cmd.dump_env {
+        println!("{}", serde_json::to_string_pretty(&env)?);
+    }
+    let report = env.doctor_report();
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    }
+    let missing: Vec<&str> = report
+        .tools
+        .iter()
+        .filter(|tool| !tool.found)
+        .map(|tool| tool.name.as_str())
+        .collect();
+    if !cmd.json {
+        for program in missing.iter() {
+            eprintln!(
+                "{}",
+                format!("{} is not installed or fails to run", program).red()
+            );
+        }
+    }
+    if !missing.is_empty() {
+        anyhow::bail!("missing {}", missing.join(", "));
+    }
+    Ok(())
+}
+
+fn merge(cmd: cli::MergeSubcommand) -> anyhow::Result<()> {
+    let merged = moon_dashboard::merge::merge_files(&cmd.inputs, cmd.strict_merge)?;
+    std::fs::write(&cmd.output, serde_json::to_string(&merged)?)?;
+    Ok(())
+}
+
+fn render(cmd: cli::RenderSubcommand) -> anyhow::Result<()> {
+    let mut dashboard = MoonBuildDashboard::from_file(&cmd.file)?;
+    output::decode_logs(&mut dashboard)?;
+    let renderers = [
+        (
+            &cmd.html,
+            report::render_html as fn(&MoonBuildDashboard) -> String,
+        ),
+        (&cmd.junit, report::render_junit),
+        (&cmd.csv, report::render_csv),
+        (&cmd.prometheus, report::render_prometheus),
+        (&cmd.markdown, report::render_markdown),
+    ];
+    for (path, render) in renderers {
+        if let Some(path) = path {
+            std::fs::write(path, render(&dashboard))?;
+        }
+    }
+    if let Some(group_by) = cmd.group_by {
+        print!("{}", report::render_groups(&dashboard, group_by));
+    }
+    if !cmd.repeats.is_empty() {
+        let mut runs = vec![dashboard];
+        for path in cmd.repeats.iter() {
+            runs.push(MoonBuildDashboard::from_file(path)?);
+        }
+        print!("{}", report::render_flaky(&runs));
+    }
+    Ok(())
+}
+
+fn diff(cmd: cli::DiffSubcommand) -> anyhow::Result<()> {
+    let read = |path: &Path| {
+        MoonBuildDashboard::from_file(path)
+            .with_context(|| format!("failed to read {}", path.display()))
+    };
+    let (before, after) = (read(&cmd.before)?, read(&cmd.after)?);
+    let changes = moon_dashboard::diff::diff(&before, &after);
+    print!("{}", moon_dashboard::diff::render_diff(&changes));
+    let regressions = changes
+        .iter()
+        .filter(|c| c.change() == moon_dashboard::diff::Change::Regression)
+        .count();
+    if regressions > 0 {
+        anyhow::bail!("{} regressions", regressions);
+    }
+    Ok(())
+}
+
+fn stats(cmd: cli::StatsSubcommand) -> anyhow::Result<()> {
+    let mut dashboards = vec![];
+    for path in cmd.files.iter() {
+        dashboards.push(
+            MoonBuildDashboard::from_file(path)
+                .with_context(|| format!("failed to read {}", path.display()))?,
+        );
+    }
+    let stats = moon_dashboard::stats::run_stats(&dashboards, cmd.top);
+    if cmd.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        print!("{}", moon_dashboard::stats::render_stats(&stats));
+    }
+    Ok(())
+}
+
+fn validate_output(cmd: cli::ValidateOutputSubcommand) -> anyhow::Result<()> {
+    let dashboard = MoonBuildDashboard::from_file(&cmd.file)?;
+    let violations = validate::validate(&dashboard);
+    for violation in violations.iter() {
+        eprintln!("{}", violation.red());
+    }
+    if !violations.is_empty() {
+        anyhow::bail!("{} is invalid", cmd.file.display());
+    }
+    Ok(())
+}
+
 fn main0() -> anyhow::Result<()> {
     let cli = cli::MoonBuildDashBoardCli::parse();
+    if let cli::MoonBuildDashBoardSubcommands::Stat(cmd) = &cli.subcommand {
+        if let Some(proxy) = &cmd.proxy {
+            set_proxy(proxy.clone());
+        }
+        set_allow_stale_registry(cmd.allow_stale_registry);
+        if let Some(script) = &cmd.mock_moon {
+            set_mock_moon(script.clone());
+        }
+        set_tmp_prefix(cmd.tmp_prefix.clone());
+        if let Some(hours) = cmd.sweep_stale {
+            let max_age = std::time::Duration::from_secs(hours * 3600);
+            let roots = std::iter::once(std::env::temp_dir()).chain(cmd.build_root.clone());
+            for root in roots {
+                let removed = sweep_stale(
+                    &root,
+                    &cmd.tmp_prefix,
+                    max_age,
+                    std::time::SystemTime::now(),
+                )
+                .with_context(|| format!("failed to sweep {}", root.display()))?;
+                if !removed.is_empty() {
+                    eprintln!(
+                        "removed {} stale temp dirs under {}",
+                        removed.len(),
+                        root.display()
+                    );
+                }
+            }
+        }
+    }
+    let (
+        max_output_file_bytes,
+        compress_logs,
+        grid,
+        group_by,
+        retain_days,
+        latest_name,
+        processors,
+        commit_status_digest,
+        journal,
+        output_format,
+        output_path,
+        markdown_out,
+        html_out,
+        thresholds,
+    ) = match &cli.subcommand {
+        cli::MoonBuildDashBoardSubcommands::Stat(cmd) => (
+            cmd.max_output_file_bytes,
+            cmd.compress_logs,
+            cmd.grid,
+            cmd.group_by,
+            cmd.retain_days,
+            cmd.latest_name.clone(),
+            cmd.processors.clone(),
+            cmd.commit_status_digest,
+            cmd.journal_path(),
+            cmd.output_format,
+            cmd.output_path.clone(),
+            cmd.markdown_out.clone(),
+            cmd.html_out.clone(),
+            Thresholds {
+                max_failure_rate: cmd.max_failure_rate,
+                max_regressions: cmd.max_regressions,
+                fail_on_error: cmd.fail_on_error,
+            },
+        ),
+        _ => (
+            None,
+            false,
+            false,
+            None,
+            None,
+            String::new(),
+            vec![],
+            None,
+            None,
+            cli::OutputFormat::JsonlGz,
+            None,
+            None,
+            None,
+            Thresholds::default(),
+        ),
+    };
     let res = match cli.subcommand {
-        cli::MoonBuildDashBoardSubcommands::Stat(cmd) => stat(cmd),
+        cli::MoonBuildDashBoardSubcommands::Stat(cmd) if cmd.print_work_items => {
+            return print_work_items(&cmd)
+        }
+        cli::MoonBuildDashBoardSubcommands::Stat(cmd) if cmd.print_matrix_cost => {
+            return print_matrix_cost(&cmd)
+        }
+        cli::MoonBuildDashBoardSubcommands::Stat(cmd) if cmd.watch => return watch(cmd),
+        cli::MoonBuildDashBoardSubcommands::Stat(cmd) => {
+            let dir = match &cmd.output_path {
+                Some(path) => match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                    _ => PathBuf::from("."),
+                },
+                None => PathBuf::from(format!("webapp/public/{}", current_os())),
+            };
+            output::ensure_writable(&dir).with_context(|| {
+                format!(
+                    "{} is not writable, the results could not be saved",
+                    dir.display()
+                )
+            })?;
+            stat(cmd)
+        }
+        cli::MoonBuildDashBoardSubcommands::Preflight(cmd) => return preflight(cmd),
+        cli::MoonBuildDashBoardSubcommands::Merge(cmd) => return merge(cmd),
+        cli::MoonBuildDashBoardSubcommands::Render(cmd) => return render(cmd),
+        cli::MoonBuildDashBoardSubcommands::ValidateOutput(cmd) => return validate_output(cmd),
+        cli::MoonBuildDashBoardSubcommands::Doctor(cmd) => return doctor(cmd),
+        cli::MoonBuildDashBoardSubcommands::Bisect(cmd) => return bisect(cmd),
+        cli::MoonBuildDashBoardSubcommands::Diff(cmd) => return diff(cmd),
+        cli::MoonBuildDashBoardSubcommands::Stats(cmd) => return stats(cmd),
     };
-    #[cfg(target_os = "windows")]
-    let os = "windows";
-    #[cfg(target_os = "linux")]
-    let os = "linux";
-    #[cfg(target_os = "macos")]
-    let os = "mac";
+    let os = current_os();
     match res {
-        Ok(dashboard) => {
-            let date = Local::now().format("%Y-%m-%d");
+        Ok(mut dashboard) => {
+            let today = Local::now().date_naive();
+            let date = today.format("%Y-%m-%d");
             let filename = format!("webapp/public/{}/{}_data.jsonl.gz", os, date);
+            let latest_filename = format!("webapp/public/{}/{}", os, latest_name);
+
+            let prior = MoonBuildDashboard::from_file(Path::new(&latest_filename)).ok();
+            dashboard.compare_bleeding_with(prior.as_ref());
+            let single_phase = dashboard.bleeding_toolchain_version.label == ToolChainLabel::Local;
+            if !single_phase && !dashboard.toolchains_differ() {
+                eprintln!("stable and bleeding are the same toolchain");
+            }
+            if dashboard.bleeding_changed_since_latest == Some(false) {
+                eprintln!("{}", "bleeding unchanged since the latest run".yellow());
+            }
+            for processor in processors.iter() {
+                dashboard = output::run_processor(&dashboard, processor)?;
+            }
+            if let Some(prior) = &prior {
+                let churn = dashboard.source_churn(prior);
+                for name in churn.added.iter() {
+                    eprintln!("new source since the latest run: {}", name.green());
+                }
+                for name in churn.removed.iter() {
+                    eprintln!("source gone since the latest run: {}", name.yellow());
+                }
+            }
 
-            let fp = std::fs::OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true)
-                .open(&filename)?;
-            let encoder = GzEncoder::new(fp, Compression::default());
-            let mut writer = std::io::BufWriter::new(encoder);
-            writeln!(writer, "{}", serde_json::to_string(&dashboard)?)?;
-            writer.flush()?;
-            writer.into_inner()?.finish()?;
+            // a no-op outside of GitHub Actions
+            if let Err(e) = report::write_step_summary(&dashboard) {
+                eprintln!("failed to write the step summary: {}", e);
+            }
+            if compress_logs {
+                output::compress_logs(&mut dashboard)?;
+            }
+            let bytes =
+                output::encode_to_fit(&mut dashboard, output_format, max_output_file_bytes)?;
+            let data_path = output_path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(&filename));
+            output::write_output(&data_path, &bytes)?;
+            eprintln!("{:?}", dashboard.summary());
+            let errors = dashboard.source_errors();
+            if !errors.is_empty() {
+                eprintln!("{} source errors:", errors.len());
+                for e in errors.iter() {
+                    eprintln!(
+                        "{}",
+                        format!("  {} {}: {}", e.toolchain, e.source, e.reason).red()
+                    );
+                }
+            }
+            if grid {
+                print!("{}", report::render_grid(&dashboard, true));
+            }
+            if let Some(group_by) = group_by {
+                print!("{}", report::render_groups(&dashboard, group_by));
+            }
+            let reports = [
+                (
+                    &markdown_out,
+                    report::render_markdown as fn(&MoonBuildDashboard) -> String,
+                ),
+                (&html_out, report::render_html),
+            ];
+            for (path, render) in reports {
+                if let Some(path) = path {
+                    std::fs::write(path, render(&dashboard))
+                        .with_context(|| format!("failed to write {}", path.display()))?;
+                }
+            }
+            if let Some(max_chars) = commit_status_digest {
+                println!("{}", dashboard.regression_digest(max_chars));
+            }
+
+            if output_path.is_none() {
+                output::update_latest(Path::new(&filename), Path::new(&latest_filename))
+                    .with_context(|| format!("failed to update {}", latest_filename))?;
+            }
+            // everything the journal held is saved now
+            if let Some(journal) = &journal {
+                if let Err(e) = std::fs::remove_file(journal) {
+                    eprintln!("failed to remove {}: {}", journal.display(), e);
+                }
+            }
 
-            let latest_filename = format!("webapp/public/{}/latest_data.jsonl.gz", os);
-            std::fs::copy(&filename, latest_filename)?;
+            if let (Some(days), None) = (retain_days, &output_path) {
+                let dir = format!("webapp/public/{}", os);
+                for removed in output::remove_old_data_files(Path::new(&dir), today, days)? {
+                    eprintln!("removed {}", removed.display());
+                }
+            }
 
+            let exceeded = thresholds.check(&dashboard);
+            for e in exceeded.iter() {
+                eprintln!("{}", e.red());
+            }
+            if !exceeded.is_empty() {
+                anyhow::bail!("failure thresholds exceeded");
+            }
             Ok(())
         }
         Err(e) => Err(anyhow::anyhow!(e)),
@@ -0,0 +1,172 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dashboard::{DashboardReadError, MoonBuildDashboard, MooncakeSource};
+
+/// Per-OS dashboards combined into one file, keyed by OS.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MergedDashboard {
+    pub dashboards: BTreeMap<String, MoonBuildDashboard>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts: Vec<MergeConflict>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeConflict {
+    /// the dashboard of `os` comes from another run than the first one
+    RunId {
+        os: String,
+        expected: String,
+        found: String,
+    },
+    /// the source at `index` is not the same package as in the first dashboard
+    Source {
+        os: String,
+        index: usize,
+        expected: String,
+        found: String,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MergeError {
+    #[error("failed to read {0}")]
+    Read(PathBuf, #[source] DashboardReadError),
+    #[error("{} conflict(s) between dashboards: {:?}", .0.len(), .0)]
+    Conflicts(Vec<MergeConflict>),
+}
+
+// windows names use `\` as separator
+fn source_identity(source: &MooncakeSource) -> String {
+    match source {
+        MooncakeSource::MooncakesIO { name, .. } => {
+            format!("mooncakes.io:{}", name.replace('\\', "/"))
+        }
+        MooncakeSource::Git { url, .. } => format!("git:{}", url),
+    }
+}
+
+fn find_conflicts(dashboards: &BTreeMap<String, MoonBuildDashboard>) -> Vec<MergeConflict> {
+    let mut conflicts = vec![];
+    let mut iter = dashboards.iter();
+    let Some((_, first)) = iter.next() else {
+        return conflicts;
+    };
+    let expected: BTreeMap<usize, String> = first
+        .sources
+        .iter()
+        .map(|s| (s.get_index(), source_identity(s)))
+        .collect();
+    for (os, dashboard) in iter {
+        if dashboard.run_id != first.run_id {
+            conflicts.push(MergeConflict::RunId {
+                os: os.clone(),
+                expected: first.run_id.clone(),
+                found: dashboard.run_id.clone(),
+            });
+        }
+        for source in dashboard.sources.iter() {
+            let found = source_identity(source);
+            match expected.get(&source.get_index()) {
+                Some(e) if *e != found => conflicts.push(MergeConflict::Source {
+                    os: os.clone(),
+                    index: source.get_index(),
+                    expected: e.clone(),
+                    found,
+                }),
+                _ => {}
+            }
+        }
+    }
+    conflicts
+}
+
+/// Merges per-OS dashboards. Conflicts are recorded in the result, or
+/// returned as an error with `strict`.
+pub fn merge(
+    dashboards: BTreeMap<String, MoonBuildDashboard>,
+    strict: bool,
+) -> Result<MergedDashboard, MergeError> {
+    let conflicts = find_conflicts(&dashboards);
+    for conflict in conflicts.iter() {
+        eprintln!("merge conflict: {:?}", conflict);
+    }
+    if strict && !conflicts.is_empty() {
+        return Err(MergeError::Conflicts(conflicts));
+    }
+    Ok(MergedDashboard {
+        dashboards,
+        conflicts,
+    })
+}
+
+/// Reads the per-OS dashboard files in parallel and merges them.
+pub fn merge_files(
+    inputs: &[(String, PathBuf)],
+    strict: bool,
+) -> Result<MergedDashboard, MergeError> {
+    let dashboards = std::thread::scope(|scope| {
+        let handles: Vec<_> = inputs
+            .iter()
+            .map(|(os, path)| {
+                scope.spawn(move || {
+                    MoonBuildDashboard::from_file(path)
+                        .map(|d| (os.clone(), d))
+                        .map_err(|e| MergeError::Read(path.clone(), e))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Result<BTreeMap<_, _>, _>>()
+    })?;
+    merge(dashboards, strict)
+}
+
+#[test]
+fn merge_conflicting_dashboards() {
+    let dashboard = |run_id: &str, source: &str| {
+        MoonBuildDashboard::from_json(&format!(
+            r#"{{
+                "run_id": "{}",
+                "run_number": "1",
+                "start_time": "",
+                "sources": [{{"Git": {{"url": "{}", "rev": ["main"], "index": 0}}}}],
+                "stable_toolchain_version": {{"label": "Stable", "moon_version": "", "moonc_version": ""}},
+                "stable_release_data": [],
+                "bleeding_toolchain_version": {{"label": "Bleeding", "moon_version": "", "moonc_version": ""}},
+                "bleeding_release_data": []
+            }}"#,
+            run_id, source
+        ))
+        .unwrap()
+    };
+    let core = "https://github.com/moonbitlang/core";
+
+    let agreeing = BTreeMap::from([
+        ("linux".to_string(), dashboard("1", core)),
+        ("mac".to_string(), dashboard("1", core)),
+    ]);
+    assert!(merge(agreeing, true).unwrap().conflicts.is_empty());
+
+    let conflicting = || {
+        BTreeMap::from([
+            ("linux".to_string(), dashboard("1", core)),
+            ("mac".to_string(), dashboard("2", core)),
+            (
+                "windows".to_string(),
+                dashboard("1", "https://github.com/moonbitlang/x"),
+            ),
+        ])
+    };
+    let merged = merge(conflicting(), false).unwrap();
+    assert_eq!(merged.conflicts.len(), 2);
+    assert!(matches!(&merged.conflicts[0], MergeConflict::RunId { os, .. } if os == "mac"));
+    assert!(matches!(&merged.conflicts[1], MergeConflict::Source { os, .. } if os == "windows"));
+    assert!(matches!(
+        merge(conflicting(), true),
+        Err(MergeError::Conflicts(c)) if c.len() == 2
+    ));
+}
@@ -1,10 +1,15 @@
 use std::{
     collections::BTreeMap,
+    ffi::OsString,
+    io::Read,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::util::{command_with_proxy, latest_version, write_atomic};
+
 const BASE_URL: &str = "https://moonbitlang-mooncakes.s3.us-west-2.amazonaws.com/user";
 
 #[derive(Debug, thiserror::Error)]
@@ -17,36 +22,103 @@ pub enum MooncakesIOError {
     FromUtf8(#[from] std::string::FromUtf8Error),
     #[error("serde")]
     Serde(#[from] serde_json::Error),
+    #[error("malformed index line {line} of {file}")]
+    MalformedIndex {
+        file: PathBuf,
+        line: usize,
+        #[source]
+        error: serde_json::Error,
+    },
     #[error("walkdir")]
     WalkDir(#[from] walkdir::Error),
+    #[error("{version} was not downloaded")]
+    NotDownloaded { version: String },
+    #[error("failed to get home directory")]
+    HomeNotFound,
+    #[error("duplicate index entry: {name}")]
+    DuplicateIndex { name: String },
+    #[error("not authorized to download {version} (HTTP {status}), check MOONCAKES_TOKEN")]
+    Unauthorized { version: String, status: u16 },
+    #[error("{version} is not in the registry (HTTP {status})")]
+    NotFound { version: String, status: u16 },
+    #[error("checksum mismatch, expected sha256 {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+/// The bearer token for private mooncakes, from `MOONCAKES_TOKEN`.
+pub fn auth_token(private: bool) -> Option<String> {
+    if !private {
+        return None;
+    }
+    std::env::var("MOONCAKES_TOKEN")
+        .ok()
+        .filter(|t| !t.is_empty())
 }
 
-pub fn download_to(name: &str, version: &str, dst: &Path) -> Result<(), MooncakesIOError> {
+fn zip_url(name: &str, version: &str) -> String {
     let version_enc = form_urlencoded::Serializer::new(String::new())
         .append_key_only(version)
         .finish();
-    let url = format!("{}/{}/{}.zip", BASE_URL, name, version_enc);
-    let output_zip = format!("{}.zip", dst.join(version).display());
+    format!("{}/{}/{}.zip", BASE_URL, name, version_enc)
+}
+
+fn zip_path(dst: &Path, version: &str) -> String {
+    format!("{}.zip", dst.join(safe_dir_name(version)).display())
+}
+
+/// Longest name returned by [`safe_dir_name`], well below the 255 bytes most
+/// filesystems allow, leaving room for the `.zip` suffix.
+const MAX_DIR_NAME: usize = 128;
+
+/// The directory a version is extracted to under the download dir. Anything
+/// but ASCII letters, digits, `.` and `-` is escaped as `_XX` in hex, so
+/// distinct versions never share a directory, and overly long names are cut
+/// and suffixed with a hash of the version.
+pub fn safe_dir_name(version: &str) -> String {
+    let mut name = String::with_capacity(version.len());
+    for (i, b) in version.bytes().enumerate() {
+        // a leading `.` would allow `.` and `..`
+        if b.is_ascii_alphanumeric() || b == b'-' || (b == b'.' && i > 0) {
+            name.push(b as char);
+        } else {
+            name.push_str(&format!("_{:02X}", b));
+        }
+    }
+    if name.len() > MAX_DIR_NAME {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        version.hash(&mut hasher);
+        name.truncate(MAX_DIR_NAME - 17);
+        name.push_str(&format!("_{:016x}", hasher.finish()));
+    }
+    name
+}
 
+pub fn download_to(
+    name: &str,
+    version: &str,
+    dst: &Path,
+    token: Option<&str>,
+) -> Result<(), MooncakesIOError> {
     #[cfg(target_os = "windows")]
     {
-        let output = std::process::Command::new("powershell")
-            .args([
-                "-Command",
-                &format!("Invoke-WebRequest -Uri '{}' -OutFile '{}'", url, output_zip),
-            ])
-            .output()
-            .map_err(|e| MooncakesIOError::IOError(e))?;
-        if !output.status.success() {
-            return Err(MooncakesIOError::ReturnNonZero(output.status));
+        let url = zip_url(name, version);
+        let output_zip = zip_path(dst, version);
+        // the token is passed through the environment to keep it out of the command line
+        let headers = match token {
+            Some(_) => " -Headers @{ Authorization = \"Bearer $env:MOONCAKES_TOKEN\" }",
+            None => "",
+        };
+        let mut cmd = command_with_proxy("powershell");
+        if let Some(token) = token {
+            cmd.env("MOONCAKES_TOKEN", token);
         }
-
-        let output = std::process::Command::new("powershell")
+        let output = cmd
             .args([
-                "-Command", 
-                &format!("Expand-Archive -Path '{}' -DestinationPath '{}'", 
-                    output_zip,
-                    dst.join(version).display()
+                "-Command",
+                &format!(
+                    "Invoke-WebRequest -Uri '{}' -OutFile '{}'{}",
+                    url, output_zip, headers
                 ),
             ])
             .output()
@@ -54,53 +126,655 @@ pub fn download_to(name: &str, version: &str, dst: &Path) -> Result<(), Mooncake
         if !output.status.success() {
             return Err(MooncakesIOError::ReturnNonZero(output.status));
         }
+
+        verify_checksum(
+            Path::new(&output_zip),
+            expected_checksum(name, version).as_deref(),
+        )?;
+        extract_archive(Path::new(&output_zip), &dst.join(safe_dir_name(version)))
     }
 
     #[cfg(unix)]
-    {
-        let output = std::process::Command::new("curl")
-            .arg("-o")
-            .arg(&output_zip)
-            .arg(&url)
-            .output()
-            .map_err(|e| MooncakesIOError::IOError(e))?;
-        if !output.status.success() {
-            return Err(MooncakesIOError::ReturnNonZero(output.status));
+    download_versions_to(name, &[version.to_string()], dst, token)
+        .pop()
+        .unwrap_or(Ok(()))
+}
+
+/// The sha256 of `version` of `name` recorded in the local registry index.
+fn expected_checksum(name: &str, version: &str) -> Option<String> {
+    let (user, pkg) = name.split_once('/')?;
+    index_checksum(&index_of_pkg(&index().ok()?, user, pkg), version)
+}
+
+/// The `checksum` of the line of `version` in the index file `path`, if any.
+pub fn index_checksum(path: &Path, version: &str) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<MooncakeInfo>(line).ok())
+        .find(|info| info.version == version)?
+        .checksum
+}
+
+/// Compares the sha256 of the downloaded `path` against `expected`, so a
+/// truncated download is caught before extraction. Without an expected digest
+/// it only warns.
+pub fn verify_checksum(path: &Path, expected: Option<&str>) -> Result<(), MooncakesIOError> {
+    use sha2::{Digest, Sha256};
+
+    let Some(expected) = expected else {
+        eprintln!("no checksum for {}, skipping verification", path.display());
+        return Ok(());
+    };
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    let actual: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(MooncakesIOError::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+#[test]
+fn checksum_of_downloaded_zip() {
+    let tmp = tempfile::tempdir().unwrap();
+    // an empty zip, just the end of central directory record
+    let mut empty_zip = b"PK\x05\x06".to_vec();
+    empty_zip.resize(22, 0);
+    let zip = tmp.path().join("0.1.0.zip");
+    std::fs::write(&zip, &empty_zip).unwrap();
+    let digest = "8739c76e681f900923b900c9df0ef75cf421d39cabb54650c4b9ad19b6a76d85";
+
+    let index = tmp.path().join("pkg.index");
+    std::fs::write(
+        &index,
+        format!(
+            "{}\n{{\"version\":\"0.1.0\",\"checksum\":\"{}\"}}\n",
+            r#"{"version":"0.0.1"}"#, digest
+        ),
+    )
+    .unwrap();
+    assert_eq!(index_checksum(&index, "0.1.0").as_deref(), Some(digest));
+    assert_eq!(index_checksum(&index, "0.0.1"), None);
+
+    assert!(verify_checksum(&zip, Some(digest)).is_ok());
+    assert!(verify_checksum(&zip, Some(&digest.to_uppercase())).is_ok());
+    // no digest to compare against, only a warning
+    assert!(verify_checksum(&zip, None).is_ok());
+
+    std::fs::write(&zip, &empty_zip[..10]).unwrap();
+    match verify_checksum(&zip, Some(digest)) {
+        Err(MooncakesIOError::ChecksumMismatch { expected, actual }) => {
+            assert_eq!(expected, digest);
+            assert_ne!(actual, digest);
         }
+        other => panic!("{:?}", other),
+    }
+}
 
-        let output = std::process::Command::new("unzip")
-            .arg(&output_zip)
-            .arg("-d")
-            .arg(dst.join(version))
-            .output()
-            .map_err(|e| MooncakesIOError::IOError(e))?;
-        if !output.status.success() {
-            return Err(MooncakesIOError::ReturnNonZero(output.status));
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+/// Sniffs the format from the gzip magic bytes, so a `.tar.gz` served under a
+/// `.zip` name is still recognized. Anything else is treated as zip.
+pub fn archive_format(path: &Path) -> std::io::Result<ArchiveFormat> {
+    let mut magic = [0u8; 2];
+    let n = std::fs::File::open(path)?.read(&mut magic)?;
+    if n == 2 && magic == [0x1f, 0x8b] {
+        Ok(ArchiveFormat::TarGz)
+    } else {
+        Ok(ArchiveFormat::Zip)
+    }
+}
+
+/// Extracts a downloaded mooncake archive into `dst`.
+pub fn extract_archive(path: &Path, dst: &Path) -> Result<(), MooncakesIOError> {
+    match archive_format(path)? {
+        ArchiveFormat::TarGz => {
+            let file = std::fs::File::open(path)?;
+            tar::Archive::new(flate2::read::GzDecoder::new(file)).unpack(dst)?;
+            Ok(())
         }
+        ArchiveFormat::Zip => unzip(path, dst),
     }
+}
 
+#[cfg(unix)]
+fn unzip(zip: &Path, dst: &Path) -> Result<(), MooncakesIOError> {
+    let output = std::process::Command::new("unzip")
+        .arg(zip)
+        .arg("-d")
+        .arg(dst)
+        .output()
+        .map_err(MooncakesIOError::IOError)?;
+    if !output.status.success() {
+        return Err(MooncakesIOError::ReturnNonZero(output.status));
+    }
     Ok(())
 }
 
-pub fn home() -> PathBuf {
-    if let Ok(moon_home) = std::env::var("MOON_HOME") {
-        return PathBuf::from(moon_home);
+#[cfg(target_os = "windows")]
+fn unzip(zip: &Path, dst: &Path) -> Result<(), MooncakesIOError> {
+    let output = std::process::Command::new("powershell")
+        .args([
+            "-Command",
+            &format!(
+                "Expand-Archive -Path '{}' -DestinationPath '{}'",
+                zip.display(),
+                dst.display()
+            ),
+        ])
+        .output()
+        .map_err(MooncakesIOError::IOError)?;
+    if !output.status.success() {
+        return Err(MooncakesIOError::ReturnNonZero(output.status));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn extract_zip_and_tar_gz() {
+    let tmp = tempfile::tempdir().unwrap();
+    let src = tmp.path().join("src");
+    std::fs::create_dir_all(&src).unwrap();
+    std::fs::write(src.join("moon.mod.json"), "{}").unwrap();
+
+    let zip = tmp.path().join("a.zip");
+    let status = std::process::Command::new("zip")
+        .current_dir(&src)
+        .arg("-q")
+        .arg(&zip)
+        .arg("moon.mod.json")
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(archive_format(&zip).unwrap(), ArchiveFormat::Zip);
+    extract_archive(&zip, &tmp.path().join("from_zip")).unwrap();
+    assert!(tmp.path().join("from_zip/moon.mod.json").is_file());
+
+    // served under a .zip name, detected by content
+    let tar_gz = tmp.path().join("b.zip");
+    let encoder = flate2::write::GzEncoder::new(
+        std::fs::File::create(&tar_gz).unwrap(),
+        flate2::Compression::default(),
+    );
+    let mut builder = tar::Builder::new(encoder);
+    builder
+        .append_path_with_name(src.join("moon.mod.json"), "moon.mod.json")
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    assert_eq!(archive_format(&tar_gz).unwrap(), ArchiveFormat::TarGz);
+    extract_archive(&tar_gz, &tmp.path().join("from_tar_gz")).unwrap();
+    assert!(tmp.path().join("from_tar_gz/moon.mod.json").is_file());
+}
+
+/// One curl process downloading every version, so the connection to the
+/// registry is reused. A failed transfer doesn't stop the others. The HTTP
+/// status of each transfer is printed on its own line. With `auth`, headers
+/// are read from stdin, see `auth_header`.
+#[cfg(unix)]
+fn batch_download_command(
+    name: &str,
+    versions: &[String],
+    dst: &Path,
+    auth: bool,
+) -> std::process::Command {
+    let mut cmd = command_with_proxy("curl");
+    cmd.args(["--fail", "--silent", "--write-out", "%{http_code}\\n"]);
+    if auth {
+        cmd.args(["--header", "@-"]);
+    }
+    for version in versions {
+        cmd.arg("-o")
+            .arg(zip_path(dst, version))
+            .arg(zip_url(name, version));
+    }
+    cmd
+}
+
+/// Written to the stdin of curl, so the token is not visible in the process list.
+#[cfg(unix)]
+fn auth_header(token: &str) -> String {
+    format!("Authorization: Bearer {}\n", token)
+}
+
+#[cfg(unix)]
+fn run_curl(
+    mut cmd: std::process::Command,
+    stdin: Option<String>,
+) -> std::io::Result<std::process::Output> {
+    use std::io::Write;
+    let Some(stdin) = stdin else {
+        return cmd.output();
+    };
+    let mut child = cmd
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    if let Some(mut pipe) = child.stdin.take() {
+        pipe.write_all(stdin.as_bytes())?;
+    }
+    child.wait_with_output()
+}
+
+#[cfg(unix)]
+fn http_statuses(stdout: &[u8]) -> Vec<u16> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|l| l.trim().parse().ok())
+        .collect()
+}
+
+#[cfg(unix)]
+fn check_http_status(version: &str, status: Option<u16>) -> Result<(), MooncakesIOError> {
+    match status {
+        Some(status @ (401 | 403)) => Err(MooncakesIOError::Unauthorized {
+            version: version.to_string(),
+            status,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `version` of `name` can be downloaded, with a HEAD request so
+/// nothing is downloaded. `token` is sent as for `download_versions_to`.
+pub fn check_version_exists(
+    name: &str,
+    version: &str,
+    token: Option<&str>,
+) -> Result<(), MooncakesIOError> {
+    #[cfg(unix)]
+    let status = {
+        let mut cmd = command_with_proxy("curl");
+        cmd.args(["--head", "--silent", "--output", "/dev/null"])
+            .args(["--write-out", "%{http_code}\\n"]);
+        if token.is_some() {
+            cmd.args(["--header", "@-"]);
+        }
+        cmd.arg(zip_url(name, version));
+        let output = run_curl(cmd, token.map(auth_header))?;
+        http_statuses(&output.stdout).first().copied()
+    };
+
+    #[cfg(target_os = "windows")]
+    let status = {
+        let headers = match token {
+            Some(_) => " -Headers @{ Authorization = \"Bearer $env:MOONCAKES_TOKEN\" }",
+            None => "",
+        };
+        let mut cmd = command_with_proxy("powershell");
+        if let Some(token) = token {
+            cmd.env("MOONCAKES_TOKEN", token);
+        }
+        let output = cmd
+            .args([
+                "-Command",
+                &format!(
+                    "try {{ (Invoke-WebRequest -Method Head -Uri '{}'{}).StatusCode }} \
+                     catch {{ [int]$_.Exception.Response.StatusCode }}",
+                    zip_url(name, version),
+                    headers
+                ),
+            ])
+            .output()?;
+        String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+    };
+
+    match status {
+        Some(200..=299) => Ok(()),
+        Some(status @ (401 | 403)) if token.is_some() => Err(MooncakesIOError::Unauthorized {
+            version: version.to_string(),
+            status,
+        }),
+        // S3 answers 403 rather than 404 for a missing object of a public bucket
+        Some(status) if status != 0 => Err(MooncakesIOError::NotFound {
+            version: version.to_string(),
+            status,
+        }),
+        _ => Err(MooncakesIOError::IOError(std::io::Error::other(format!(
+            "no response for {}/{}",
+            name, version
+        )))),
+    }
+}
+
+/// Downloads and extracts several versions of a package into `dst/<version>`,
+/// returning the result of each version in order. `token` is sent as a bearer
+/// token and never logged.
+pub fn download_versions_to(
+    name: &str,
+    versions: &[String],
+    dst: &Path,
+    token: Option<&str>,
+) -> Vec<Result<(), MooncakesIOError>> {
+    #[cfg(unix)]
+    {
+        let cmd = batch_download_command(name, versions, dst, token.is_some());
+        let output = match run_curl(cmd, token.map(auth_header)) {
+            Ok(output) => output,
+            Err(e) => {
+                return versions
+                    .iter()
+                    .map(|_| {
+                        Err(MooncakesIOError::IOError(std::io::Error::new(
+                            e.kind(),
+                            e.to_string(),
+                        )))
+                    })
+                    .collect();
+            }
+        };
+        let statuses = http_statuses(&output.stdout);
+        versions
+            .iter()
+            .enumerate()
+            .map(|(i, version)| {
+                check_http_status(version, statuses.get(i).copied())?;
+                let zip = zip_path(dst, version);
+                if !Path::new(&zip).is_file() {
+                    return Err(MooncakesIOError::NotDownloaded {
+                        version: version.clone(),
+                    });
+                }
+                verify_checksum(Path::new(&zip), expected_checksum(name, version).as_deref())?;
+                extract_archive(Path::new(&zip), &dst.join(safe_dir_name(version)))
+            })
+            .collect()
+    }
+
+    #[cfg(target_os = "windows")]
+    versions
+        .iter()
+        .map(|version| download_to(name, version, dst, token))
+        .collect()
+}
+
+/// Downloads `versions` like `download_versions_to`, except for those whose
+/// archive is in `cache`, which are copied from there. Downloaded archives are
+/// added to `cache`, archives are immutable once published. Private packages,
+/// with a `token`, are never cached. Also returns whether any version was
+/// downloaded.
+pub fn fetch_versions_to(
+    name: &str,
+    versions: &[String],
+    dst: &Path,
+    token: Option<&str>,
+    cache: Option<&Path>,
+) -> (Vec<Result<(), MooncakesIOError>>, bool) {
+    let cache = cache.filter(|_| token.is_none()).map(|dir| dir.join(name));
+    let cached: Vec<Option<PathBuf>> = versions
+        .iter()
+        .map(|version| {
+            let zip = PathBuf::from(zip_path(cache.as_deref()?, version));
+            zip.is_file().then_some(zip)
+        })
+        .collect();
+    let missing: Vec<String> = versions
+        .iter()
+        .zip(cached.iter())
+        .filter(|(_, zip)| zip.is_none())
+        .map(|(version, _)| version.clone())
+        .collect();
+    let mut downloaded = match missing.is_empty() {
+        true => vec![],
+        false => download_versions_to(name, &missing, dst, token),
     }
+    .into_iter();
+    let results = versions
+        .iter()
+        .zip(cached)
+        .map(|(version, zip)| match zip {
+            Some(zip) => {
+                let dst_zip = zip_path(dst, version);
+                std::fs::copy(&zip, &dst_zip)?;
+                verify_checksum(
+                    Path::new(&dst_zip),
+                    expected_checksum(name, version).as_deref(),
+                )?;
+                extract_archive(Path::new(&dst_zip), &dst.join(safe_dir_name(version)))
+            }
+            None => {
+                let result = downloaded.next().expect("a result per missing version");
+                if let (Ok(()), Some(cache)) = (&result, &cache) {
+                    if let Err(e) =
+                        cache_archive(Path::new(&zip_path(dst, version)), cache, version)
+                    {
+                        eprintln!("failed to cache {}/{}: {}", name, version, e);
+                    }
+                }
+                result
+            }
+        })
+        .collect();
+    (results, !missing.is_empty())
+}
+
+fn cache_archive(zip: &Path, cache: &Path, version: &str) -> std::io::Result<()> {
+    std::fs::create_dir_all(cache)?;
+    crate::util::write_atomic_with(Path::new(&zip_path(cache, version)), |file| {
+        std::io::copy(&mut std::fs::File::open(zip)?, file).map(|_| ())
+    })
+}
+
+#[test]
+fn cached_archives_are_not_downloaded() {
+    let tmp = tempfile::tempdir().unwrap();
+    let cache = tmp.path().join("cache");
+    let pkg = cache.join("alice").join("pkg");
+    std::fs::create_dir_all(&pkg).unwrap();
+    // a `.tar.gz` served under a `.zip` name, extracted without `unzip`
+    let mut archive = tar::Builder::new(flate2::write::GzEncoder::new(
+        vec![],
+        flate2::Compression::default(),
+    ));
+    let mut header = tar::Header::new_gnu();
+    header.set_size(2);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, "moon.mod.json", &b"{}"[..])
+        .unwrap();
+    let archive = archive.into_inner().unwrap().finish().unwrap();
+    std::fs::write(zip_path(&pkg, "0.1.0"), archive).unwrap();
+
+    let dst = tmp.path().join("dst");
+    std::fs::create_dir(&dst).unwrap();
+    let versions = vec!["0.1.0".to_string()];
+    let (results, network_used) =
+        fetch_versions_to("alice/pkg", &versions, &dst, None, Some(&cache));
+    assert!(results.iter().all(|r| r.is_ok()));
+    assert!(!network_used);
+    assert_eq!(
+        std::fs::read_to_string(dst.join("0.1.0").join("moon.mod.json")).unwrap(),
+        "{}"
+    );
+}
 
-    let h = home::home_dir();
-    if h.is_none() {
-        eprintln!("Failed to get home directory");
-        std::process::exit(1);
+#[cfg(unix)]
+#[test]
+fn batch_download_reuses_one_process() {
+    let versions = vec!["0.1.0".to_string(), "0.2.0+1".to_string()];
+    let cmd = batch_download_command("alice/pkg", &versions, Path::new("/tmp/x"), false);
+    assert_eq!(cmd.get_program(), "curl");
+    let args: Vec<String> = cmd
+        .get_args()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(
+        args,
+        [
+            "--fail",
+            "--silent",
+            "--write-out",
+            "%{http_code}\\n",
+            "-o",
+            "/tmp/x/0.1.0.zip",
+            &format!("{}/alice/pkg/0.1.0.zip", BASE_URL),
+            "-o",
+            "/tmp/x/0.2.0_2B1.zip",
+            &format!("{}/alice/pkg/0.2.0%2B1.zip", BASE_URL),
+        ]
+    );
+}
+
+#[test]
+fn safe_dir_names() {
+    assert_eq!(safe_dir_name("0.1.0"), "0.1.0");
+    assert_eq!(safe_dir_name("1.0.0-rc.1+build.5"), "1.0.0-rc.1_2Bbuild.5");
+    assert_eq!(safe_dir_name("1.0/../x"), "1.0_2F.._2Fx");
+    assert_eq!(safe_dir_name("1.0 beta"), "1.0_20beta");
+    assert_eq!(safe_dir_name(".."), "_2E.");
+    // escaping keeps distinct versions apart
+    assert_ne!(safe_dir_name("1.0+a"), safe_dir_name("1.0_2Ba"));
+
+    let long = format!("1.0.0+{}", "x".repeat(300));
+    let name = safe_dir_name(&long);
+    assert_eq!(name.len(), MAX_DIR_NAME);
+    assert_ne!(name, safe_dir_name(&format!("{}y", long)));
+    assert_eq!(name, safe_dir_name(&long));
+
+    let tmp = tempfile::tempdir().unwrap();
+    for version in ["1.0+build", "1.0/x", "1.0 beta", long.as_str()] {
+        let dir = tmp.path().join(safe_dir_name(version));
+        std::fs::create_dir(&dir).unwrap();
+        assert_eq!(dir.parent(), Some(tmp.path()));
     }
-    let hm = h.unwrap().join(".moon");
+}
+
+#[cfg(unix)]
+#[test]
+fn authenticated_download() {
+    let versions = vec!["0.1.0".to_string()];
+    let cmd = batch_download_command("alice/pkg", &versions, Path::new("/tmp/x"), true);
+    let args: Vec<String> = cmd
+        .get_args()
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    assert!(args.windows(2).any(|w| w == ["--header", "@-"]));
+    assert!(!args.iter().any(|a| a.contains("secret")));
+    assert_eq!(auth_header("secret"), "Authorization: Bearer secret\n");
+
+    // a registry answering 401, recording the request it got
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!(
+        "http://{}/alice/pkg/0.1.0.zip",
+        listener.local_addr().unwrap()
+    );
+    let server = std::thread::spawn(move || {
+        use std::io::{BufRead, Write};
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![];
+        for line in std::io::BufReader::new(stream.try_clone().unwrap()).lines() {
+            let line = line.unwrap();
+            if line.is_empty() {
+                break;
+            }
+            request.push(line);
+        }
+        stream
+            .write_all(b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        request
+    });
+    let mut cmd = std::process::Command::new("curl");
+    cmd.args([
+        "--silent",
+        "--write-out",
+        "%{http_code}\\n",
+        "--header",
+        "@-",
+        "-o",
+    ])
+    .arg("/dev/null")
+    .arg(&url);
+    let output = run_curl(cmd, Some(auth_header("secret"))).unwrap();
+    let request = server.join().unwrap();
+    assert!(request.contains(&"Authorization: Bearer secret".to_string()));
+    assert_eq!(http_statuses(&output.stdout), vec![401]);
+
+    assert!(matches!(
+        check_http_status("0.1.0", Some(403)),
+        Err(MooncakesIOError::Unauthorized { status: 403, .. })
+    ));
+    assert!(check_http_status("0.1.0", Some(200)).is_ok());
+    assert_eq!(http_statuses(b"200\n401\n"), vec![200, 401]);
+}
+
+pub fn home() -> Result<PathBuf, MooncakesIOError> {
+    let hm = home_with(|key| std::env::var_os(key), home::home_dir)?;
     if !hm.exists() {
-        std::fs::create_dir_all(&hm).unwrap();
+        std::fs::create_dir_all(&hm).map_err(MooncakesIOError::IOError)?;
+    }
+    Ok(hm)
+}
+
+/// `MOON_HOME`, then `$XDG_DATA_HOME/moon`, then `~/.moon`. `HOME`/`USERPROFILE`
+/// are used when `home_dir` can't resolve the home directory.
+pub(crate) fn home_with(
+    env: impl Fn(&str) -> Option<OsString>,
+    home_dir: impl FnOnce() -> Option<PathBuf>,
+) -> Result<PathBuf, MooncakesIOError> {
+    let non_empty = |key: &str| env(key).filter(|v| !v.is_empty());
+    if let Some(moon_home) = non_empty("MOON_HOME") {
+        return Ok(PathBuf::from(moon_home));
+    }
+    if let Some(data_home) = non_empty("XDG_DATA_HOME") {
+        return Ok(PathBuf::from(data_home).join("moon"));
     }
-    hm
+    home_dir()
+        .or_else(|| non_empty("HOME").map(PathBuf::from))
+        .or_else(|| non_empty("USERPROFILE").map(PathBuf::from))
+        .map(|h| h.join(".moon"))
+        .ok_or(MooncakesIOError::HomeNotFound)
 }
 
-pub fn index() -> PathBuf {
-    home().join("registry").join("index")
+pub fn index() -> Result<PathBuf, MooncakesIOError> {
+    Ok(home()?.join("registry").join("index"))
+}
+
+#[test]
+fn home_resolution() {
+    let env = |vars: &'static [(&str, &str)]| {
+        move |key: &str| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| OsString::from(v))
+        }
+    };
+    let moon_home = &[("MOON_HOME", "/opt/moon"), ("XDG_DATA_HOME", "/data")];
+    assert_eq!(
+        home_with(env(moon_home), || None).unwrap(),
+        PathBuf::from("/opt/moon")
+    );
+    assert_eq!(
+        home_with(env(&[("XDG_DATA_HOME", "/data")]), || None).unwrap(),
+        PathBuf::from("/data").join("moon")
+    );
+    assert_eq!(
+        home_with(env(&[("MOON_HOME", "")]), || Some(PathBuf::from("/home/a"))).unwrap(),
+        PathBuf::from("/home/a").join(".moon")
+    );
+    assert_eq!(
+        home_with(env(&[("HOME", "/home/b")]), || None).unwrap(),
+        PathBuf::from("/home/b").join(".moon")
+    );
+    assert!(matches!(
+        home_with(env(&[]), || None),
+        Err(MooncakesIOError::HomeNotFound)
+    ));
 }
 
 pub fn index_of_pkg(base: &Path, user: &str, pkg: &str) -> PathBuf {
@@ -110,9 +784,26 @@ pub fn index_of_pkg(base: &Path, user: &str, pkg: &str) -> PathBuf {
         .with_extension("index")
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct MooncakesDB {
     pub db: BTreeMap<String, Vec<String>>,
+    /// direct dependencies of the latest version of each package
+    pub deps: BTreeMap<String, Vec<String>>,
+    /// keywords of the latest version of each package
+    pub keywords: BTreeMap<String, Vec<String>>,
+    /// malformed index lines that were skipped, not kept in a snapshot
+    #[serde(skip)]
+    pub index_errors: Vec<IndexLineError>,
+}
+
+/// A line of a registry index file that could not be parsed, e.g. after a
+/// partial write of the registry cache.
+#[derive(Debug)]
+pub struct IndexLineError {
+    pub file: PathBuf,
+    /// 1-based
+    pub line: usize,
+    pub error: serde_json::Error,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -129,10 +820,20 @@ pub enum MooncakesDBErrorKind {
 }
 
 impl MooncakesDB {
+    /// The greatest version of `name` by semver, see `util::latest_version`.
     pub fn get_latest_version(&self, name: &str) -> Result<String, MooncakesDBError> {
+        self.get_latest_version_with(name, true)
+    }
+
+    pub fn get_latest_version_with(
+        &self,
+        name: &str,
+        include_prerelease: bool,
+    ) -> Result<String, MooncakesDBError> {
         self.db
             .get(name)
-            .map(|versions| versions.last().unwrap().to_string())
+            .and_then(|versions| latest_version(versions, include_prerelease))
+            .cloned()
             .ok_or(MooncakesDBError {
                 kind: MooncakesDBErrorKind::NotFound {
                     key: name.to_string(),
@@ -143,52 +844,371 @@ impl MooncakesDB {
     pub fn contains_key(&self, name: &str) -> bool {
         self.db.contains_key(name)
     }
+
+    /// Whether the latest version of `name` lists `keyword`.
+    pub fn has_keyword(&self, name: &str, keyword: &str) -> bool {
+        self.keywords
+            .get(name)
+            .is_some_and(|keywords| keywords.iter().any(|k| k == keyword))
+    }
+}
+
+#[test]
+fn latest_version_of_unsorted_index() {
+    let db = MooncakesDB {
+        db: BTreeMap::from([(
+            "alice/a".to_string(),
+            vec!["0.10.0", "0.2.0-beta", "0.9.1", "0.11.0-rc.1"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        )]),
+        ..Default::default()
+    };
+    assert_eq!(db.get_latest_version("alice/a").unwrap(), "0.11.0-rc.1");
+    assert_eq!(
+        db.get_latest_version_with("alice/a", false).unwrap(),
+        "0.10.0"
+    );
+    assert!(db.get_latest_version("alice/b").is_err());
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct MooncakeInfo {
     version: String,
+    checksum: Option<String>,
     keywords: Option<Vec<String>>,
+    deps: Option<BTreeMap<String, serde_json::Value>>,
 }
 
 #[test]
 fn gen_latest_list() {
     let db = get_all_mooncakes().unwrap();
     for (name, versions) in db.db {
-        let latest_version = versions.last().unwrap();
-        println!("{} {}", name, latest_version);
+        if let Some(latest_version) = latest_version(&versions, true) {
+            println!("{} {}", name, latest_version);
+        }
     }
 }
 
 pub fn get_all_mooncakes() -> Result<MooncakesDB, MooncakesIOError> {
+    get_all_mooncakes_with(ScanOptions::default())
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScanOptions {
+    /// a package found under more than one index file, or a malformed index
+    /// line, is an error instead of a warning
+    pub strict_index: bool,
+    /// follow symlinks in the registry, loops are detected and skipped
+    pub follow_symlinks: bool,
+}
+
+pub fn get_all_mooncakes_with(opts: ScanOptions) -> Result<MooncakesDB, MooncakesIOError> {
+    get_all_mooncakes_in(&index()?.join("user"), opts)
+}
+
+/// A `MooncakesDB` saved after a scan, see `load_or_scan`.
+#[derive(Debug, Serialize, Deserialize)]
+struct DbSnapshot {
+    /// seconds since the unix epoch
+    taken_at: u64,
+    db: MooncakesDB,
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The db of the snapshot at `path` if it was taken at most `max_age` before
+/// `now`, otherwise the result of `scan`, which is then saved to `path`. A
+/// missing or unreadable snapshot is rescanned, a failed save only warns.
+pub fn load_or_scan(
+    path: &Path,
+    max_age: Duration,
+    now: SystemTime,
+    scan: impl FnOnce() -> Result<MooncakesDB, MooncakesIOError>,
+) -> Result<MooncakesDB, MooncakesIOError> {
+    let snapshot = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<DbSnapshot>(&s).ok());
+    if let Some(snapshot) = snapshot {
+        if unix_secs(now).saturating_sub(snapshot.taken_at) <= max_age.as_secs() {
+            return Ok(snapshot.db);
+        }
+    }
+    let db = scan()?;
+    let snapshot = DbSnapshot {
+        taken_at: unix_secs(now),
+        db,
+    };
+    let saved = serde_json::to_string(&snapshot)
+        .map_err(std::io::Error::from)
+        .and_then(|json| write_atomic(path, json));
+    if let Err(e) = saved {
+        eprintln!(
+            "failed to save the registry snapshot {}: {}",
+            path.display(),
+            e
+        );
+    }
+    Ok(snapshot.db)
+}
+
+fn insert_index(
+    db: &mut BTreeMap<String, Vec<String>>,
+    name: &str,
+    versions: Vec<String>,
+    strict_index: bool,
+) -> Result<(), MooncakesIOError> {
+    if db.contains_key(name) {
+        if strict_index {
+            return Err(MooncakesIOError::DuplicateIndex {
+                name: name.to_string(),
+            });
+        }
+        eprintln!("duplicate index entry for {}, keeping the last one", name);
+    }
+    db.insert(name.to_string(), versions);
+    Ok(())
+}
+
+pub fn get_all_mooncakes_in(
+    dir: &Path,
+    opts: ScanOptions,
+) -> Result<MooncakesDB, MooncakesIOError> {
     let mut db: BTreeMap<String, Vec<String>> = BTreeMap::new();
-    let dir = index().join("user");
-    let walker = walkdir::WalkDir::new(&dir).into_iter();
+    let mut deps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut keywords: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut index_errors = vec![];
+    let walker = walkdir::WalkDir::new(dir)
+        .follow_links(opts.follow_symlinks)
+        .into_iter();
+    // loops surface as walk errors; without following, symlinks are not regular files
     for entry in walker.filter_map(|e| e.ok()).filter(|e| {
-        e.path().is_file() && e.path().extension().and_then(|ext| ext.to_str()) == Some("index")
+        e.file_type().is_file()
+            && e.path().extension().and_then(|ext| ext.to_str()) == Some("index")
     }) {
         let p = entry.path();
-        let name = p.strip_prefix(&dir).unwrap().to_str().unwrap();
+        let name = p.strip_prefix(dir).unwrap().to_str().unwrap();
         let dot_index = name.rfind(".index").unwrap_or(name.len());
         let name = &name[0..dot_index];
 
         let index_file_content =
-            std::fs::read_to_string(entry.path()).map_err(|e| MooncakesIOError::IOError(e))?;
+            std::fs::read_to_string(entry.path()).map_err(MooncakesIOError::IOError)?;
         let mut is_mooncakes_test = false;
         let mut indexes = vec![];
-        for line in index_file_content.lines() {
-            let index: MooncakeInfo =
-                serde_json::from_str(line).map_err(|e| MooncakesIOError::Serde(e))?;
-            indexes.push(index.version);
-            if let Some(keywords) = &index.keywords {
-                if keywords.contains(&"mooncakes-test".to_string()) {
-                    is_mooncakes_test = true;
+        let mut latest_deps = vec![];
+        let mut latest_keywords = vec![];
+        for (i, line) in index_file_content.lines().enumerate() {
+            let index: MooncakeInfo = match serde_json::from_str(line) {
+                Ok(index) => index,
+                Err(error) if opts.strict_index => {
+                    return Err(MooncakesIOError::MalformedIndex {
+                        file: p.to_path_buf(),
+                        line: i + 1,
+                        error,
+                    })
                 }
+                Err(error) => {
+                    eprintln!(
+                        "skip malformed line {} of {}: {}",
+                        i + 1,
+                        p.display(),
+                        error
+                    );
+                    index_errors.push(IndexLineError {
+                        file: p.to_path_buf(),
+                        line: i + 1,
+                        error,
+                    });
+                    continue;
+                }
+            };
+            indexes.push(index.version);
+            latest_deps = index.deps.unwrap_or_default().into_keys().collect();
+            latest_keywords = index.keywords.unwrap_or_default();
+            if latest_keywords.contains(&"mooncakes-test".to_string()) {
+                is_mooncakes_test = true;
             }
         }
+        if indexes.is_empty() {
+            continue;
+        }
         if !is_mooncakes_test {
-            db.insert(name.to_string(), indexes);
+            insert_index(&mut db, name, indexes, opts.strict_index)?;
+            // keep dependency names consistent with `name` on windows
+            #[cfg(target_os = "windows")]
+            let latest_deps: Vec<String> =
+                latest_deps.iter().map(|d| d.replace('/', "\\")).collect();
+            deps.insert(name.to_string(), latest_deps);
+            keywords.insert(name.to_string(), latest_keywords);
         }
     }
-    Ok(MooncakesDB { db })
+    Ok(MooncakesDB {
+        db,
+        deps,
+        keywords,
+        index_errors,
+    })
+}
+
+#[test]
+fn fresh_snapshot_skips_scan() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("mooncakes_db.json");
+    let hour = Duration::from_secs(3600);
+    let now = SystemTime::now();
+    let scan = |version: &str| {
+        let db = BTreeMap::from([("alice/a".to_string(), vec![version.to_string()])]);
+        move || {
+            Ok(MooncakesDB {
+                db,
+                ..Default::default()
+            })
+        }
+    };
+
+    // no snapshot yet
+    let db = load_or_scan(&path, hour, now, scan("0.1.0")).unwrap();
+    assert_eq!(db.get_latest_version("alice/a").unwrap(), "0.1.0");
+    assert!(path.is_file());
+
+    let db = load_or_scan(&path, hour, now + hour / 2, || panic!("rescanned")).unwrap();
+    assert_eq!(db.get_latest_version("alice/a").unwrap(), "0.1.0");
+
+    // stale, the rescan replaces the snapshot
+    let db = load_or_scan(&path, hour, now + 2 * hour, scan("0.2.0")).unwrap();
+    assert_eq!(db.get_latest_version("alice/a").unwrap(), "0.2.0");
+    let db = load_or_scan(&path, hour, now + 2 * hour, || panic!("rescanned")).unwrap();
+    assert_eq!(db.get_latest_version("alice/a").unwrap(), "0.2.0");
+
+    std::fs::write(&path, "not json").unwrap();
+    let db = load_or_scan(&path, hour, now, scan("0.3.0")).unwrap();
+    assert_eq!(db.get_latest_version("alice/a").unwrap(), "0.3.0");
+}
+
+#[test]
+fn duplicate_index_entry() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().join("alice");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.index"), "{\"version\":\"0.1.0\"}\n").unwrap();
+    std::fs::write(
+        dir.join("b.index"),
+        "{\"version\":\"0.2.0\",\"deps\":{\"alice/a\":\"0.1.0\"}}\n",
+    )
+    .unwrap();
+    let opts = ScanOptions {
+        strict_index: true,
+        ..Default::default()
+    };
+    let db = get_all_mooncakes_in(tmp.path(), opts).unwrap();
+    assert_eq!(db.db.len(), 2);
+    assert_eq!(
+        db.deps[&format!("alice{}b", std::path::MAIN_SEPARATOR)].len(),
+        1
+    );
+
+    // two index files resolving to the same package name
+    let mut db = BTreeMap::new();
+    insert_index(&mut db, "alice/a", vec!["0.1.0".to_string()], false).unwrap();
+    insert_index(&mut db, "alice/a", vec!["0.2.0".to_string()], false).unwrap();
+    assert_eq!(db["alice/a"], vec!["0.2.0".to_string()]);
+    assert!(matches!(
+        insert_index(&mut db, "alice/a", vec![], true),
+        Err(MooncakesIOError::DuplicateIndex { name }) if name == "alice/a"
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loop_in_registry() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().join("alice");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.index"), "{\"version\":\"0.1.0\"}\n").unwrap();
+    std::os::unix::fs::symlink(tmp.path(), dir.join("loop")).unwrap();
+    std::os::unix::fs::symlink(dir.join("a.index"), dir.join("b.index")).unwrap();
+
+    let db = get_all_mooncakes_in(tmp.path(), ScanOptions::default()).unwrap();
+    assert_eq!(db.db.keys().collect::<Vec<_>>(), vec!["alice/a"]);
+
+    let opts = ScanOptions {
+        follow_symlinks: true,
+        ..Default::default()
+    };
+    let db = get_all_mooncakes_in(tmp.path(), opts).unwrap();
+    assert_eq!(db.db.keys().collect::<Vec<_>>(), vec!["alice/a", "alice/b"]);
+}
+
+#[test]
+fn malformed_index_is_skipped() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().join("alice");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.index"), "{\"version\":\"0.1.0\"}\n").unwrap();
+    std::fs::write(
+        dir.join("b.index"),
+        "{\"version\":\"0.1.0\"}\n{\"version\":\"0.2\n{\"version\":\"0.3.0\"}\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("c.index"), "not json\n").unwrap();
+
+    let db = get_all_mooncakes_in(tmp.path(), ScanOptions::default()).unwrap();
+    let sep = std::path::MAIN_SEPARATOR;
+    assert_eq!(
+        db.db.keys().cloned().collect::<Vec<_>>(),
+        vec![format!("alice{}a", sep), format!("alice{}b", sep)]
+    );
+    assert_eq!(db.db[&format!("alice{}b", sep)], vec!["0.1.0", "0.3.0"]);
+    let mut errors: Vec<_> = db
+        .index_errors
+        .iter()
+        .map(|e| (e.file.file_name().unwrap().to_owned(), e.line))
+        .collect();
+    errors.sort();
+    assert_eq!(errors, vec![("b.index".into(), 2), ("c.index".into(), 1)]);
+
+    let opts = ScanOptions {
+        strict_index: true,
+        ..Default::default()
+    };
+    assert!(matches!(
+        get_all_mooncakes_in(tmp.path(), opts),
+        Err(MooncakesIOError::MalformedIndex { .. })
+    ));
+}
+
+#[test]
+fn filter_by_keyword() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().join("alice");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("server.index"),
+        "{\"version\":\"0.1.0\",\"keywords\":[\"cli\"]}\n\
+         {\"version\":\"0.2.0\",\"keywords\":[\"web\",\"http\"]}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("args.index"),
+        "{\"version\":\"0.1.0\",\"keywords\":[\"cli\"]}\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("json.index"), "{\"version\":\"0.1.0\"}\n").unwrap();
+
+    let db = get_all_mooncakes_in(tmp.path(), ScanOptions::default()).unwrap();
+    let with = |keyword: &str| {
+        db.db
+            .keys()
+            .filter(|name| db.has_keyword(name, keyword))
+            .map(|name| name.replace('\\', "/"))
+            .collect::<Vec<_>>()
+    };
+    // only the keywords of the latest version count
+    assert_eq!(with("cli"), vec!["alice/args"]);
+    assert_eq!(with("web"), vec!["alice/server"]);
+    assert!(with("mooncakes-test").is_empty());
 }
@@ -0,0 +1,678 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::{cli::OutputFormat, util::write_atomic};
+
+use crate::dashboard::{
+    DashboardReadError, ExecuteResult, LogEncoding, MoonBuildDashboard, Status,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum OutputError {
+    #[error("io error")]
+    IOError(#[from] std::io::Error),
+    #[error("serde")]
+    Serde(#[from] serde_json::Error),
+    #[error("failed to write {}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("output is {size} bytes even after pruning, limit is {max} bytes")]
+    TooLarge { size: usize, max: usize },
+    #[error("processor `{command}` failed with {status}: {stderr}")]
+    ProcessorFailed {
+        command: String,
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    #[error("processor `{command}` did not print a dashboard")]
+    ProcessorOutput {
+        command: String,
+        #[source]
+        source: DashboardReadError,
+    },
+}
+
+/// The dashboard as a gzipped JSON line, the format of `*_data.jsonl.gz`.
+pub fn to_gzip_jsonl(dashboard: &MoonBuildDashboard) -> Result<Vec<u8>, OutputError> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    writeln!(encoder, "{}", serde_json::to_string(dashboard)?)?;
+    Ok(encoder.finish()?)
+}
+
+/// The dashboard in `format`, see `--output-format`.
+pub fn encode(
+    dashboard: &MoonBuildDashboard,
+    format: OutputFormat,
+) -> Result<Vec<u8>, OutputError> {
+    match format {
+        OutputFormat::JsonlGz => to_gzip_jsonl(dashboard),
+        OutputFormat::Jsonl => Ok(format!("{}\n", serde_json::to_string(dashboard)?).into_bytes()),
+        OutputFormat::JsonPretty => {
+            Ok(format!("{}\n", serde_json::to_string_pretty(dashboard)?).into_bytes())
+        }
+    }
+}
+
+/// The dashboard in `format`, pruned first with a `max`, see `prune_to_fit`.
+pub fn encode_to_fit(
+    dashboard: &mut MoonBuildDashboard,
+    format: OutputFormat,
+    max: Option<usize>,
+) -> Result<Vec<u8>, OutputError> {
+    match (max, format) {
+        (Some(max), OutputFormat::JsonlGz) => prune_to_fit(dashboard, max),
+        // the limit is on the gzipped size whatever the format
+        (Some(max), format) => {
+            prune_to_fit(dashboard, max)?;
+            encode(dashboard, format)
+        }
+        (None, format) => encode(dashboard, format),
+    }
+}
+
+/// Pipes `dashboard` as JSON to the shell command `command` and reads back the
+/// dashboard it prints, e.g. with added labels or filtered sources.
+pub fn run_processor(
+    dashboard: &MoonBuildDashboard,
+    command: &str,
+) -> Result<MoonBuildDashboard, OutputError> {
+    let json = serde_json::to_vec(dashboard)?;
+    #[cfg(unix)]
+    let mut cmd = std::process::Command::new("sh");
+    #[cfg(unix)]
+    cmd.args(["-c", command]);
+    #[cfg(target_os = "windows")]
+    let mut cmd = std::process::Command::new("cmd");
+    #[cfg(target_os = "windows")]
+    cmd.args(["/C", command]);
+    let mut child = cmd
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("piped stdin");
+    // written on its own thread, the processor may print before it read everything
+    let writer = std::thread::spawn(move || stdin.write_all(&json));
+    let output = child.wait_with_output()?;
+    // a processor ignoring its input closes the pipe early, which is fine
+    let _ = writer.join().expect("stdin writer panicked");
+    if !output.status.success() {
+        return Err(OutputError::ProcessorFailed {
+            command: command.to_string(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    MoonBuildDashboard::from_json(&String::from_utf8_lossy(&output.stdout)).map_err(|source| {
+        OutputError::ProcessorOutput {
+            command: command.to_string(),
+            source,
+        }
+    })
+}
+
+/// Creates `dir` and writes and removes a probe file in it, so a run that could
+/// not save its results fails before doing any work.
+pub fn ensure_writable(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let probe = dir.join(format!(".write_probe_{}", std::process::id()));
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+/// Writes `bytes` to `path`, creating the directories it is in, e.g. for a new
+/// OS label under `webapp/public`. The file is renamed into place, so readers
+/// see the previous or the new content, never a partial write.
+pub fn write_output(path: &Path, bytes: &[u8]) -> Result<(), OutputError> {
+    let write = || -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_atomic(path, bytes)
+    };
+    write().map_err(|source| OutputError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Points `latest` at `data`, a file in the same directory: a relative symlink
+/// on Unix, a copy elsewhere. An existing `latest` is replaced atomically.
+pub fn update_latest(data: &Path, latest: &Path) -> std::io::Result<()> {
+    let tmp = latest.with_file_name(format!(
+        ".{}.tmp",
+        latest.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    if let Some(dir) = latest.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let _ = std::fs::remove_file(&tmp);
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(data.file_name().unwrap_or_default(), &tmp)?;
+    #[cfg(not(unix))]
+    std::fs::copy(data, &tmp)?;
+    std::fs::rename(&tmp, latest)
+}
+
+/// The date of a `<YYYY-MM-DD>_data.jsonl.gz` file name, `None` for anything else.
+fn dated_file_date(file_name: &str) -> Option<NaiveDate> {
+    let date = file_name.strip_suffix("_data.jsonl.gz")?;
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// Deletes the dated data files in `dir` older than `retain_days` before
+/// `today`, returning the removed paths. Other files, like `latest_data.jsonl.gz`,
+/// are never touched.
+pub fn remove_old_data_files(
+    dir: &Path,
+    today: NaiveDate,
+    retain_days: u32,
+) -> std::io::Result<Vec<PathBuf>> {
+    let oldest = today - chrono::Duration::days(retain_days as i64);
+    let mut removed = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let Some(date) = entry.file_name().to_str().and_then(dated_file_date) else {
+            continue;
+        };
+        if date < oldest {
+            std::fs::remove_file(entry.path())?;
+            removed.push(entry.path());
+        }
+    }
+    removed.sort();
+    Ok(removed)
+}
+
+#[test]
+fn output_formats_read_back() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dashboard = crate::dashboard::sample_dashboard();
+    for (format, name, lines) in [
+        (OutputFormat::JsonlGz, "data.jsonl.gz", None),
+        (OutputFormat::Jsonl, "data.jsonl", Some(1)),
+        (OutputFormat::JsonPretty, "data.json", None),
+    ] {
+        let bytes = encode(&dashboard, format).unwrap();
+        if let Some(lines) = lines {
+            assert_eq!(String::from_utf8_lossy(&bytes).lines().count(), lines);
+        }
+        let path = tmp.path().join(name);
+        write_output(&path, &bytes).unwrap();
+        let read = MoonBuildDashboard::from_file(&path).unwrap();
+        assert_eq!(read.sources, dashboard.sources);
+    }
+    let pretty = encode(&dashboard, OutputFormat::JsonPretty).unwrap();
+    assert!(String::from_utf8(pretty).unwrap().starts_with("{\n  \""));
+}
+
+#[test]
+fn default_output_path_gets_gzip() {
+    use crate::cli::{MoonBuildDashBoardCli, MoonBuildDashBoardSubcommands, StatSubcommand};
+    use clap::Parser;
+
+    let stat = |args: &[&str]| -> Result<StatSubcommand, clap::Error> {
+        let args = ["moon_dashboard", "stat"].iter().chain(args);
+        match MoonBuildDashBoardCli::try_parse_from(args)?.subcommand {
+            MoonBuildDashBoardSubcommands::Stat(cmd) => Ok(cmd),
+            _ => unreachable!(),
+        }
+    };
+    // plain JSON would end up as the dated `.jsonl.gz` and the latest link
+    for format in ["jsonl", "json-pretty"] {
+        assert!(stat(&["--output-format", format]).is_err());
+        assert!(stat(&["--output-format", format, "--output-path", "out.json"]).is_ok());
+    }
+    for args in [
+        &[][..],
+        &["--output-format", "jsonl-gz"],
+        &["--max-output-file-bytes", "100000"],
+    ] {
+        let cmd = stat(args).unwrap();
+        assert!(cmd.output_path.is_none());
+        let mut dashboard = crate::dashboard::sample_dashboard();
+        let bytes =
+            encode_to_fit(&mut dashboard, cmd.output_format, cmd.max_output_file_bytes).unwrap();
+        assert_eq!(bytes[..2], [0x1f, 0x8b], "{:?}", args);
+    }
+}
+
+#[test]
+fn retention_removes_old_dated_files() {
+    let tmp = tempfile::tempdir().unwrap();
+    for name in [
+        "2024-01-01_data.jsonl.gz",
+        "2024-01-09_data.jsonl.gz",
+        "2024-01-10_data.jsonl.gz",
+        "2024-01-11_data.jsonl.gz",
+        "latest_data.jsonl.gz",
+        "2023-13-01_data.jsonl.gz",
+        "2023-01-01_notes.txt",
+    ] {
+        std::fs::write(tmp.path().join(name), "").unwrap();
+    }
+    let today = NaiveDate::from_ymd_opt(2024, 1, 11).unwrap();
+    let removed = remove_old_data_files(tmp.path(), today, 1).unwrap();
+    assert_eq!(
+        removed,
+        vec![
+            tmp.path().join("2024-01-01_data.jsonl.gz"),
+            tmp.path().join("2024-01-09_data.jsonl.gz"),
+        ]
+    );
+    let mut left: Vec<String> = std::fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    left.sort();
+    assert_eq!(
+        left,
+        [
+            "2023-01-01_notes.txt",
+            "2023-13-01_data.jsonl.gz",
+            "2024-01-10_data.jsonl.gz",
+            "2024-01-11_data.jsonl.gz",
+            "latest_data.jsonl.gz",
+        ]
+    );
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | ((*b as u32) << (16 - 8 * i)));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> std::io::Result<Vec<u8>> {
+    let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid base64");
+    let s = s.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.chunks(4) {
+        if chunk.len() == 1 {
+            return Err(invalid());
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = BASE64.iter().position(|b| b == c).ok_or_else(invalid)?;
+            n |= (v as u32) << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            out.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn encode_log(log: &str) -> std::io::Result<String> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(log.as_bytes())?;
+    Ok(base64_encode(&encoder.finish()?))
+}
+
+fn decode_log(log: &str) -> std::io::Result<String> {
+    let mut decoded = String::new();
+    GzDecoder::new(base64_decode(log)?.as_slice()).read_to_string(&mut decoded)?;
+    Ok(decoded)
+}
+
+/// Gzips and base64 encodes `stdout`/`stderr` of every result, so the logs stay
+/// embedded in a single small file.
+pub fn compress_logs(dashboard: &mut MoonBuildDashboard) -> std::io::Result<()> {
+    for r in dashboard.results_mut() {
+        if r.log_encoding.is_some() {
+            continue;
+        }
+        r.stdout = encode_log(&r.stdout)?;
+        r.stderr = encode_log(&r.stderr)?;
+        r.log_encoding = Some(LogEncoding::GzipBase64);
+    }
+    Ok(())
+}
+
+/// Turns the logs of `compress_logs` back into plain text, plain logs are kept.
+pub fn decode_logs(dashboard: &mut MoonBuildDashboard) -> std::io::Result<()> {
+    for r in dashboard.results_mut() {
+        if let Some(LogEncoding::GzipBase64) = r.log_encoding {
+            r.stdout = decode_log(&r.stdout)?;
+            r.stderr = decode_log(&r.stderr)?;
+            r.log_encoding = None;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn compressed_logs_round_trip() {
+    for s in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+        assert_eq!(
+            base64_decode(&base64_encode(s.as_bytes())).unwrap(),
+            s.as_bytes()
+        );
+    }
+    assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64_encode(b"fo"), "Zm8=");
+    assert!(base64_decode("Zm9v!").is_err());
+
+    let mut dashboard = crate::dashboard::sample_dashboard();
+    let log = "error: unbound identifier `x`\n".repeat(100) + "ünïcode\n";
+    for r in dashboard.results_mut() {
+        r.stderr = log.clone();
+    }
+    compress_logs(&mut dashboard).unwrap();
+    let json = serde_json::to_string(&dashboard).unwrap();
+    assert!(json.contains("\"log_encoding\":\"gzip+base64\""));
+    assert!(!json.contains("unbound identifier"));
+
+    let mut dashboard = MoonBuildDashboard::from_json(&json).unwrap();
+    let r = dashboard.results_mut().next().unwrap();
+    assert!(r.stderr.len() < log.len());
+    decode_logs(&mut dashboard).unwrap();
+    assert!(dashboard
+        .results_mut()
+        .all(|r| r.stderr == log && r.stdout.is_empty() && r.log_encoding.is_none()));
+}
+
+const TRUNCATED: &str = "\n...[truncated]";
+
+fn truncate_log(log: &mut String, max: usize) -> bool {
+    if log.len() <= max {
+        return false;
+    }
+    let mut end = max;
+    while !log.is_char_boundary(end) {
+        end -= 1;
+    }
+    log.truncate(end);
+    log.push_str(TRUNCATED);
+    true
+}
+
+fn is_success(r: &ExecuteResult) -> bool {
+    matches!(r.status, Status::Success)
+}
+
+/// Drops logs from the dashboard until its gzipped size fits into `max` bytes:
+/// first the logs of successful cells, then failing logs are truncated more
+/// and more. What was pruned is recorded in `dashboard.pruned`.
+pub fn prune_to_fit(
+    dashboard: &mut MoonBuildDashboard,
+    max: usize,
+) -> Result<Vec<u8>, OutputError> {
+    let mut bytes = to_gzip_jsonl(dashboard)?;
+    if bytes.len() <= max {
+        return Ok(bytes);
+    }
+
+    let mut cleared = 0;
+    for r in dashboard.results_mut().filter(|r| is_success(r)) {
+        if !r.stdout.is_empty() || !r.stderr.is_empty() {
+            r.stdout.clear();
+            r.stderr.clear();
+            cleared += 1;
+        }
+    }
+    dashboard
+        .pruned
+        .push(format!("cleared logs of {} successful cells", cleared));
+    bytes = to_gzip_jsonl(dashboard)?;
+
+    let mut limit = 64 * 1024;
+    while bytes.len() > max {
+        let mut truncated = 0;
+        for r in dashboard.results_mut().filter(|r| !is_success(r)) {
+            let stdout = truncate_log(&mut r.stdout, limit);
+            let stderr = truncate_log(&mut r.stderr, limit);
+            if stdout || stderr {
+                truncated += 1;
+            }
+        }
+        dashboard.pruned.push(format!(
+            "truncated logs of {} failing cells to {} bytes",
+            truncated, limit
+        ));
+        bytes = to_gzip_jsonl(dashboard)?;
+        if limit == 0 {
+            break;
+        }
+        limit /= 4;
+    }
+    if bytes.len() > max {
+        return Err(OutputError::TooLarge {
+            size: bytes.len(),
+            max,
+        });
+    }
+    Ok(bytes)
+}
+
+#[test]
+fn oversized_dashboard_is_pruned() {
+    // incompressible logs
+    let mut seed: u64 = 42;
+    let mut noise = |len: usize| -> String {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                char::from(b'a' + ((seed >> 33) % 26) as u8)
+            })
+            .collect()
+    };
+    let result = |status: Status, log: String| ExecuteResult {
+        status,
+        stdout: log,
+        ..Default::default()
+    };
+    let state = |wasm, wasm_gc, js| serde_json::json!({"wasm": wasm, "wasm_gc": wasm_gc, "js": js});
+    let cbt = serde_json::json!({
+        "check": state(
+            result(Status::Success, noise(20_000)),
+            result(Status::Failure, noise(100_000)),
+            result(Status::Success, noise(20_000)),
+        ),
+        "build": state(
+            result(Status::Success, noise(20_000)),
+            result(Status::Success, noise(20_000)),
+            result(Status::Success, noise(20_000)),
+        ),
+        "test": state(
+            result(Status::Success, noise(20_000)),
+            result(Status::Success, noise(20_000)),
+            result(Status::Failure, noise(100_000)),
+        ),
+    });
+    let dashboard = serde_json::json!({
+        "run_id": "1",
+        "run_number": "1",
+        "start_time": "",
+        "sources": [],
+        "stable_toolchain_version": {"label": "Stable", "moon_version": "", "moonc_version": ""},
+        "stable_release_data": [{"source": 0, "cbts": [cbt]}],
+        "bleeding_toolchain_version": {"label": "Bleeding", "moon_version": "", "moonc_version": ""},
+        "bleeding_release_data": []
+    });
+    let mut dashboard = MoonBuildDashboard::from_json(&dashboard.to_string()).unwrap();
+    let full = to_gzip_jsonl(&dashboard).unwrap().len();
+
+    let max = 50_000;
+    assert!(full > max);
+    let bytes = prune_to_fit(&mut dashboard, max).unwrap();
+    assert!(bytes.len() <= max);
+    assert!(dashboard.pruned.len() >= 2);
+    let results: Vec<&ExecuteResult> = dashboard.stable_release_data[0].cbts[0]
+        .as_ref()
+        .unwrap()
+        .results()
+        .map(|(_, _, r)| r)
+        .collect();
+    assert!(results
+        .iter()
+        .all(|r| !is_success(r) || r.stdout.is_empty()));
+    assert!(results
+        .iter()
+        .any(|r| !is_success(r) && r.stdout.ends_with(TRUNCATED)));
+
+    let mut dashboard =
+        MoonBuildDashboard::from_json(&serde_json::to_string(&dashboard).unwrap()).unwrap();
+    assert!(matches!(
+        prune_to_fit(&mut dashboard, 10),
+        Err(OutputError::TooLarge { .. })
+    ));
+}
+
+#[test]
+fn unwritable_output_dir() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().join("public/linux");
+    ensure_writable(&dir).unwrap();
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    // a file in the way of the directory
+    std::fs::write(tmp.path().join("file"), "").unwrap();
+    assert!(ensure_writable(&tmp.path().join("file/linux")).is_err());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let read_only = tmp.path().join("read_only");
+        std::fs::create_dir(&read_only).unwrap();
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // permissions do not apply to root
+        let privileged = std::fs::write(read_only.join("x"), "").is_ok();
+        if !privileged {
+            assert!(ensure_writable(&read_only).is_err());
+            assert!(ensure_writable(&read_only.join("linux")).is_err());
+        }
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+#[test]
+fn output_into_missing_dirs() {
+    let tmp = tempfile::tempdir().unwrap();
+    let data = tmp
+        .path()
+        .join("webapp/public/linux/2024-01-01_data.jsonl.gz");
+    write_output(&data, b"data").unwrap();
+    assert_eq!(std::fs::read(&data).unwrap(), b"data");
+    let latest = tmp.path().join("webapp/public/linux/latest_data.jsonl.gz");
+    update_latest(&data, &latest).unwrap();
+    assert_eq!(std::fs::read(&latest).unwrap(), b"data");
+
+    // a file in the way of the directory
+    std::fs::write(tmp.path().join("file"), "").unwrap();
+    let blocked = tmp.path().join("file/linux/2024-01-01_data.jsonl.gz");
+    let e = write_output(&blocked, b"data").unwrap_err();
+    assert!(matches!(e, OutputError::Write { ref path, .. } if *path == blocked));
+    assert!(
+        e.to_string()
+            .contains("file/linux/2024-01-01_data.jsonl.gz"),
+        "{}",
+        e
+    );
+}
+
+#[test]
+fn readers_never_see_partial_output() {
+    let tmp = tempfile::tempdir().unwrap();
+    let data = tmp.path().join("2024-01-01_data.jsonl.gz");
+    let latest = tmp.path().join("latest_data.jsonl.gz");
+    // large enough for a plain write to be observed half done
+    let contents = [vec![b'a'; 1 << 20], vec![b'b'; 1 << 20]];
+    write_output(&data, &contents[0]).unwrap();
+    update_latest(&data, &latest).unwrap();
+
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            for i in 0..50 {
+                write_output(&data, &contents[i % 2]).unwrap();
+                update_latest(&data, &latest).unwrap();
+            }
+            done.store(true, std::sync::atomic::Ordering::SeqCst);
+        });
+        while !done.load(std::sync::atomic::Ordering::SeqCst) {
+            for path in [&data, &latest] {
+                let read = std::fs::read(path).unwrap();
+                assert!(contents.contains(&read), "{} bytes", read.len());
+            }
+        }
+    });
+}
+
+#[test]
+fn latest_under_another_name() {
+    let tmp = tempfile::tempdir().unwrap();
+    let old = tmp.path().join("2024-01-01_data.jsonl.gz");
+    let new = tmp.path().join("2024-01-02_data.jsonl.gz");
+    std::fs::write(&old, "old").unwrap();
+    std::fs::write(&new, "new").unwrap();
+    let latest = tmp.path().join("strict_latest.jsonl.gz");
+    update_latest(&old, &latest).unwrap();
+    update_latest(&new, &latest).unwrap();
+    assert_eq!(std::fs::read_to_string(&latest).unwrap(), "new");
+    #[cfg(unix)]
+    assert_eq!(
+        std::fs::read_link(&latest).unwrap(),
+        Path::new("2024-01-02_data.jsonl.gz")
+    );
+    let mut names: Vec<_> = std::fs::read_dir(tmp.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "2024-01-01_data.jsonl.gz",
+            "2024-01-02_data.jsonl.gz",
+            "strict_latest.jsonl.gz"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn processor_adds_label() {
+    let dashboard = crate::dashboard::sample_dashboard();
+    let label = r#"sed 's/^{/{"labels":{"runner":"arm64"},/'"#;
+    let processed = run_processor(&dashboard, label).unwrap();
+    assert_eq!(processed.labels["runner"], "arm64");
+    assert_eq!(processed.sources.len(), dashboard.sources.len());
+    let json = serde_json::to_string(&processed).unwrap();
+    assert!(json.contains(r#""labels":{"runner":"arm64"}"#));
+
+    assert!(matches!(
+        run_processor(&dashboard, "echo not a dashboard"),
+        Err(OutputError::ProcessorOutput { .. })
+    ));
+    assert!(matches!(
+        run_processor(&dashboard, "echo oops >&2; exit 1"),
+        Err(OutputError::ProcessorFailed { stderr, .. }) if stderr == "oops\n"
+    ));
+}
@@ -0,0 +1,467 @@
+//! Best-effort parsers for the human readable output of `moon`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::dashboard::{Diagnostic, DiagnosticLevel, FailedTest};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TestSummary {
+    pub passed: u32,
+    pub failed: u32,
+    pub total: u32,
+}
+
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // skip `ESC [ ... letter`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn number_after(line: &str, key: &str) -> Option<u32> {
+    let rest = &line[line.find(key)? + key.len()..];
+    let rest = rest.trim_start_matches(|c: char| c == ':' || c == '=' || c.is_whitespace());
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Parses summary lines like `Total tests: 200, passed: 197, failed: 3.`
+/// from `moon test` output. Counts of several summary lines are added up.
+pub fn parse_test_summary(output: &str) -> Option<TestSummary> {
+    let mut summary: Option<TestSummary> = None;
+    for line in output.lines() {
+        let line = strip_ansi(line).to_lowercase();
+        let Some(total) = number_after(&line, "total tests") else {
+            continue;
+        };
+        let passed = number_after(&line, "passed");
+        let failed = number_after(&line, "failed");
+        let (passed, failed) = match (passed, failed) {
+            (Some(p), Some(f)) => (p, f),
+            (Some(p), None) => (p, total.saturating_sub(p)),
+            (None, Some(f)) => (total.saturating_sub(f), f),
+            (None, None) => continue,
+        };
+        let s = summary.get_or_insert_with(TestSummary::default);
+        s.passed += passed;
+        s.failed += failed;
+        s.total += total;
+    }
+    summary
+}
+
+/// Splits `file::name` or `file:line ("name")` as printed by `moon test`.
+fn split_test_descriptor(descriptor: &str) -> (String, String) {
+    if let Some((file, name)) = descriptor.rsplit_once("::") {
+        return (file.to_string(), name.to_string());
+    }
+    if let Some((file, name)) = descriptor.split_once(" (") {
+        let name = name.trim_end_matches(')').trim_matches('"');
+        return (file.to_string(), name.to_string());
+    }
+    (descriptor.to_string(), String::new())
+}
+
+/// Parses lines like `test pkg/lib/a_test.mbt::name failed: message`, optionally
+/// prefixed by `[module]`. A message on the following line is picked up when
+/// the failure line has none.
+pub fn parse_failed_tests(output: &str) -> Vec<FailedTest> {
+    let lines: Vec<String> = output.lines().map(strip_ansi).collect();
+    let mut failed = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let mut line = line.trim();
+        if line.starts_with('[') {
+            let Some(end) = line.find("] ") else {
+                continue;
+            };
+            line = line[end + 2..].trim_start();
+        }
+        let Some(rest) = line.strip_prefix("test ") else {
+            continue;
+        };
+        let Some(at) = rest.rfind(" failed") else {
+            continue;
+        };
+        let (file, name) = split_test_descriptor(rest[..at].trim());
+        let mut message = rest[at + " failed".len()..]
+            .trim_start_matches(':')
+            .trim()
+            .to_string();
+        if message.is_empty() {
+            if let Some(next) = lines.get(i + 1).map(|l| l.trim()) {
+                if !next.starts_with("test ") && !next.to_lowercase().starts_with("total tests") {
+                    message = next.to_string();
+                }
+            }
+        }
+        failed.push(FailedTest {
+            file,
+            name,
+            message,
+        });
+    }
+    failed
+}
+
+/// `path:line:col-line:col`, the location string of older moonc versions.
+fn split_location(loc: &str) -> (String, u32) {
+    let start = loc
+        .rsplit_once('-')
+        .filter(|(_, end)| !end.contains(['/', '\\']))
+        .map_or(loc, |(start, _)| start);
+    let mut parts = start.rsplitn(3, ':');
+    let (Some(_col), Some(line), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+        return (loc.to_string(), 0);
+    };
+    match line.parse() {
+        Ok(line) => (file.to_string(), line),
+        Err(_) => (loc.to_string(), 0),
+    }
+}
+
+/// Parses the JSON lines printed by `moon check/build --output-json`. Lines that
+/// are not diagnostics, and levels other than errors and warnings, are ignored.
+pub fn parse_json_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for line in output.lines() {
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(line.trim()) else {
+            continue;
+        };
+        let level = match value.get("level").and_then(|l| l.as_str()) {
+            Some("error") => DiagnosticLevel::Error,
+            Some("warning") => DiagnosticLevel::Warning,
+            _ => continue,
+        };
+        let (file, line) = match value.get("loc") {
+            Some(serde_json::Value::String(loc)) => split_location(loc),
+            Some(loc) => (
+                loc.get("path")
+                    .and_then(|p| p.as_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                loc.pointer("/start/line")
+                    .and_then(|l| l.as_u64())
+                    .unwrap_or(0) as u32,
+            ),
+            None => continue,
+        };
+        let message = value
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string();
+        diagnostics.push(Diagnostic {
+            file,
+            line,
+            level,
+            message,
+        });
+    }
+    diagnostics
+}
+
+/// Packages whose registry index file is mentioned in the output of `moon update`,
+/// e.g. in the diffstat ` user/alice/pkg.index | 1 +` of the index pull.
+pub fn parse_updated_packages(output: &str) -> BTreeSet<String> {
+    let mut updated = BTreeSet::new();
+    for word in strip_ansi(output).split_whitespace() {
+        let word = word.replace('\\', "/");
+        let Some(path) = word
+            .trim_matches(|c: char| c == '\'' || c == '"')
+            .strip_suffix(".index")
+        else {
+            continue;
+        };
+        // `.../index/user/alice/pkg`, `user/alice/pkg` or `alice/pkg`
+        let path = path.rsplit_once("user/").map_or(path, |(_, p)| p);
+        let path = path.trim_start_matches('/');
+        let valid = |part: &str| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        };
+        if path.split('/').count() == 2 && path.split('/').all(valid) {
+            updated.insert(path.to_string());
+        }
+    }
+    updated
+}
+
+/// The core library version on the `core` line of `moon version --all`, e.g.
+/// `core 0.1.20250108 (6a5bc8f 2025-01-08) ~/.moon/lib/core`. `None` when
+/// there is no such line.
+pub fn parse_core_version(output: &str) -> Option<String> {
+    strip_ansi(output).lines().find_map(|line| {
+        let mut words = line
+            .split_whitespace()
+            .map(|w| w.trim_end_matches(':'))
+            .filter(|w| !w.is_empty());
+        let name = words.next()?;
+        if !matches!(name.to_lowercase().as_str(), "core" | "moonbitlang/core") {
+            return None;
+        }
+        words
+            .find(|w| !w.eq_ignore_ascii_case("version"))
+            .map(|w| w.to_string())
+    })
+}
+
+/// The `deps` of a `moon.mod.json`, by name. A dependency is a version, or an
+/// object with a `version` or a local `path`, recorded as `path:<path>`. Empty
+/// when the file can't be parsed.
+pub fn parse_mod_deps(mod_json: &str) -> BTreeMap<String, String> {
+    let Ok(module) = serde_json::from_str::<serde_json::Value>(mod_json) else {
+        return BTreeMap::new();
+    };
+    let Some(deps) = module.get("deps").and_then(|d| d.as_object()) else {
+        return BTreeMap::new();
+    };
+    deps.iter()
+        .filter_map(|(name, dep)| {
+            let version = match dep {
+                serde_json::Value::String(version) => version.clone(),
+                dep => match (dep.get("version"), dep.get("path")) {
+                    (Some(version), _) => version.as_str()?.to_string(),
+                    (None, Some(path)) => format!("path:{}", path.as_str()?),
+                    (None, None) => return None,
+                },
+            };
+            Some((name.clone(), version))
+        })
+        .collect()
+}
+
+/// The `version` of a `moon.mod.json`.
+pub fn parse_mod_version(mod_json: &str) -> Option<String> {
+    let module = serde_json::from_str::<serde_json::Value>(mod_json).ok()?;
+    Some(module.get("version")?.as_str()?.to_string())
+}
+
+/// Messages of moon/moonc hinting that a package or its dependencies were
+/// built by another compiler version.
+pub const DEFAULT_COMPILER_MISMATCH_PATTERNS: &[&str] = &[
+    "different version of moonc",
+    "built with a different compiler",
+    "compiled by a different version",
+    "incompatible compiler version",
+];
+
+/// Messages of moon saying a build had nothing to recompile.
+pub const DEFAULT_FULLY_CACHED_PATTERNS: &[&str] = &["no work to do"];
+
+/// Case insensitive search of any of `patterns` in `output`.
+pub fn detect_compiler_mismatch(output: &str, patterns: &[String]) -> bool {
+    matches_any(output, patterns)
+}
+
+/// Case insensitive search of any of `patterns` in `output`, ignoring colors.
+pub fn matches_any(output: &str, patterns: &[String]) -> bool {
+    let output = strip_ansi(output).to_lowercase();
+    patterns
+        .iter()
+        .any(|p| !p.is_empty() && output.contains(&p.to_lowercase()))
+}
+
+#[test]
+fn core_version_of_version_all() {
+    let output = "moon 0.1.20250108 (1d4b5ba 2025-01-08) ~/.moon/bin/moon\n\
+                  moonc v0.1.20250108+6a5bc8f96 ~/.moon/bin/moonc\n\
+                  moonrun 0.1.20250108 (1d4b5ba 2025-01-08) ~/.moon/bin/moonrun\n\
+                  core 0.1.20250108+9ac1e7b (9ac1e7b 2025-01-08) ~/.moon/lib/core\n";
+    assert_eq!(
+        parse_core_version(output).as_deref(),
+        Some("0.1.20250108+9ac1e7b")
+    );
+    assert_eq!(
+        parse_core_version("Core version: 0.2.0\n").as_deref(),
+        Some("0.2.0")
+    );
+    assert_eq!(parse_core_version("moon 0.1.0\nmoonc v0.1.0\n"), None);
+    assert_eq!(parse_core_version("core\n"), None);
+    assert_eq!(parse_core_version("corely 1.0\n"), None);
+}
+
+#[test]
+fn fully_cached_build() {
+    let patterns: Vec<String> = DEFAULT_FULLY_CACHED_PATTERNS
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+    assert!(matches_any("Finished. moon: no work to do\n", &patterns));
+    assert!(matches_any(
+        "\x1b[32mmoon: No work to do\x1b[0m\n",
+        &patterns
+    ));
+    assert!(!matches_any(
+        "Finished. moon: ran 12 tasks, now up to date\n",
+        &patterns
+    ));
+    assert!(matches_any(
+        "Finished. moon: ran 0 tasks, now up to date\n",
+        &["ran 0 tasks".to_string()]
+    ));
+}
+
+#[test]
+fn compiler_mismatch_warning() {
+    let patterns: Vec<String> = DEFAULT_COMPILER_MISMATCH_PATTERNS
+        .iter()
+        .map(|p| p.to_string())
+        .collect();
+    let output = "Warning: .mi file of moonbitlang/core/builtin was generated by a \
+                  Different Version of moonc, rebuilding\nFinished.";
+    assert!(detect_compiler_mismatch(output, &patterns));
+    assert!(!detect_compiler_mismatch(
+        "Finished. moon: ran 3 tasks",
+        &patterns
+    ));
+    assert!(detect_compiler_mismatch(
+        "custom toolchain skew",
+        &["toolchain skew".to_string()]
+    ));
+}
+
+#[test]
+fn test_summary_formats() {
+    assert_eq!(
+        parse_test_summary("Total tests: 200, passed: 197, failed: 3."),
+        Some(TestSummary {
+            passed: 197,
+            failed: 3,
+            total: 200
+        })
+    );
+    let output = "running...\n\u{1b}[32mTotal tests\u{1b}[0m: 10, passed: 10, failed: 0.\n\
+                  total tests = 5 , PASSED = 4\n";
+    assert_eq!(
+        parse_test_summary(output),
+        Some(TestSummary {
+            passed: 14,
+            failed: 1,
+            total: 15
+        })
+    );
+    assert_eq!(parse_test_summary("Finished. moon: ran 3 tasks"), None);
+    assert_eq!(parse_test_summary("Total tests: many"), None);
+}
+
+#[test]
+fn failed_test_cases() {
+    let output =
+        "test alice/pkg/lib/a_test.mbt::add failed: FAILED: lib/a_test.mbt:3:3-3:27 `1 == 2`\n\
+                  [alice/pkg] test lib/b_test.mbt:12 (\"sub works\") failed\n\
+                  expect test failed at lib/b_test.mbt:14\n\
+                  \u{1b}[31mtest lib/c_wbtest.mbt::0 failed\u{1b}[0m\n\
+                  Total tests: 10, passed: 7, failed: 3.\n";
+    let failed = parse_failed_tests(output);
+    assert_eq!(
+        failed,
+        vec![
+            FailedTest {
+                file: "alice/pkg/lib/a_test.mbt".to_string(),
+                name: "add".to_string(),
+                message: "FAILED: lib/a_test.mbt:3:3-3:27 `1 == 2`".to_string(),
+            },
+            FailedTest {
+                file: "lib/b_test.mbt:12".to_string(),
+                name: "sub works".to_string(),
+                message: "expect test failed at lib/b_test.mbt:14".to_string(),
+            },
+            FailedTest {
+                file: "lib/c_wbtest.mbt".to_string(),
+                name: "0".to_string(),
+                message: String::new(),
+            },
+        ]
+    );
+    assert!(parse_failed_tests("Total tests: 3, passed: 3, failed: 0.").is_empty());
+}
+
+#[test]
+fn json_diagnostics() {
+    let output = r#"{"$message_type":"diagnostic","level":"warning","loc":{"path":"/w/lib/a.mbt","start":{"line":3,"col":7},"end":{"line":3,"col":8}},"message":"Warning: Unused variable 'x'","error_code":2}
+{"$message_type":"diagnostic","level":"error","loc":"/w/my-app/main.mbt:10:1-10:4","message":"The value identifier foo is unbound.","error_code":4020}
+{"$message_type":"diagnostic","level":"note","loc":"/w/lib/a.mbt:1:1-1:2","message":"note"}
+Finished. moon: ran 2 tasks, now up to date
+"#;
+    assert_eq!(
+        parse_json_diagnostics(output),
+        vec![
+            Diagnostic {
+                file: "/w/lib/a.mbt".to_string(),
+                line: 3,
+                level: DiagnosticLevel::Warning,
+                message: "Warning: Unused variable 'x'".to_string(),
+            },
+            Diagnostic {
+                file: "/w/my-app/main.mbt".to_string(),
+                line: 10,
+                level: DiagnosticLevel::Error,
+                message: "The value identifier foo is unbound.".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn updated_packages_of_moon_update() {
+    let output = "Updating a1b2c3d..e4f5a6b\n\
+                  Fast-forward\n \
+                  user/alice/json.index          | 1 +\n \
+                  user/bob/http.index            | 2 ++\n \
+                  user/bob/{old.index => new.index} | 0\n \
+                  3 files changed, 3 insertions(+)\n\
+                  create mode 100644 /home/u/.moon/registry/index/user/carol/cli.index\n\
+                  Registry index updated successfully\n";
+    assert_eq!(
+        parse_updated_packages(output),
+        BTreeSet::from([
+            "alice/json".to_string(),
+            "bob/http".to_string(),
+            "carol/cli".to_string(),
+        ])
+    );
+    assert!(parse_updated_packages("Registry index is already up-to-date").is_empty());
+}
+
+#[test]
+fn deps_of_moon_mod_json() {
+    let deps = parse_mod_deps(
+        r#"{
+            "name": "alice/app",
+            "version": "0.1.0",
+            "deps": {
+                "moonbitlang/x": "0.4.6",
+                "alice/local": { "path": "../local" },
+                "bob/pinned": { "version": "1.2.0" },
+                "bob/odd": { "git": "https://example.com" }
+            }
+        }"#,
+    );
+    assert_eq!(
+        deps,
+        BTreeMap::from([
+            ("alice/local".to_string(), "path:../local".to_string()),
+            ("bob/pinned".to_string(), "1.2.0".to_string()),
+            ("moonbitlang/x".to_string(), "0.4.6".to_string()),
+        ])
+    );
+    assert!(parse_mod_deps(r#"{ "name": "alice/app" }"#).is_empty());
+    assert!(parse_mod_deps("not json").is_empty());
+    assert_eq!(
+        parse_mod_version(r#"{ "name": "moonbitlang/x", "version": "0.4.7" }"#),
+        Some("0.4.7".to_string())
+    );
+}
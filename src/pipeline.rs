@@ -0,0 +1,157 @@
+use std::sync::{mpsc, Mutex};
+
+/// Runs `fetch` on `workers` threads ahead of `build`, which runs on the calling
+/// thread, so downloads overlap builds. At most `workers` fetched items wait
+/// for `build`. Items are built in the order they are fetched, the results are
+/// returned in the order of `items`.
+pub fn run_pipeline<T, F, R>(
+    items: Vec<T>,
+    workers: usize,
+    fetch: impl Fn(T) -> F + Sync,
+    mut build: impl FnMut(F) -> R,
+) -> Vec<R>
+where
+    T: Send,
+    F: Send,
+{
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let (tx, rx) = mpsc::sync_channel(workers.max(1));
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            let tx = tx.clone();
+            let (queue, fetch) = (&queue, &fetch);
+            scope.spawn(move || loop {
+                let Some((i, item)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                if tx.send((i, fetch(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+        rx.iter().map(|(i, fetched)| (i, build(fetched))).collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// How `stat` spreads its work over threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    /// one source and one command at a time
+    Serial,
+    /// fetch up to N sources on their own threads ahead of the one being built
+    SourcePipeline(usize),
+    /// one source at a time, with up to N of its backends built in parallel
+    BackendParallel(usize),
+}
+
+impl Schedule {
+    /// Runs `f` on every backend, on up to N threads with `BackendParallel(N)`,
+    /// returning the results in the order of `backends` whatever order they
+    /// complete in.
+    pub fn run_backends<T, R>(self, backends: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+    {
+        match self {
+            Schedule::BackendParallel(jobs) => {
+                let workers = jobs.clamp(1, backends.len().max(1));
+                let queue = Mutex::new(backends.into_iter().enumerate());
+                let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+                    let handles: Vec<_> = (0..workers)
+                        .map(|_| {
+                            let (queue, f) = (&queue, &f);
+                            scope.spawn(move || {
+                                let mut done = vec![];
+                                loop {
+                                    // not held while `f` runs
+                                    let Some((i, backend)) = queue.lock().unwrap().next() else {
+                                        break done;
+                                    };
+                                    done.push((i, f(backend)));
+                                }
+                            })
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .flat_map(|h| h.join().unwrap())
+                        .collect()
+                });
+                results.sort_by_key(|(i, _)| *i);
+                results.into_iter().map(|(_, r)| r).collect()
+            }
+            Schedule::Serial | Schedule::SourcePipeline(_) => backends.into_iter().map(f).collect(),
+        }
+    }
+}
+
+#[test]
+fn backend_concurrency_of_schedules() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    // the most backends seen running at once
+    let peak = |schedule: Schedule| {
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let results = schedule.run_backends(vec!["wasm", "wasm-gc", "js"], |backend| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            // `wasm-gc` finishes first, then `wasm`
+            std::thread::sleep(Duration::from_millis(100 + 30 * (7 - backend.len() as u64)));
+            running.fetch_sub(1, Ordering::SeqCst);
+            backend.len()
+        });
+        assert_eq!(results, [4, 7, 2]);
+        peak.into_inner()
+    };
+    assert_eq!(peak(Schedule::Serial), 1);
+    assert_eq!(peak(Schedule::SourcePipeline(2)), 1);
+    assert_eq!(peak(Schedule::BackendParallel(usize::MAX)), 3);
+    assert_eq!(peak(Schedule::BackendParallel(2)), 2);
+    assert_eq!(peak(Schedule::BackendParallel(1)), 1);
+}
+
+#[test]
+fn pipeline_over_fixtures_is_complete_and_ordered() {
+    use std::time::Duration;
+
+    // one fixture "package" per source, fetched by copying it to a work dir
+    let fixtures = tempfile::tempdir().unwrap();
+    let work = tempfile::tempdir().unwrap();
+    let sources: Vec<(usize, String)> = (0..6).map(|i| (i, format!("pkg{}", i))).collect();
+    for (i, name) in sources.iter() {
+        std::fs::write(fixtures.path().join(name), format!("fn main{}", i)).unwrap();
+    }
+
+    let mut build_order = vec![];
+    let results = run_pipeline(
+        sources.clone(),
+        3,
+        |(i, name)| {
+            // later sources are fetched faster, so they arrive out of order
+            std::thread::sleep(Duration::from_millis(10 * (6 - i as u64)));
+            let dst = work.path().join(&name);
+            std::fs::copy(fixtures.path().join(&name), &dst).map(|_| (i, dst))
+        },
+        |fetched| {
+            let (i, dst) = fetched.unwrap();
+            build_order.push(i);
+            (i, std::fs::read_to_string(dst).unwrap())
+        },
+    );
+
+    assert_eq!(
+        results,
+        (0..6)
+            .map(|i| (i, format!("fn main{}", i)))
+            .collect::<Vec<_>>()
+    );
+    let mut built = build_order.clone();
+    built.sort();
+    assert_eq!(built, (0..6).collect::<Vec<_>>());
+    assert!(run_pipeline(Vec::<usize>::new(), 2, |i| i, |i| i).is_empty());
+}
@@ -0,0 +1,110 @@
+use crate::{dashboard::MooncakeSource, pipeline::run_pipeline};
+
+/// A git url or mooncake version that failed its reachability check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unreachable {
+    pub source: usize,
+    /// git url or mooncakes.io package name
+    pub name: String,
+    /// `None` for a git url, whose revs are not checked
+    pub version: Option<String>,
+    pub error: String,
+}
+
+/// Checks every git url with `git` and every mooncake version with `mooncake`
+/// on `workers` threads, returning what is unreachable in the order of `sources`.
+pub fn preflight<G, M>(
+    sources: &[MooncakeSource],
+    workers: usize,
+    git: G,
+    mooncake: M,
+) -> Vec<Unreachable>
+where
+    G: Fn(&str) -> Result<(), String> + Sync,
+    M: Fn(&str, &str, bool) -> Result<(), String> + Sync,
+{
+    let checks: Vec<(&MooncakeSource, Option<&String>)> = sources
+        .iter()
+        .flat_map(|source| match source {
+            MooncakeSource::Git { .. } => vec![(source, None)],
+            MooncakeSource::MooncakesIO { version, .. } => {
+                version.iter().map(|v| (source, Some(v))).collect()
+            }
+        })
+        .collect();
+    let check = |(source, version): (&MooncakeSource, Option<&String>)| {
+        let result = match (source, version) {
+            (MooncakeSource::MooncakesIO { name, private, .. }, Some(version)) => {
+                mooncake(name, version, private.unwrap_or(false))
+            }
+            _ => git(source.name()),
+        };
+        result.err().map(|error| Unreachable {
+            source: source.get_index(),
+            name: source.name().to_string(),
+            version: version.cloned(),
+            error,
+        })
+    };
+    run_pipeline(checks, workers, check, |r| r)
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+#[test]
+fn unreachable_sources_of_mixed_config() {
+    use std::collections::BTreeSet;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = tmp.path().join("repo");
+    let git = |args: &[&str]| {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    git(&["init", "-q", repo.to_str().unwrap()]);
+    let url = |path: &std::path::Path| path.to_str().unwrap().to_string();
+    let sources = vec![
+        MooncakeSource::Git {
+            url: url(&repo),
+            rev: vec!["main".to_string()],
+            index: 0,
+            pre_build: None,
+        },
+        MooncakeSource::Git {
+            url: url(&tmp.path().join("typo")),
+            rev: vec!["main".to_string()],
+            index: 1,
+            pre_build: None,
+        },
+        MooncakeSource::MooncakesIO {
+            name: "alice/pkg".to_string(),
+            version: vec!["0.1.0".to_string(), "9.9.9".to_string()],
+            index: 2,
+            pre_build: None,
+            private: None,
+        },
+    ];
+    // the registry, as (name, version)
+    let published: BTreeSet<(&str, &str)> = [("alice/pkg", "0.1.0")].into_iter().collect();
+    let unreachable = preflight(
+        &sources,
+        3,
+        |url| crate::git::check_reachable(url).map_err(|e| e.to_string()),
+        |name, version, _| match published.contains(&(name, version)) {
+            true => Ok(()),
+            false => Err("HTTP 404".to_string()),
+        },
+    );
+    assert_eq!(
+        unreachable
+            .iter()
+            .map(|u| (u.source, u.version.as_deref()))
+            .collect::<Vec<_>>(),
+        [(1, None), (2, Some("9.9.9"))]
+    );
+    assert_eq!(unreachable[1].error, "HTTP 404");
+}
@@ -0,0 +1,759 @@
+//! Reports rendered from a `MoonBuildDashboard`, independent of the webapp.
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use colored::Colorize;
+
+use crate::{
+    cli::GroupBy,
+    dashboard::{Cell, MoonBuildDashboard, Status},
+};
+
+fn status_str(status: &Status) -> String {
+    format!("{:?}", status)
+}
+
+fn is_success(cell: &Cell) -> bool {
+    matches!(cell.result.status, Status::Success)
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn label_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// One line per cell.
+pub fn render_csv(dashboard: &MoonBuildDashboard) -> String {
+    let mut out =
+        String::from("toolchain,source,rev,command,backend,status,elapsed_ms,exit_code\n");
+    for cell in dashboard.cells() {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            cell.toolchain,
+            csv_field(cell.source),
+            csv_field(cell.rev),
+            cell.command,
+            csv_field(cell.backend),
+            status_str(&cell.result.status),
+            cell.result.elapsed,
+            cell.result
+                .exit_code
+                .map(|c| c.to_string())
+                .unwrap_or_default(),
+        );
+    }
+    out
+}
+
+/// One test suite per toolchain, one test case per cell.
+pub fn render_junit(dashboard: &MoonBuildDashboard) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    for toolchain in ["stable", "bleeding"] {
+        let cells: Vec<Cell> = dashboard
+            .cells()
+            .filter(|c| c.toolchain == toolchain)
+            .collect();
+        let failures = cells.iter().filter(|c| !is_success(c)).count();
+        let _ = writeln!(
+            out,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+            toolchain,
+            cells.len(),
+            failures
+        );
+        for cell in cells.iter() {
+            let _ = write!(
+                out,
+                "    <testcase classname=\"{}@{}\" name=\"{} {}\" time=\"{:.3}\"",
+                xml_escape(cell.source),
+                xml_escape(cell.rev),
+                cell.command,
+                xml_escape(cell.backend),
+                cell.result.elapsed as f64 / 1000.0
+            );
+            if is_success(cell) {
+                out.push_str("/>\n");
+            } else {
+                let _ = writeln!(
+                    out,
+                    ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                    status_str(&cell.result.status),
+                    xml_escape(&cell.result.stderr)
+                );
+            }
+        }
+        out.push_str("  </testsuite>\n");
+    }
+    out.push_str("</testsuites>\n");
+    out
+}
+
+/// Prometheus text exposition format.
+pub fn render_prometheus(dashboard: &MoonBuildDashboard) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP moon_dashboard_cell_success 1 if the cell succeeded, 0 otherwise\n");
+    out.push_str("# TYPE moon_dashboard_cell_success gauge\n");
+    let mut elapsed = String::new();
+    elapsed.push_str("# HELP moon_dashboard_cell_elapsed_ms elapsed time of the cell\n");
+    elapsed.push_str("# TYPE moon_dashboard_cell_elapsed_ms gauge\n");
+    for cell in dashboard.cells() {
+        let labels = format!(
+            "toolchain=\"{}\",source=\"{}\",rev=\"{}\",command=\"{}\",backend=\"{}\"",
+            cell.toolchain,
+            label_escape(cell.source),
+            label_escape(cell.rev),
+            cell.command,
+            label_escape(cell.backend)
+        );
+        let _ = writeln!(
+            out,
+            "moon_dashboard_cell_success{{{}}} {}",
+            labels,
+            is_success(&cell) as u8
+        );
+        let _ = writeln!(
+            elapsed,
+            "moon_dashboard_cell_elapsed_ms{{{}}} {}",
+            labels, cell.result.elapsed
+        );
+    }
+    out.push_str(&elapsed);
+    out
+}
+
+fn html_status(status: Option<&Status>) -> String {
+    match status {
+        Some(Status::Success) => "<td class=\"Success\">✓</td>".to_string(),
+        Some(Status::Failure) => "<td class=\"Failure\">✗</td>".to_string(),
+        Some(Status::Timeout) => "<td class=\"Timeout\">⏱</td>".to_string(),
+        None => "<td class=\"none\">–</td>".to_string(),
+    }
+}
+
+/// A standalone HTML page with inline CSS: a row per source, rev and command,
+/// a stable and a bleeding column per backend, and a diff column naming the
+/// backends that regressed or got fixed on bleeding.
+pub fn render_html(dashboard: &MoonBuildDashboard) -> String {
+    let mut backends: Vec<&str> = vec![];
+    // (source, rev, command, backend) -> status on stable and on bleeding
+    let mut statuses: BTreeMap<(&str, &str, &str, &str), [Option<&Status>; 2]> = BTreeMap::new();
+    for cell in dashboard.cells() {
+        if !backends.contains(&cell.backend) {
+            backends.push(cell.backend);
+        }
+        let toolchain = (cell.toolchain == "bleeding") as usize;
+        statuses
+            .entry((cell.source, cell.rev, cell.command, cell.backend))
+            .or_default()[toolchain] = Some(&cell.result.status);
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>moon build dashboard #{}</title>\n\
+         <style>table {{ border-collapse: collapse; }} \
+         td, th {{ padding: 2px 8px; border: 1px solid #ddd; }} \
+         .Success {{ background: #dfd; }} .Failure {{ background: #fdd; }} \
+         .Timeout {{ background: #ffd; }} \
+         .none {{ color: #999; }}</style>\n</head>\n<body>",
+        xml_escape(&dashboard.run_number)
+    );
+    let _ = writeln!(
+        out,
+        "<p>stable: {} / {}<br>bleeding: {} / {}</p>",
+        xml_escape(&dashboard.stable_toolchain_version.moon_version),
+        xml_escape(&dashboard.stable_toolchain_version.moonc_version),
+        xml_escape(&dashboard.bleeding_toolchain_version.moon_version),
+        xml_escape(&dashboard.bleeding_toolchain_version.moonc_version),
+    );
+    out.push_str("<table>\n<tr><th rowspan=\"2\">source</th><th rowspan=\"2\">rev</th><th rowspan=\"2\">command</th>");
+    for backend in backends.iter() {
+        let _ = write!(out, "<th colspan=\"2\">{}</th>", xml_escape(backend));
+    }
+    out.push_str("<th rowspan=\"2\">diff</th></tr>\n<tr>");
+    out.push_str(&"<th>stable</th><th>bleeding</th>".repeat(backends.len()));
+    out.push_str("</tr>\n");
+    for source in dashboard.sources.iter() {
+        for rev in source.revs() {
+            for command in ["check", "build", "test"] {
+                let mut row = format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td>",
+                    xml_escape(source.name()),
+                    xml_escape(rev),
+                    command
+                );
+                let mut diff = vec![];
+                for backend in backends.iter() {
+                    let [stable, bleeding] = statuses
+                        .get(&(source.name(), rev.as_str(), command, *backend))
+                        .copied()
+                        .unwrap_or_default();
+                    row.push_str(&html_status(stable));
+                    row.push_str(&html_status(bleeding));
+                    match (stable, bleeding) {
+                        (Some(Status::Success), Some(Status::Failure | Status::Timeout)) => {
+                            diff.push(format!("regressed {}", xml_escape(backend)))
+                        }
+                        (Some(Status::Failure | Status::Timeout), Some(Status::Success)) => {
+                            diff.push(format!("fixed {}", xml_escape(backend)))
+                        }
+                        _ => {}
+                    }
+                }
+                let _ = writeln!(out, "{}<td>{}</td></tr>", row, diff.join(", "));
+            }
+        }
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GroupCounts {
+    pub success: usize,
+    pub failure: usize,
+}
+
+/// Cell statuses of both toolchains counted per group.
+pub fn group_counts(dashboard: &MoonBuildDashboard, by: GroupBy) -> BTreeMap<String, GroupCounts> {
+    let mut groups: BTreeMap<String, GroupCounts> = BTreeMap::new();
+    for cell in dashboard.cells() {
+        let source = dashboard.sources.iter().find(|s| s.name() == cell.source);
+        let key = match by {
+            GroupBy::User => source.map(|s| s.user()).unwrap_or_default(),
+            GroupBy::Host => source.map(|s| s.host()).unwrap_or_default(),
+            GroupBy::Backend => cell.backend,
+        };
+        let counts = groups.entry(key.to_string()).or_default();
+        if is_success(&cell) {
+            counts.success += 1;
+        } else {
+            counts.failure += 1;
+        }
+    }
+    groups
+}
+
+/// One line per group, `group success/total`.
+pub fn render_groups(dashboard: &MoonBuildDashboard, by: GroupBy) -> String {
+    let mut out = String::new();
+    for (group, counts) in group_counts(dashboard, by) {
+        let _ = writeln!(
+            out,
+            "{}: {}/{} succeeded",
+            group,
+            counts.success,
+            counts.success + counts.failure
+        );
+    }
+    out
+}
+
+/// Passes of a cell over repeated runs of the same matrix.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PassRate {
+    pub passed: usize,
+    pub runs: usize,
+}
+
+impl PassRate {
+    fn rate(&self) -> f64 {
+        self.passed as f64 / self.runs as f64
+    }
+
+    fn is_mixed(&self) -> bool {
+        self.passed > 0 && self.passed < self.runs
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flakiness {
+    /// bleeding passes markedly less often than stable
+    ConsistentRegression,
+    /// the cell passes only sometimes, about as often on both toolchains
+    Flaky,
+}
+
+/// The least drop of the pass rate from stable to bleeding taken as a
+/// regression rather than noise.
+pub const REGRESSION_RATE_GAP: f64 = 0.5;
+
+/// Classifies a cell from its pass rates on each toolchain, `None` when it
+/// passes or fails alike on every run.
+pub fn classify(stable: PassRate, bleeding: PassRate) -> Option<Flakiness> {
+    if stable.runs == 0 || bleeding.runs == 0 {
+        return None;
+    }
+    if stable.rate() - bleeding.rate() >= REGRESSION_RATE_GAP {
+        Some(Flakiness::ConsistentRegression)
+    } else if stable.is_mixed() || bleeding.is_mixed() {
+        Some(Flakiness::Flaky)
+    } else {
+        None
+    }
+}
+
+type CellKey<'a> = (&'a str, &'a str, &'static str, &'a str);
+
+/// `(stable, bleeding)` pass rates of each `(source, rev, command, backend)`
+/// over `runs`.
+pub fn pass_rates(runs: &[MoonBuildDashboard]) -> BTreeMap<CellKey<'_>, (PassRate, PassRate)> {
+    let mut rates: BTreeMap<CellKey, (PassRate, PassRate)> = BTreeMap::new();
+    for cell in runs.iter().flat_map(|run| run.cells()) {
+        let (stable, bleeding) = rates
+            .entry((cell.source, cell.rev, cell.command, cell.backend))
+            .or_default();
+        let rate = if cell.toolchain == "stable" {
+            stable
+        } else {
+            bleeding
+        };
+        rate.runs += 1;
+        rate.passed += is_success(&cell) as usize;
+    }
+    rates
+}
+
+/// One line per cell that regressed or is flaky over `runs`.
+pub fn render_flaky(runs: &[MoonBuildDashboard]) -> String {
+    let mut out = String::new();
+    for ((source, rev, command, backend), (stable, bleeding)) in pass_rates(runs) {
+        let verdict = match classify(stable, bleeding) {
+            Some(Flakiness::ConsistentRegression) => "consistent regression",
+            Some(Flakiness::Flaky) => "flaky",
+            None => continue,
+        };
+        let _ = writeln!(
+            out,
+            "{}: {}@{} {} {} (stable {}/{}, bleeding {}/{})",
+            verdict,
+            source,
+            rev,
+            command,
+            backend,
+            stable.passed,
+            stable.runs,
+            bleeding.passed,
+            bleeding.runs
+        );
+    }
+    out
+}
+
+const GRID_NAME_WIDTH: usize = 32;
+
+fn grid_name(name: &str) -> String {
+    if name.chars().count() > GRID_NAME_WIDTH {
+        let truncated: String = name.chars().take(GRID_NAME_WIDTH - 1).collect();
+        format!("{}…", truncated)
+    } else {
+        format!("{:<width$}", name, width = GRID_NAME_WIDTH)
+    }
+}
+
+/// One row per toolchain and source rev, one glyph per command×backend:
+/// `✓` success, `✗` failure, `⏱` timeout, `–` not run.
+pub fn render_grid(dashboard: &MoonBuildDashboard, color: bool) -> String {
+    let mut columns: Vec<(&str, &str)> = vec![];
+    for cell in dashboard.cells() {
+        if !columns.contains(&(cell.command, cell.backend)) {
+            columns.push((cell.command, cell.backend));
+        }
+    }
+    let mut out = String::new();
+    for (i, (command, backend)) in columns.iter().enumerate() {
+        let _ = writeln!(out, "# column {}: {} {}", i + 1, command, backend);
+    }
+    for (toolchain, data) in [
+        ("stable", &dashboard.stable_release_data),
+        ("bleeding", &dashboard.bleeding_release_data),
+    ] {
+        for state in data {
+            let Some(source) = dashboard.source_of(state) else {
+                continue;
+            };
+            for (i, rev) in source.revs().iter().enumerate() {
+                let cbt = state.cbts.get(i).and_then(|c| c.as_ref());
+                let mut glyphs = String::new();
+                for (command, backend) in columns.iter() {
+                    let result = cbt.and_then(|cbt| {
+                        cbt.results()
+                            .find(|(c, b, _)| c == command && b == backend)
+                            .map(|(_, _, r)| r)
+                    });
+                    let glyph = match result.map(|r| &r.status) {
+                        Some(Status::Success) if color => "✓".green().to_string(),
+                        Some(Status::Failure) if color => "✗".red().to_string(),
+                        Some(Status::Timeout) if color => "⏱".yellow().to_string(),
+                        Some(Status::Success) => "✓".to_string(),
+                        Some(Status::Failure) => "✗".to_string(),
+                        Some(Status::Timeout) => "⏱".to_string(),
+                        None => "–".to_string(),
+                    };
+                    glyphs.push_str(&glyph);
+                }
+                let _ = writeln!(
+                    out,
+                    "{:<8} {} {}",
+                    toolchain,
+                    grid_name(&format!("{}@{}", source.name(), rev)),
+                    glyphs
+                );
+            }
+        }
+    }
+    out
+}
+
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|")
+}
+
+fn status_glyph(status: Option<&Status>) -> &'static str {
+    match status {
+        Some(Status::Success) => "✅",
+        Some(Status::Failure) => "❌",
+        Some(Status::Timeout) => "⏱",
+        None => "⏭",
+    }
+}
+
+/// A header with the toolchains and the total elapsed time, then a table per
+/// source with a row per toolchain, rev and command and a column per backend:
+/// ✅ success, ❌ failure, ⏱ timeout, ⏭ not run. The last row counts the successes.
+pub fn render_markdown(dashboard: &MoonBuildDashboard) -> String {
+    let mut backends: Vec<&str> = vec![];
+    for cell in dashboard.cells() {
+        if !backends.contains(&cell.backend) {
+            backends.push(cell.backend);
+        }
+    }
+    let elapsed: u64 = dashboard.cells().map(|c| c.result.elapsed).sum();
+    let mut out = String::new();
+    let _ = writeln!(out, "# moon build dashboard #{}\n", dashboard.run_number);
+    for (label, version) in [
+        ("stable", &dashboard.stable_toolchain_version),
+        ("bleeding", &dashboard.bleeding_toolchain_version),
+    ] {
+        let _ = writeln!(
+            out,
+            "- {}: `{}` / `{}`",
+            label, version.moon_version, version.moonc_version
+        );
+    }
+    let _ = writeln!(out, "- total elapsed: {:.1}s", elapsed as f64 / 1000.0);
+
+    for source in dashboard.sources.iter() {
+        let _ = writeln!(out, "\n## {}\n", markdown_escape(source.name()));
+        let _ = writeln!(
+            out,
+            "| toolchain | rev | command | {} |",
+            backends.join(" | ")
+        );
+        let _ = writeln!(out, "|---|---|---|{}", "---|".repeat(backends.len()));
+        let mut success = vec![0; backends.len()];
+        let mut runs = vec![0; backends.len()];
+        for (toolchain, data) in [
+            ("stable", &dashboard.stable_release_data),
+            ("bleeding", &dashboard.bleeding_release_data),
+        ] {
+            let Some(state) = data.iter().find(|s| s.source == source.get_index()) else {
+                continue;
+            };
+            for (i, rev) in source.revs().iter().enumerate() {
+                let cbt = state.cbts.get(i).and_then(|c| c.as_ref());
+                for command in ["check", "build", "test"] {
+                    let mut row =
+                        format!("| {} | {} | {} |", toolchain, markdown_escape(rev), command);
+                    for (j, backend) in backends.iter().enumerate() {
+                        let status = cbt.and_then(|cbt| {
+                            cbt.results()
+                                .find(|(c, b, _)| c == &command && b == backend)
+                                .map(|(_, _, r)| &r.status)
+                        });
+                        runs[j] += status.is_some() as usize;
+                        success[j] += matches!(status, Some(Status::Success)) as usize;
+                        let _ = write!(row, " {} |", status_glyph(status));
+                    }
+                    let _ = writeln!(out, "{}", row);
+                }
+            }
+        }
+        let totals: Vec<String> = success
+            .iter()
+            .zip(runs.iter())
+            .map(|(success, runs)| format!("{}/{}", success, runs))
+            .collect();
+        let _ = writeln!(out, "| **total** | | | {} |", totals.join(" | "));
+    }
+    out
+}
+
+/// Characters of stderr kept per failing cell in the step summary.
+const STEP_SUMMARY_TAIL: usize = 1000;
+
+/// The last `max` characters of `s`, `max` is at least 1.
+fn tail(s: &str, max: usize) -> &str {
+    let start = s
+        .char_indices()
+        .rev()
+        .nth(max.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    &s[start..]
+}
+
+/// `render_markdown` followed by a collapsed list of the failing cells of each
+/// source with the end of their stderr, for `$GITHUB_STEP_SUMMARY`.
+pub fn render_step_summary(dashboard: &MoonBuildDashboard) -> String {
+    let mut out = render_markdown(dashboard);
+    let mut failing: BTreeMap<&str, Vec<Cell>> = BTreeMap::new();
+    for cell in dashboard.cells().filter(|c| !is_success(c)) {
+        failing.entry(cell.source).or_default().push(cell);
+    }
+    if failing.is_empty() {
+        return out;
+    }
+    let _ = writeln!(
+        out,
+        "\n<details><summary>{} failing sources</summary>\n",
+        failing.len()
+    );
+    for (source, cells) in failing.iter() {
+        let _ = writeln!(out, "### {}\n", markdown_escape(source));
+        for cell in cells {
+            let stderr = cell.result.stderr.trim_end();
+            let tail = tail(stderr, STEP_SUMMARY_TAIL);
+            let _ = writeln!(
+                out,
+                "{} {} {} {}\n<pre>{}{}</pre>\n",
+                cell.toolchain,
+                markdown_escape(cell.rev),
+                cell.command,
+                cell.backend,
+                if tail.len() < stderr.len() { "…" } else { "" },
+                xml_escape(tail)
+            );
+        }
+    }
+    out.push_str("</details>\n");
+    out
+}
+
+/// Appends `render_step_summary` to the file named by `GITHUB_STEP_SUMMARY`
+/// in `env`, returning whether it was set.
+pub fn write_step_summary_with(
+    env: impl Fn(&str) -> Option<std::ffi::OsString>,
+    dashboard: &MoonBuildDashboard,
+) -> std::io::Result<bool> {
+    use std::io::Write as _;
+
+    let Some(path) = env("GITHUB_STEP_SUMMARY").filter(|p| !p.is_empty()) else {
+        return Ok(false);
+    };
+    let mut file = std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(render_step_summary(dashboard).as_bytes())?;
+    Ok(true)
+}
+
+pub fn write_step_summary(dashboard: &MoonBuildDashboard) -> std::io::Result<bool> {
+    write_step_summary_with(|key| std::env::var_os(key), dashboard)
+}
+
+#[test]
+fn render_grid_dimensions() {
+    let dashboard = crate::dashboard::sample_dashboard();
+    let grid = render_grid(&dashboard, false);
+    let rows: Vec<&str> = grid.lines().filter(|l| !l.starts_with('#')).collect();
+    // (2 git revs + 1 version) per toolchain
+    assert_eq!(rows.len(), 6);
+    for row in rows.iter() {
+        let glyphs = row.chars().skip(8 + 1 + GRID_NAME_WIDTH + 1);
+        assert_eq!(glyphs.count(), 9);
+    }
+    assert!(rows.iter().any(|r| r.ends_with("–––––––––")));
+    assert_eq!(grid.matches('✗').count(), 2);
+    assert_eq!(grid_name(&"x".repeat(40)).chars().count(), GRID_NAME_WIDTH);
+}
+
+#[test]
+fn render_every_format() {
+    let dashboard = crate::dashboard::sample_dashboard();
+    let cells = dashboard.cells().count();
+
+    let csv = render_csv(&dashboard);
+    assert_eq!(csv.lines().count(), cells + 1);
+    assert!(csv.contains("stable,alice/pkg,0.1.0,check,js,Failure,300,"));
+
+    let junit = render_junit(&dashboard);
+    assert_eq!(junit.matches("<testcase ").count(), cells);
+    assert_eq!(junit.matches("<failure ").count(), 2);
+
+    let prometheus = render_prometheus(&dashboard);
+    assert_eq!(
+        prometheus
+            .lines()
+            .filter(|l| l.starts_with("moon_dashboard_cell_success{"))
+            .count(),
+        cells
+    );
+
+    let markdown = render_markdown(&dashboard);
+    assert!(markdown.contains("- stable: `moon 0.1.0` / `v0.1.0`"));
+    assert!(markdown.contains("| toolchain | rev | command | wasm | wasm-gc | js |"));
+    // 2 git revs and 1 version per toolchain, a row per command
+    let rows = markdown
+        .lines()
+        .filter(|l| l.starts_with("| stable") || l.starts_with("| bleeding"))
+        .count();
+    assert_eq!(rows, 2 * 3 * 3);
+    assert_eq!(
+        markdown.matches('✅').count() + markdown.matches('❌').count(),
+        cells
+    );
+    assert_eq!(markdown.matches('❌').count(), 2);
+    assert!(markdown.contains("| stable | missing | check | ⏭ | ⏭ | ⏭ |"));
+    assert!(markdown.contains("| **total** | | | 6/6 | 6/6 | 4/6 |"));
+
+    let mut dashboard = dashboard;
+    dashboard.bleeding_release_data[1].cbts[0]
+        .as_mut()
+        .unwrap()
+        .build
+        .wasm
+        .status = Status::Failure;
+    let html = render_html(&dashboard);
+    // 2 git revs and 1 version, a row per command
+    assert_eq!(html.matches("<tr><td>").count(), 3 * 3);
+    assert_eq!(
+        html.matches("<td class=\"Success\">").count()
+            + html.matches("<td class=\"Failure\">").count(),
+        cells
+    );
+    assert!(html.contains("<th colspan=\"2\">wasm-gc</th>"));
+    assert!(html.contains("<td>alice/pkg</td><td>0.1.0</td><td>build</td><td class=\"Success\">✓</td><td class=\"Failure\">✗</td>"));
+    assert!(html.contains("<td>regressed wasm</td></tr>"));
+    assert!(html.ends_with("</html>\n"));
+}
+
+#[test]
+fn group_by_buckets() {
+    let dashboard = crate::dashboard::sample_dashboard();
+    let counts = |success, failure| GroupCounts { success, failure };
+
+    // the git rev and the mooncake version run 9 cells per toolchain each
+    assert_eq!(
+        group_counts(&dashboard, GroupBy::User),
+        BTreeMap::from([
+            ("alice".to_string(), counts(16, 2)),
+            ("moonbitlang".to_string(), counts(18, 0)),
+        ])
+    );
+    assert_eq!(
+        group_counts(&dashboard, GroupBy::Host),
+        BTreeMap::from([
+            ("github.com".to_string(), counts(18, 0)),
+            ("mooncakes.io".to_string(), counts(16, 2)),
+        ])
+    );
+    let by_backend = group_counts(&dashboard, GroupBy::Backend);
+    assert_eq!(by_backend.len(), 3);
+    assert_eq!(by_backend["js"], counts(10, 2));
+    assert_eq!(
+        render_groups(&dashboard, GroupBy::Backend).lines().next(),
+        Some("js: 10/12 succeeded")
+    );
+}
+
+#[test]
+fn flakiness_of_pass_rates() {
+    let rate = |passed, runs| PassRate { passed, runs };
+    let cases = [
+        ((5, 5), (0, 5), Some(Flakiness::ConsistentRegression)),
+        ((4, 5), (1, 5), Some(Flakiness::ConsistentRegression)),
+        ((5, 5), (3, 5), Some(Flakiness::Flaky)),
+        ((3, 5), (3, 5), Some(Flakiness::Flaky)),
+        ((2, 5), (5, 5), Some(Flakiness::Flaky)),
+        ((5, 5), (5, 5), None),
+        // broken alike on both toolchains
+        ((0, 5), (0, 5), None),
+        ((1, 1), (0, 0), None),
+    ];
+    for (stable, bleeding, expected) in cases {
+        assert_eq!(
+            classify(rate(stable.0, stable.1), rate(bleeding.0, bleeding.1)),
+            expected,
+            "stable {:?}, bleeding {:?}",
+            stable,
+            bleeding
+        );
+    }
+
+    // js check of alice/pkg fails on both toolchains, except on bleeding in the second run
+    let mut second = crate::dashboard::sample_dashboard();
+    second.bleeding_release_data[1].cbts[0]
+        .as_mut()
+        .unwrap()
+        .check
+        .js
+        .status = Status::Success;
+    let runs = [crate::dashboard::sample_dashboard(), second];
+    assert_eq!(
+        render_flaky(&runs),
+        "flaky: alice/pkg@0.1.0 check js (stable 0/2, bleeding 1/2)\n"
+    );
+    assert_eq!(render_flaky(&runs[..1]), "");
+}
+
+#[test]
+fn step_summary_of_failing_sources() {
+    let mut dashboard = crate::dashboard::sample_dashboard();
+    for state in dashboard.bleeding_release_data.iter_mut() {
+        if let Some(cbt) = state.cbts[0].as_mut() {
+            cbt.check.js.stderr = format!("{}error: type mismatch", "x".repeat(2000));
+        }
+    }
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("summary.md");
+    std::fs::write(&path, "previous step\n").unwrap();
+    let env = |vars: Vec<(&'static str, std::ffi::OsString)>| {
+        move |key: &str| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.clone())
+    };
+
+    assert!(!write_step_summary_with(env(vec![]), &dashboard).unwrap());
+    let summary_env = env(vec![("GITHUB_STEP_SUMMARY", path.clone().into())]);
+    assert!(write_step_summary_with(summary_env, &dashboard).unwrap());
+
+    let summary = std::fs::read_to_string(&path).unwrap();
+    assert!(summary.starts_with("previous step\n# moon build dashboard #1"));
+    assert!(summary.contains("## https://github.com/moonbitlang/core"));
+    assert!(summary.contains("<details><summary>1 failing sources</summary>"));
+    assert!(summary.contains("### alice/pkg\n"));
+    // only the end of a long stderr
+    assert!(summary.contains("…xxx"));
+    assert!(summary.contains("error: type mismatch</pre>"));
+    assert!(!summary.contains(&"x".repeat(STEP_SUMMARY_TAIL)));
+    assert_eq!(tail("abc", 2), "bc");
+    assert_eq!(tail("abc", 5), "abc");
+}
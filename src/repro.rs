@@ -0,0 +1,226 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{mooncakesio::home_with, util::toolchain_command};
+
+/// The execution environment of a run, to help reproducing it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ReproInfo {
+    pub arch: String,
+    pub os: String,
+    pub moon_path: Option<PathBuf>,
+    pub moonc_path: Option<PathBuf>,
+    pub git_path: Option<PathBuf>,
+    /// first line of the version output of moon, moonc and git, by program
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versions: BTreeMap<String, String>,
+    /// the effective moon home, see `mooncakesio::home`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moon_home: Option<PathBuf>,
+    /// relevant environment variables, secret looking values are redacted
+    pub env: BTreeMap<String, String>,
+}
+
+/// The tools a run needs, probed by `doctor`.
+pub const REQUIRED_TOOLS: &[&str] = &["moon", "moonc", "git"];
+
+/// One probed tool in the `doctor --json` output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolStatus {
+    pub name: String,
+    /// whether the tool runs and reports a version
+    pub found: bool,
+    pub path: Option<PathBuf>,
+    pub version: Option<String>,
+}
+
+/// The `doctor --json` output, `ready` when every required tool is found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub tools: Vec<ToolStatus>,
+    pub ready: bool,
+}
+
+const ENV_PREFIXES: &[&str] = &["MOON", "GITHUB_", "RUNNER_"];
+const ENV_NAMES: &[&str] = &["PATH", "HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY"];
+const SECRET_MARKERS: &[&str] = &["TOKEN", "SECRET", "PASSWORD", "KEY", "CREDENTIAL", "AUTH"];
+
+fn is_relevant(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    ENV_NAMES.contains(&upper.as_str()) || ENV_PREFIXES.iter().any(|p| upper.starts_with(p))
+}
+
+fn is_secret(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    SECRET_MARKERS.iter().any(|m| upper.contains(m))
+}
+
+/// Looks up `name` in the directories of a `PATH`-like value.
+pub fn which(name: &str, path: &OsStr) -> Option<PathBuf> {
+    let exe = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(path)
+        .map(|dir| dir.join(&exe))
+        .find(|p| Path::is_file(p))
+}
+
+/// First line of the version output of `program`, `None` when it can't be run.
+fn version_of(program: &str) -> Option<String> {
+    let (mut cmd, args): (_, &[&str]) = match program {
+        "moon" => (toolchain_command("moon"), &["version"]),
+        "moonc" => (toolchain_command("moonc"), &["-v"]),
+        _ => (std::process::Command::new(program), &["--version"]),
+    };
+    let output = cmd.args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout.lines().next().map(|line| line.trim().to_string())
+}
+
+impl ReproInfo {
+    pub fn capture() -> Self {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        Self::capture_with(&path, std::env::vars(), version_of)
+    }
+
+    /// The snapshot for a `PATH` of `path` and environment `vars`, asking
+    /// `version` for the version of each program.
+    pub fn capture_with(
+        path: &OsStr,
+        vars: impl Iterator<Item = (String, String)>,
+        version: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let vars: Vec<(String, String)> = vars.collect();
+        let var = |key: &str| {
+            vars.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| OsString::from(value))
+        };
+        let moon_home = home_with(var, home::home_dir).ok();
+        let versions = REQUIRED_TOOLS
+            .iter()
+            .filter_map(|program| Some((program.to_string(), version(program)?)))
+            .collect();
+        let env = vars
+            .into_iter()
+            .filter(|(name, _)| is_relevant(name))
+            .map(|(name, value)| {
+                if is_secret(&name) {
+                    (name, "<redacted>".to_string())
+                } else {
+                    (name, value)
+                }
+            })
+            .collect();
+        ReproInfo {
+            arch: std::env::consts::ARCH.to_string(),
+            os: std::env::consts::OS.to_string(),
+            moon_path: which("moon", path),
+            moonc_path: which("moonc", path),
+            git_path: which("git", path),
+            versions,
+            moon_home,
+            env,
+        }
+    }
+
+    /// The status of every required tool, from the probes of this snapshot.
+    pub fn doctor_report(&self) -> DoctorReport {
+        let tools: Vec<ToolStatus> = REQUIRED_TOOLS
+            .iter()
+            .map(|name| {
+                let path = match *name {
+                    "moon" => &self.moon_path,
+                    "moonc" => &self.moonc_path,
+                    _ => &self.git_path,
+                };
+                let version = self.versions.get(*name).cloned();
+                ToolStatus {
+                    name: name.to_string(),
+                    found: version.is_some(),
+                    path: path.clone(),
+                    version,
+                }
+            })
+            .collect();
+        DoctorReport {
+            ready: tools.iter().all(|tool| tool.found),
+            tools,
+        }
+    }
+}
+
+#[test]
+fn repro_info_snapshot() {
+    let tmp = tempfile::tempdir().unwrap();
+    for name in ["moon", "moonc"] {
+        let exe = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+        std::fs::write(tmp.path().join(exe), "").unwrap();
+    }
+    let path = std::env::join_paths([tmp.path()]).unwrap();
+    let vars = [
+        ("MOON_HOME", "/home/ci/.moon"),
+        ("GITHUB_TOKEN", "ghp_secret"),
+        ("UNRELATED", "x"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()));
+
+    let version = |program: &str| match program {
+        "moon" => Some("moon 0.1.20250108".to_string()),
+        "moonc" => Some("v0.1.20250108+6a5bc8f96".to_string()),
+        _ => None,
+    };
+    let info = ReproInfo::capture_with(&path, vars, version);
+    assert_eq!(info.moon_path.unwrap().parent(), Some(tmp.path()));
+    assert_eq!(info.moonc_path.unwrap().parent(), Some(tmp.path()));
+    assert!(info.git_path.is_none());
+    assert_eq!(info.env["MOON_HOME"], "/home/ci/.moon");
+    assert_eq!(info.env["GITHUB_TOKEN"], "<redacted>");
+    assert!(!info.env.contains_key("UNRELATED"));
+    assert!(!info.arch.is_empty());
+    assert_eq!(info.versions["moon"], "moon 0.1.20250108");
+    assert_eq!(info.versions["moonc"], "v0.1.20250108+6a5bc8f96");
+    assert!(!info.versions.contains_key("git"));
+    assert_eq!(info.moon_home, Some(PathBuf::from("/home/ci/.moon")));
+}
+
+#[test]
+fn doctor_report_json() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = std::env::join_paths([tmp.path()]).unwrap();
+    let report = |version: fn(&str) -> Option<String>| {
+        let info = ReproInfo::capture_with(&path, std::iter::empty(), version);
+        serde_json::to_value(info.doctor_report()).unwrap()
+    };
+
+    let json = report(|program| Some(format!("{} 1.0", program)));
+    assert_eq!(json["ready"], true);
+    let names: Vec<&str> = json["tools"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tool| tool["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, REQUIRED_TOOLS);
+    assert_eq!(json["tools"][1]["version"], "moonc 1.0");
+
+    // git is missing
+    let json = report(|program| (program != "git").then(|| format!("{} 1.0", program)));
+    assert_eq!(json["ready"], false);
+    assert_eq!(
+        json["tools"].as_array().unwrap().len(),
+        REQUIRED_TOOLS.len()
+    );
+    assert_eq!(
+        json["tools"][2],
+        serde_json::json!({"name": "git", "found": false, "path": null, "version": null})
+    );
+    assert_eq!(json["tools"][0]["found"], true);
+}
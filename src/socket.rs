@@ -0,0 +1,80 @@
+use std::{
+    io::Write,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+use crate::dashboard::BuildState;
+
+/// Streams each completed `BuildState` as a JSON line to every viewer
+/// connected to a Unix socket. Viewers may come and go during the run.
+pub struct ResultSocket {
+    path: PathBuf,
+    listener: UnixListener,
+    viewers: Vec<UnixStream>,
+}
+
+impl ResultSocket {
+    pub fn bind(path: &Path) -> std::io::Result<ResultSocket> {
+        // a socket left over from a previous run
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(ResultSocket {
+            path: path.to_path_buf(),
+            listener,
+            viewers: vec![],
+        })
+    }
+
+    fn accept(&mut self) {
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(false).is_ok() {
+                self.viewers.push(stream);
+            }
+        }
+    }
+
+    /// Sends `state` to the connected viewers, dropping the disconnected ones.
+    pub fn send(&mut self, state: &BuildState) {
+        self.accept();
+        let Ok(mut line) = serde_json::to_string(state) else {
+            return;
+        };
+        line.push('\n');
+        self.viewers
+            .retain_mut(|viewer| viewer.write_all(line.as_bytes()).is_ok());
+    }
+}
+
+impl Drop for ResultSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[test]
+fn viewer_receives_one_line_per_source() {
+    use std::io::{BufRead, BufReader};
+
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("results.sock");
+    let mut socket = ResultSocket::bind(&path).unwrap();
+
+    let gone = UnixStream::connect(&path).unwrap();
+    let viewer = UnixStream::connect(&path).unwrap();
+    socket.send(&BuildState::new(0));
+    drop(gone);
+    for i in 1..3 {
+        socket.send(&BuildState::new(i));
+    }
+    drop(socket);
+    assert!(!path.exists());
+
+    let lines: Vec<String> = BufReader::new(viewer).lines().map(|l| l.unwrap()).collect();
+    assert_eq!(lines.len(), 3);
+    let last: BuildState = serde_json::from_str(&lines[2]).unwrap();
+    assert_eq!(last.source, 2);
+}
@@ -0,0 +1,217 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::dashboard::{nearest_rank, MoonBuildDashboard, Status};
+
+/// Successes over executed cells.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SuccessRate {
+    pub success: usize,
+    pub runs: usize,
+}
+
+impl SuccessRate {
+    pub fn rate(&self) -> f64 {
+        match self.runs {
+            0 => 0.0,
+            runs => self.success as f64 / runs as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ElapsedStats {
+    pub median: u64,
+    pub p95: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceTime {
+    pub source: String,
+    /// total `elapsed` of the source per run, averaged over the runs it is in
+    pub elapsed: u64,
+}
+
+/// Aggregates of one or more dashboards, both toolchains together.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct RunStats {
+    pub runs: usize,
+    /// by command, then backend
+    pub success_rates: BTreeMap<&'static str, BTreeMap<String, SuccessRate>>,
+    /// over the commands that ran, `None` when none did
+    pub elapsed: Option<ElapsedStats>,
+    /// slowest first
+    pub slowest: Vec<SourceTime>,
+}
+
+/// Computes `RunStats` over `dashboards`, keeping the `top` slowest sources.
+/// Skipped commands are recorded with zero `elapsed` and left out of the
+/// elapsed percentiles.
+pub fn run_stats(dashboards: &[MoonBuildDashboard], top: usize) -> RunStats {
+    let mut stats = RunStats {
+        runs: dashboards.len(),
+        ..Default::default()
+    };
+    let mut elapsed = vec![];
+    // source -> (sum of its totals per run, runs)
+    let mut by_source: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for dashboard in dashboards {
+        let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+        for cell in dashboard.cells() {
+            let rate = stats
+                .success_rates
+                .entry(cell.command)
+                .or_default()
+                .entry(cell.backend.to_string())
+                .or_default();
+            rate.runs += 1;
+            rate.success += matches!(cell.result.status, Status::Success) as usize;
+            *totals.entry(cell.source).or_default() += cell.result.elapsed;
+            if cell.result.elapsed > 0 {
+                elapsed.push(cell.result.elapsed);
+            }
+        }
+        for (source, total) in totals {
+            let entry = by_source.entry(source).or_default();
+            entry.0 += total;
+            entry.1 += 1;
+        }
+    }
+    elapsed.sort_unstable();
+    stats.elapsed = (!elapsed.is_empty()).then(|| ElapsedStats {
+        median: nearest_rank(&elapsed, 50),
+        p95: nearest_rank(&elapsed, 95),
+    });
+    let mut slowest: Vec<SourceTime> = by_source
+        .into_iter()
+        .map(|(source, (total, runs))| SourceTime {
+            source: source.to_string(),
+            elapsed: total / runs,
+        })
+        .collect();
+    slowest.sort_by(|a, b| b.elapsed.cmp(&a.elapsed).then(a.source.cmp(&b.source)));
+    slowest.truncate(top);
+    stats.slowest = slowest;
+    stats
+}
+
+/// `stats` as aligned plain text tables.
+pub fn render_stats(stats: &RunStats) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{} runs\n", stats.runs);
+    let backends: Vec<&String> = {
+        let mut backends: Vec<&String> = stats
+            .success_rates
+            .values()
+            .flat_map(|b| b.keys())
+            .collect();
+        backends.sort();
+        backends.dedup();
+        backends
+    };
+    let _ = write!(out, "{:<8}", "command");
+    for backend in backends.iter() {
+        let _ = write!(out, " {:>16}", backend);
+    }
+    out.push('\n');
+    for command in ["check", "build", "test"] {
+        let Some(rates) = stats.success_rates.get(command) else {
+            continue;
+        };
+        let _ = write!(out, "{:<8}", command);
+        for backend in backends.iter() {
+            let cell = match rates.get(*backend) {
+                Some(r) => format!("{:.1}% {}/{}", r.rate() * 100.0, r.success, r.runs),
+                None => "-".to_string(),
+            };
+            let _ = write!(out, " {:>16}", cell);
+        }
+        out.push('\n');
+    }
+    if let Some(elapsed) = stats.elapsed {
+        let _ = writeln!(
+            out,
+            "\nelapsed: median {}ms, p95 {}ms",
+            elapsed.median, elapsed.p95
+        );
+    }
+    if !stats.slowest.is_empty() {
+        out.push_str("\nslowest sources:\n");
+        for s in stats.slowest.iter() {
+            let _ = writeln!(out, "{:>10}ms  {}", s.elapsed, s.source);
+        }
+    }
+    out
+}
+
+#[test]
+fn stats_over_runs() {
+    let first = crate::dashboard::sample_dashboard();
+    let mut second = crate::dashboard::sample_dashboard();
+    // a skipped command in the second run, out of the percentiles
+    second.stable_release_data[0].cbts[0]
+        .as_mut()
+        .unwrap()
+        .test
+        .js
+        .elapsed = 0;
+    second.bleeding_release_data[1].cbts[0]
+        .as_mut()
+        .unwrap()
+        .build
+        .wasm
+        .status = Status::Failure;
+
+    let stats = run_stats(&[first, second], 1);
+    assert_eq!(stats.runs, 2);
+    // 2 runs, 2 toolchains, 2 built revs/versions
+    assert_eq!(
+        stats.success_rates["check"]["js"],
+        SuccessRate {
+            success: 4,
+            runs: 8
+        }
+    );
+    assert_eq!(
+        stats.success_rates["build"]["wasm"],
+        SuccessRate {
+            success: 7,
+            runs: 8
+        }
+    );
+    // 24 cells each of 100ms and 200ms, 23 of 300ms
+    let elapsed = stats.elapsed.unwrap();
+    assert_eq!((elapsed.median, elapsed.p95), (200, 300));
+    assert_eq!(stats.slowest.len(), 1);
+    // 2 toolchains x 3 commands x 600ms per run, core lost its skipped 300ms
+    assert_eq!(
+        stats.slowest[0],
+        SourceTime {
+            source: "alice/pkg".to_string(),
+            elapsed: 3600,
+        }
+    );
+
+    let table = render_stats(&stats);
+    assert!(table.contains("check"));
+    assert!(table.contains("50.0% 4/8"));
+    assert!(table.contains("elapsed: median 200ms, p95 300ms"));
+
+    let json = serde_json::to_value(&stats).unwrap();
+    assert_eq!(json["elapsed"]["p95"], 300);
+    assert_eq!(run_stats(&[], 10), RunStats::default());
+}
+
+#[test]
+fn p95_of_non_skipped_results() {
+    let mut dashboard = crate::dashboard::sample_dashboard();
+    // of 36 executed cells, all but 5 with elapsed 1..=5 are skipped
+    for (i, result) in dashboard.results_mut().enumerate() {
+        result.elapsed = if i < 5 { i as u64 + 1 } else { 0 };
+    }
+    let elapsed = run_stats(&[dashboard], 0).elapsed.unwrap();
+    // with the 31 zeros, the p95 would be 4 and the median 0
+    assert_eq!(elapsed.p95, 5);
+    assert_eq!(elapsed.median, 3);
+}
@@ -0,0 +1,72 @@
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// Copies `reader` to every writer as it arrives and returns all bytes read.
+/// Each chunk is flushed right away, so the writers keep what was read before
+/// an error.
+pub fn tee(mut reader: impl Read, writers: &mut [&mut dyn Write]) -> std::io::Result<Vec<u8>> {
+    let mut captured = vec![];
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(captured),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        captured.extend_from_slice(&buf[..n]);
+        for writer in writers.iter_mut() {
+            writer.write_all(&buf[..n])?;
+            writer.flush()?;
+        }
+    }
+}
+
+/// Opens `<stem>.<stream>.log` for appending, with a header naming the command.
+pub fn open_log(stem: &Path, stream: &str, header: &str) -> std::io::Result<File> {
+    let path = format!("{}.{}.log", stem.display(), stream);
+    let mut file = File::options().create(true).append(true).open(path)?;
+    writeln!(file, "# {}", header)?;
+    Ok(file)
+}
+
+#[test]
+fn log_keeps_output_of_interrupted_capture() {
+    /// Yields `data`, then fails like a killed pipe.
+    struct Broken<'a> {
+        data: &'a [u8],
+    }
+    impl Read for Broken<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            let n = self.data.len().min(buf.len()).min(4);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    let tmp = tempfile::tempdir().unwrap();
+    let stem = tmp.path().join("0_build_js");
+    let mut file = open_log(&stem, "stdout", "moon build --target js").unwrap();
+    let mut live = vec![];
+    let reader = Broken {
+        data: b"Compiling...\n",
+    };
+    assert!(tee(reader, &mut [&mut file, &mut live]).is_err());
+    drop(file);
+
+    assert_eq!(live, b"Compiling...\n");
+    assert_eq!(
+        std::fs::read_to_string(tmp.path().join("0_build_js.stdout.log")).unwrap(),
+        "# moon build --target js\nCompiling...\n"
+    );
+
+    let captured = tee(&b"done\n"[..], &mut []).unwrap();
+    assert_eq!(captured, b"done\n");
+}
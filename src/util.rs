@@ -1,4 +1,20 @@
-use std::{io::Write, string::FromUtf8Error};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+    string::FromUtf8Error,
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::{FixedOffset, Local};
+
+use crate::{
+    cli::PhaseOrder,
+    dashboard::{ExecuteResult, MoonBuildDashboard, Status, ToolChainLabel, ToolChainVersion},
+    parse::{parse_core_version, parse_mod_deps, parse_mod_version},
+};
 
 #[derive(Debug, thiserror::Error)]
 #[error("moon operations error: {cmd}")]
@@ -16,11 +32,317 @@ pub enum MoonOpsErrorKind {
     IOError(#[from] std::io::Error),
     #[error("utf8 error")]
     FromUtf8Error(#[from] FromUtf8Error),
+    #[error("network failure: {0}")]
+    Network(String),
+}
+
+impl MoonOpsError {
+    /// Whether the command failed to reach the network, see `is_network_failure`.
+    pub fn is_network_failure(&self) -> bool {
+        matches!(self.kind, MoonOpsErrorKind::Network(_))
+    }
+}
+
+/// Messages of git, curl and the moon downloader when the host is offline or
+/// unreachable, matched case insensitively.
+const NETWORK_FAILURE_PATTERNS: &[&str] = &[
+    "could not resolve host",
+    "failed to lookup address",
+    "temporary failure in name resolution",
+    "dns error",
+    "network is unreachable",
+    "connection refused",
+    "connection reset",
+    "connection timed out",
+    "operation timed out",
+    "error sending request",
+    "failed to connect",
+    "unable to access",
+];
+
+/// Whether `stderr` of a failed command says the network could not be reached.
+pub fn is_network_failure(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    NETWORK_FAILURE_PATTERNS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+}
+
+/// The exit code of `status`, or on Unix the negated number of the signal
+/// that killed the process, so a crash tells apart from a compile error.
+pub fn exit_code_of(status: &std::process::ExitStatus) -> Option<i32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status
+            .code()
+            .or_else(|| status.signal().map(|signal| -signal))
+    }
+    #[cfg(not(unix))]
+    status.code()
+}
+
+/// The OS name used for the output directory under `webapp/public`.
+pub fn current_os() -> &'static str {
+    #[cfg(target_os = "windows")]
+    let os = "windows";
+    #[cfg(target_os = "linux")]
+    let os = "linux";
+    #[cfg(target_os = "macos")]
+    let os = "mac";
+    os
+}
+
+/// CPU time spent by the children of this process that have been waited for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTime {
+    pub user_ms: u64,
+    pub sys_ms: u64,
+}
+
+impl CpuTime {
+    /// `getrusage(RUSAGE_CHILDREN)`, `None` when unavailable or not on Unix.
+    pub fn children() -> Option<CpuTime> {
+        #[cfg(unix)]
+        {
+            let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+            // SAFETY: getrusage only writes to the buffer it is given
+            let usage = unsafe {
+                if libc::getrusage(libc::RUSAGE_CHILDREN, usage.as_mut_ptr()) != 0 {
+                    return None;
+                }
+                usage.assume_init()
+            };
+            let ms = |t: libc::timeval| t.tv_sec as u64 * 1000 + t.tv_usec as u64 / 1000;
+            Some(CpuTime {
+                user_ms: ms(usage.ru_utime),
+                sys_ms: ms(usage.ru_stime),
+            })
+        }
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// The time spent since `earlier`. Children of other threads waited for in
+    /// the meantime are counted too, e.g. with `--backend-parallel`.
+    pub fn since(self, earlier: CpuTime) -> CpuTime {
+        CpuTime {
+            user_ms: self.user_ms.saturating_sub(earlier.user_ms),
+            sys_ms: self.sys_ms.saturating_sub(earlier.sys_ms),
+        }
+    }
+}
+
+static PROXY: OnceLock<String> = OnceLock::new();
+static ALLOW_STALE_REGISTRY: OnceLock<bool> = OnceLock::new();
+
+/// Sets `--allow-stale-registry`, only the first call has an effect.
+pub fn set_allow_stale_registry(allow: bool) {
+    let _ = ALLOW_STALE_REGISTRY.set(allow);
+}
+
+/// Sets the `--proxy` used by `command_with_proxy`, only the first call has an effect.
+pub fn set_proxy(proxy: String) {
+    let _ = PROXY.set(proxy);
+}
+
+/// Routes the traffic of `cmd` through `proxy`, overriding the proxy variables
+/// of the environment. `NO_PROXY` is left untouched.
+pub fn apply_proxy<'a>(cmd: &'a mut Command, proxy: Option<&str>) -> &'a mut Command {
+    if let Some(proxy) = proxy {
+        for var in ["HTTP_PROXY", "HTTPS_PROXY", "http_proxy", "https_proxy"] {
+            cmd.env(var, proxy);
+        }
+    }
+    cmd
+}
+
+/// A command for network operations (curl, git, installers). Without `--proxy`,
+/// these tools already honor `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY`.
+pub fn command_with_proxy(program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    apply_proxy(&mut cmd, PROXY.get().map(|p| p.as_str()));
+    cmd
+}
+
+/// The prefix of the temp dirs of the crate, see `sweep_stale`.
+pub const DEFAULT_TMP_PREFIX: &str = "moon-dashboard-";
+
+static TMP_PREFIX: OnceLock<String> = OnceLock::new();
+
+/// Sets the `--tmp-prefix` used by `tempdir_under`, only the first call has an effect.
+pub fn set_tmp_prefix(prefix: String) {
+    let _ = TMP_PREFIX.set(prefix);
+}
+
+fn tmp_prefix() -> &'static str {
+    TMP_PREFIX.get().map_or(DEFAULT_TMP_PREFIX, |p| p.as_str())
+}
+
+static MOCK_MOON: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the `--mock-moon` script, only the first call has an effect.
+pub fn set_mock_moon(script: PathBuf) {
+    let _ = MOCK_MOON.set(script);
+}
+
+fn mock_moon() -> Option<&'static Path> {
+    MOCK_MOON.get().map(|script| script.as_path())
+}
+
+/// A `moon` or `moonc` command, see `toolchain_command_with`.
+pub fn toolchain_command(program: &str) -> Command {
+    toolchain_command_with(mock_moon(), program)
+}
+
+/// A `moon` or `moonc` command. With a `--mock-moon` script, the script is run
+/// instead with `program` as its first argument, e.g. `script moonc -v`.
+pub fn toolchain_command_with(mock: Option<&Path>, program: &str) -> Command {
+    match mock {
+        Some(script) => {
+            let mut cmd = Command::new(script);
+            cmd.arg(program);
+            cmd
+        }
+        None => Command::new(program),
+    }
+}
+
+/// Replaces `path` with what `write` writes to a temp file next to it, so a
+/// failed or killed write leaves the previous content intact.
+pub fn write_atomic_with(
+    path: &Path,
+    write: impl FnOnce(&mut std::fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    write(tmp.as_file_mut())?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    write_atomic_with(path, |file| file.write_all(contents.as_ref()))
+}
+
+/// Runs `f` holding an exclusive advisory lock on `<path>.lock`, to serialize
+/// read-modify-write cycles of `path` across processes.
+pub fn with_file_lock<T>(path: &Path, f: impl FnOnce() -> T) -> std::io::Result<T> {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock = std::fs::File::create(lock_path)?;
+    lock.lock()?;
+    let result = f();
+    lock.unlock()?;
+    Ok(result)
+}
+
+/// Orders versions by semver, falling back to string order for versions
+/// that aren't valid semver.
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// The greatest of `versions` by `compare_versions`, whatever their order in
+/// the registry index. Without `include_prerelease`, semver prereleases are
+/// left out unless there is nothing else.
+pub fn latest_version(versions: &[String], include_prerelease: bool) -> Option<&String> {
+    let is_prerelease = |v: &String| semver::Version::parse(v).is_ok_and(|v| !v.pre.is_empty());
+    let latest = |include_prerelease: bool| {
+        versions
+            .iter()
+            .filter(|v| include_prerelease || !is_prerelease(v))
+            .max_by(|a, b| compare_versions(a, b))
+    };
+    latest(include_prerelease).or_else(|| latest(true))
+}
+
+/// Timestamp recorded as `start_time` of an `ExecuteResult`, in UTC+8.
+pub fn start_time_now() -> String {
+    Local::now()
+        .with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+        .format("%Y-%m-%d %H:%M:%S.%3f")
+        .to_string()
+}
+
+/// Builds a throwaway package in its own temp dir so moon's core cache is
+/// populated before timings are taken.
+pub fn warm_core() -> std::io::Result<ExecuteResult> {
+    let dir = tempdir_under(None)?;
+    std::fs::write(
+        dir.path().join("moon.mod.json"),
+        r#"{ "name": "dashboard/warmup" }"#,
+    )?;
+    std::fs::write(dir.path().join("moon.pkg.json"), "{}")?;
+    std::fs::write(dir.path().join("lib.mbt"), "pub fn warmup() -> Int { 1 }\n")?;
+    Ok(run_pre_build(dir.path(), "moon build"))
+}
+
+/// Runs `warm` unless the toolchain `label` was already warmed up.
+pub fn warm_core_once<E>(
+    warmup: &mut BTreeMap<String, ExecuteResult>,
+    label: &str,
+    warm: impl FnOnce() -> Result<ExecuteResult, E>,
+) -> Result<(), E> {
+    if !warmup.contains_key(label) {
+        warmup.insert(label.to_string(), warm()?);
+    }
+    Ok(())
+}
+
+/// Runs the pre-build command of a source with the platform shell.
+pub fn run_pre_build(workdir: &Path, command: &str) -> ExecuteResult {
+    let start_time = start_time_now();
+    let start = Instant::now();
+    #[cfg(unix)]
+    let output = std::process::Command::new("sh")
+        .current_dir(workdir)
+        .args(["-c", command])
+        .output();
+    #[cfg(target_os = "windows")]
+    let output = std::process::Command::new("cmd")
+        .current_dir(workdir)
+        .args(["/C", command])
+        .output();
+    let elapsed = start.elapsed().as_millis() as u64;
+    let (status, stdout, stderr, exit_code) = match output {
+        Ok(output) => (
+            if output.status.success() {
+                Status::Success
+            } else {
+                Status::Failure
+            },
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+            exit_code_of(&output.status),
+        ),
+        Err(e) => (Status::Failure, String::new(), e.to_string(), None),
+    };
+    ExecuteResult {
+        status,
+        start_time,
+        elapsed,
+        stdout,
+        stderr,
+        exit_code,
+        ..Default::default()
+    }
 }
 
 pub fn get_moon_version() -> Result<String, MoonOpsError> {
+    get_moon_version_with(mock_moon())
+}
+
+pub fn get_moon_version_with(mock: Option<&Path>) -> Result<String, MoonOpsError> {
     let cmd = "moon version";
-    let output = std::process::Command::new("moon")
+    let output = toolchain_command_with(mock, "moon")
         .args(["version"])
         .output()
         .map_err(|e| MoonOpsError {
@@ -41,8 +363,12 @@ pub fn get_moon_version() -> Result<String, MoonOpsError> {
 }
 
 pub fn get_moonc_version() -> Result<String, MoonOpsError> {
+    get_moonc_version_with(mock_moon())
+}
+
+pub fn get_moonc_version_with(mock: Option<&Path>) -> Result<String, MoonOpsError> {
     let cmd = "moonc -v";
-    let output = std::process::Command::new("moonc")
+    let output = toolchain_command_with(mock, "moonc")
         .args(["-v"])
         .output()
         .map_err(|e| MoonOpsError {
@@ -62,9 +388,23 @@ pub fn get_moonc_version() -> Result<String, MoonOpsError> {
     Ok(version.trim().to_string())
 }
 
+/// The core version reported by `moon version --all`, `None` when it cannot be
+/// told, so an older `moon` never fails the run.
+pub fn get_core_version() -> Option<String> {
+    let output = toolchain_command("moon")
+        .args(["version", "--all"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_core_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(unix)]
 fn install_unix_release(args: &[&str]) -> Result<(), MoonOpsError> {
     let curl_cmd = "curl -fsSL https://cli.moonbitlang.com/install/unix.sh";
-    let output = std::process::Command::new("curl")
+    let output = command_with_proxy("curl")
         .args(["-fsSL", "https://cli.moonbitlang.com/install/unix.sh"])
         .output()
         .map_err(|e| MoonOpsError {
@@ -79,7 +419,7 @@ fn install_unix_release(args: &[&str]) -> Result<(), MoonOpsError> {
     }
 
     let bash_cmd = format!("bash {}", args.join(" "));
-    let mut cmd = std::process::Command::new("bash")
+    let mut cmd = command_with_proxy("bash")
         .args(args)
         .stdin(std::process::Stdio::piped())
         .spawn()
@@ -107,7 +447,7 @@ fn install_unix_release(args: &[&str]) -> Result<(), MoonOpsError> {
     }
 
     let version_cmd = "moon version --all";
-    let output = std::process::Command::new("moon")
+    let output = toolchain_command("moon")
         .args(["version", "--all"])
         .output()
         .map_err(|e| MoonOpsError {
@@ -120,18 +460,22 @@ fn install_unix_release(args: &[&str]) -> Result<(), MoonOpsError> {
             kind: MoonOpsErrorKind::ReturnNonZero(output.status),
         });
     }
-    println!("Version command output: {}", String::from_utf8_lossy(&output.stdout));
+    println!(
+        "Version command output: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
 
     Ok(())
 }
 
-fn install_windows_release(is_bleeding: bool) -> Result<(), MoonOpsError> {
+#[cfg(target_os = "windows")]
+fn install_windows_release(version: Option<&str>) -> Result<(), MoonOpsError> {
     let cmd_str = "Set-ExecutionPolicy RemoteSigned -Scope CurrentUser; irm https://cli.moonbitlang.com/install/powershell.ps1 | iex";
-    let mut cmd = std::process::Command::new("powershell");
+    let mut cmd = command_with_proxy("powershell");
     cmd.args(["-Command", cmd_str]);
-    
-    if is_bleeding {
-        cmd.env("MOONBIT_INSTALL_VERSION", "bleeding");
+
+    if let Some(version) = version {
+        cmd.env("MOONBIT_INSTALL_VERSION", version);
     }
 
     let output = cmd.output().map_err(|e| MoonOpsError {
@@ -147,7 +491,7 @@ fn install_windows_release(is_bleeding: bool) -> Result<(), MoonOpsError> {
     }
 
     let version_cmd = "moon version --all";
-    let output = std::process::Command::new("moon")
+    let output = toolchain_command("moon")
         .args(["version", "--all"])
         .output()
         .map_err(|e| MoonOpsError {
@@ -160,18 +504,39 @@ fn install_windows_release(is_bleeding: bool) -> Result<(), MoonOpsError> {
             kind: MoonOpsErrorKind::ReturnNonZero(output.status),
         });
     }
-    println!("Version command output: {}", String::from_utf8_lossy(&output.stdout));
+    println!(
+        "Version command output: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
 
     Ok(())
 }
 
+/// `script install <channel>` in place of the installer, with `--mock-moon`.
+fn install_mock_release(script: &Path, channel: &str) -> Result<(), MoonOpsError> {
+    let cmd = format!("{} install {}", script.display(), channel);
+    let status = Command::new(script)
+        .args(["install", channel])
+        .status()
+        .map_err(|e| MoonOpsError {
+            cmd: cmd.clone(),
+            kind: MoonOpsErrorKind::IOError(e),
+        })?;
+    if !status.success() {
+        return Err(MoonOpsError {
+            cmd,
+            kind: MoonOpsErrorKind::ReturnNonZero(status),
+        });
+    }
+    Ok(())
+}
 
 pub fn install_stable_release() -> Result<(), MoonOpsError> {
     #[cfg(unix)]
     let res = install_unix_release(&["-s"]);
 
     #[cfg(target_os = "windows")]
-    let res = install_windows_release(false);
+    let res = install_windows_release(None);
 
     res
 }
@@ -181,14 +546,31 @@ pub fn install_bleeding_release() -> Result<(), MoonOpsError> {
     let res = install_unix_release(&["-s", "bleeding"]);
 
     #[cfg(target_os = "windows")]
-    let res = install_windows_release(true);
+    let res = install_windows_release(Some("bleeding"));
+
+    res
+}
+
+/// Installs an exact toolchain version, e.g. `0.1.20241231+ba15a9a4`.
+pub fn install_pinned_release(version: &str) -> Result<(), MoonOpsError> {
+    #[cfg(unix)]
+    let res = install_unix_release(&["-s", version]);
+
+    #[cfg(target_os = "windows")]
+    let res = install_windows_release(Some(version));
 
     res
 }
 
-pub fn moon_update() -> Result<(), MoonOpsError> {
+/// Runs `moon update`, returning its stdout and stderr.
+pub fn moon_update() -> Result<String, MoonOpsError> {
+    moon_update_with(mock_moon())
+}
+
+pub fn moon_update_with(mock: Option<&Path>) -> Result<String, MoonOpsError> {
     let update_cmd = "moon update";
-    let output = std::process::Command::new("moon")
+    let mut cmd = toolchain_command_with(mock, "moon");
+    let output = apply_proxy(&mut cmd, PROXY.get().map(|p| p.as_str()))
         .args(["update"])
         .output()
         .map_err(|e| MoonOpsError {
@@ -196,10 +578,559 @@ pub fn moon_update() -> Result<(), MoonOpsError> {
             kind: MoonOpsErrorKind::IOError(e),
         })?;
     if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let kind = if is_network_failure(&stderr) {
+            MoonOpsErrorKind::Network(stderr.trim().to_string())
+        } else {
+            MoonOpsErrorKind::ReturnNonZero(output.status)
+        };
         return Err(MoonOpsError {
             cmd: update_cmd.to_string(),
-            kind: MoonOpsErrorKind::ReturnNonZero(output.status),
+            kind,
         });
     }
+    Ok(format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ))
+}
+
+/// The warning to print instead of failing when `update` could not reach the
+/// network and a stale registry index is allowed, `None` if it must fail.
+pub fn stale_registry_warning(error: &MoonOpsError, allow_stale: bool) -> Option<String> {
+    (allow_stale && error.is_network_failure()).then(|| {
+        format!(
+            "warning: {} ({}), using the existing registry index",
+            error, error.kind
+        )
+    })
+}
+
+/// Passes `result` of `moon update` through, except network failures with
+/// `allow_stale`, which are printed as a warning and give an empty output.
+pub fn tolerate_stale_registry(
+    result: Result<String, MoonOpsError>,
+    allow_stale: bool,
+) -> Result<String, MoonOpsError> {
+    match result {
+        Err(e) => match stale_registry_warning(&e, allow_stale) {
+            Some(warning) => {
+                eprintln!("{}", warning);
+                Ok(String::new())
+            }
+            None => Err(e),
+        },
+        ok => ok,
+    }
+}
+
+/// Files under `target/<target>/release` of `workdir`, relative to it and sorted.
+pub fn list_artifacts(workdir: &Path, target: &str) -> Vec<String> {
+    let dir = workdir.join("target").join(target).join("release");
+    let mut artifacts: Vec<String> = walkdir::WalkDir::new(&dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(&dir).ok()?;
+            Some(path.to_string_lossy().replace('\\', "/"))
+        })
+        .collect();
+    artifacts.sort();
+    artifacts
+}
+
+/// The dependency versions of the module in `workdir`, the ones moon resolved
+/// under `.mooncakes` when it got there, else the ones in `moon.mod.json`.
+pub fn resolved_deps(workdir: &Path) -> BTreeMap<String, String> {
+    let read = |path: PathBuf| std::fs::read_to_string(path).ok();
+    let Some(module) = read(workdir.join("moon.mod.json")) else {
+        return BTreeMap::new();
+    };
+    let mut deps = parse_mod_deps(&module);
+    for (name, version) in deps.iter_mut() {
+        let resolved = workdir.join(".mooncakes").join(name).join("moon.mod.json");
+        if let Some(resolved) = read(resolved).as_deref().and_then(parse_mod_version) {
+            *version = resolved;
+        }
+    }
+    deps
+}
+
+/// A temp dir under `root`, or under the default temp location when `root` is
+/// unset or the dir cannot be created there, e.g. because it is full. Its name
+/// starts with the `--tmp-prefix`.
+pub fn tempdir_under(root: Option<&Path>) -> std::io::Result<tempfile::TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(tmp_prefix());
+    if let Some(root) = root {
+        match builder.tempdir_in(root) {
+            Ok(dir) => return Ok(dir),
+            Err(e) => eprintln!(
+                "failed to create a build dir under {}, using the default temp dir: {}",
+                root.display(),
+                e
+            ),
+        }
+    }
+    builder.tempdir()
+}
+
+/// Removes the dirs directly under `dir` whose name starts with `prefix` and
+/// that were last modified more than `max_age` before `now`, as left behind by
+/// a crashed run. Returns the removed dirs. The dirs of a run still going are
+/// kept as long as `max_age` exceeds its longest build.
+pub fn sweep_stale(
+    dir: &Path,
+    prefix: &str,
+    max_age: Duration,
+    now: SystemTime,
+) -> std::io::Result<Vec<PathBuf>> {
+    if prefix.is_empty() {
+        // would match every dir of the temp location
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "refusing to sweep with an empty prefix",
+        ));
+    }
+    let mut removed = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with(prefix) {
+            continue;
+        }
+        // entries of other users or racing sweeps are skipped, not fatal
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        let stale = meta
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if meta.is_dir() && stale && std::fs::remove_dir_all(entry.path()).is_ok() {
+            removed.push(entry.path());
+        }
+    }
+    Ok(removed)
+}
+
+/// Copies the tree at `src` into `dst`, leaving out the build output in `target`.
+pub fn copy_dir(src: &Path, dst: &Path) -> std::io::Result<()> {
+    let entries = walkdir::WalkDir::new(src)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() != 1 || entry.file_name() != "target");
+    for entry in entries {
+        let entry = entry.map_err(std::io::Error::other)?;
+        let to = dst.join(entry.path().strip_prefix(src).unwrap());
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&to)?;
+        } else if entry.file_type().is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(std::fs::read_link(entry.path())?, &to)?;
+        } else {
+            std::fs::copy(entry.path(), &to)?;
+        }
+    }
     Ok(())
 }
+
+/// A toolchain the matrix is run against, and how to get it in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Phase {
+    pub label: ToolChainLabel,
+    pub install: bool,
+    pub update: bool,
+    /// version to install instead of the latest one of `label`
+    pub pin: Option<String>,
+}
+
+/// Stable and bleeding in `order`, or a single phase with the `moon`/`moonc`
+/// already on `PATH` for `--use-local-toolchain`.
+pub fn plan_phases(
+    skip_install: bool,
+    skip_update: bool,
+    use_local_toolchain: bool,
+    order: PhaseOrder,
+) -> Vec<Phase> {
+    if use_local_toolchain {
+        return vec![Phase {
+            label: ToolChainLabel::Local,
+            install: false,
+            update: false,
+            pin: None,
+        }];
+    }
+    let labels = match order {
+        PhaseOrder::StableFirst => [ToolChainLabel::Stable, ToolChainLabel::Bleeding],
+        PhaseOrder::BleedingFirst => [ToolChainLabel::Bleeding, ToolChainLabel::Stable],
+    };
+    labels
+        .into_iter()
+        .map(|label| Phase {
+            label,
+            install: !skip_install,
+            update: !skip_update,
+            pin: None,
+        })
+        .collect()
+}
+
+/// The installer version of a recorded toolchain, e.g. `0.1.20241231+ba15a9a4`
+/// for moonc `v0.1.20241231+ba15a9a4`.
+pub fn installable_version(version: &ToolChainVersion) -> Option<String> {
+    let v = version.moonc_version.split_whitespace().next()?;
+    let v = v.strip_prefix('v').unwrap_or(v);
+    v.starts_with(|c: char| c.is_ascii_digit())
+        .then(|| v.to_string())
+}
+
+/// Pins each phase to the toolchain `prior` recorded for its label, to rerun
+/// against the toolchains of a past run. Returns the labels without a usable
+/// recorded version.
+pub fn pin_phases(phases: &mut [Phase], prior: &MoonBuildDashboard) -> Vec<ToolChainLabel> {
+    let mut missing = vec![];
+    for phase in phases.iter_mut() {
+        let recorded = match phase.label {
+            ToolChainLabel::Bleeding => &prior.bleeding_toolchain_version,
+            _ => &prior.stable_toolchain_version,
+        };
+        match installable_version(recorded) {
+            Some(version) => {
+                phase.install = true;
+                phase.pin = Some(version);
+            }
+            None => missing.push(phase.label),
+        }
+    }
+    missing
+}
+
+impl Phase {
+    /// Installs and updates the toolchain of this phase as planned, returning
+    /// the output of `moon update` when it ran.
+    pub fn prepare(&self) -> Result<Option<String>, MoonOpsError> {
+        self.prepare_mocked(mock_moon())
+    }
+
+    /// `prepare` with the toolchain of the `mock` script, if any.
+    pub fn prepare_mocked(&self, mock: Option<&Path>) -> Result<Option<String>, MoonOpsError> {
+        self.prepare_with(
+            |phase| match (mock, &phase.pin, phase.label) {
+                (_, _, ToolChainLabel::Local) => Ok(()),
+                (Some(script), Some(version), _) => install_mock_release(script, version),
+                (Some(script), None, label) => install_mock_release(script, label.key()),
+                (None, Some(version), _) => install_pinned_release(version),
+                (None, None, ToolChainLabel::Stable) => install_stable_release(),
+                (None, None, ToolChainLabel::Bleeding) => install_bleeding_release(),
+            },
+            || {
+                let allow_stale = ALLOW_STALE_REGISTRY.get().copied().unwrap_or(false);
+                tolerate_stale_registry(moon_update_with(mock), allow_stale)
+            },
+        )
+    }
+
+    pub fn prepare_with<U, E>(
+        &self,
+        install: impl FnOnce(&Phase) -> Result<(), E>,
+        update: impl FnOnce() -> Result<U, E>,
+    ) -> Result<Option<U>, E> {
+        if self.install {
+            install(self)?;
+        }
+        if self.update {
+            return update().map(Some);
+        }
+        Ok(None)
+    }
+}
+
+#[test]
+fn pre_build_creates_needed_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let result = run_pre_build(tmp.path(), "echo generated > gen.mbt");
+    assert!(matches!(result.status, Status::Success));
+    assert!(tmp.path().join("gen.mbt").exists());
+
+    let result = run_pre_build(tmp.path(), "exit 3");
+    assert!(matches!(result.status, Status::Failure));
+    assert_eq!(result.exit_code, Some(3));
+}
+
+#[test]
+fn proxy_is_applied_to_command() {
+    let mut cmd = Command::new("curl");
+    apply_proxy(&mut cmd, Some("http://proxy.example.com:3128"));
+    let envs: Vec<_> = cmd.get_envs().collect();
+    assert_eq!(envs.len(), 4);
+    assert!(envs
+        .iter()
+        .all(|(_, v)| *v == Some(std::ffi::OsStr::new("http://proxy.example.com:3128"))));
+
+    let mut cmd = Command::new("curl");
+    apply_proxy(&mut cmd, None);
+    assert_eq!(cmd.get_envs().count(), 0);
+}
+
+#[test]
+fn warm_core_once_per_label() {
+    let mut warmup = BTreeMap::new();
+    let mut runs = 0;
+    for label in ["stable", "stable", "bleeding", "stable", "bleeding"] {
+        warm_core_once(&mut warmup, label, || {
+            runs += 1;
+            Ok::<_, std::io::Error>(crate::dashboard::sample_result(Status::Success, 1))
+        })
+        .unwrap();
+    }
+    assert_eq!(runs, 2);
+    assert_eq!(
+        warmup.keys().collect::<Vec<_>>(),
+        vec!["bleeding", "stable"]
+    );
+}
+
+#[test]
+fn local_toolchain_skips_install() {
+    let prepare = |phases: Vec<Phase>| {
+        let calls = std::cell::RefCell::new(vec![]);
+        for phase in phases {
+            phase
+                .prepare_with(
+                    |phase| {
+                        let version = phase.pin.as_deref().unwrap_or("latest");
+                        calls
+                            .borrow_mut()
+                            .push(format!("install {:?} {}", phase.label, version));
+                        Ok::<_, ()>(())
+                    },
+                    || {
+                        calls.borrow_mut().push("update".to_string());
+                        Ok(())
+                    },
+                )
+                .unwrap();
+        }
+        calls.into_inner()
+    };
+    assert_eq!(
+        prepare(plan_phases(false, false, false, PhaseOrder::StableFirst)),
+        [
+            "install Stable latest",
+            "update",
+            "install Bleeding latest",
+            "update"
+        ]
+    );
+    assert_eq!(
+        prepare(plan_phases(true, false, false, PhaseOrder::StableFirst)),
+        ["update", "update"]
+    );
+
+    let phases = plan_phases(false, false, true, PhaseOrder::BleedingFirst);
+    assert_eq!(phases.len(), 1);
+    assert_eq!(phases[0].label, ToolChainLabel::Local);
+    assert!(prepare(phases).is_empty());
+
+    // `--select-toolchain-from`
+    let mut prior = crate::dashboard::sample_dashboard();
+    prior.stable_toolchain_version.moonc_version = "v0.1.20241231+ba15a9a4".to_string();
+    prior.bleeding_toolchain_version.moonc_version = "v0.1.20250101+0123abcd".to_string();
+    let mut phases = plan_phases(true, true, false, PhaseOrder::BleedingFirst);
+    assert!(pin_phases(&mut phases, &prior).is_empty());
+    assert_eq!(
+        prepare(phases),
+        [
+            "install Bleeding 0.1.20250101+0123abcd",
+            "install Stable 0.1.20241231+ba15a9a4"
+        ]
+    );
+    prior.bleeding_toolchain_version.moonc_version = String::new();
+    let mut phases = plan_phases(true, true, false, PhaseOrder::StableFirst);
+    assert_eq!(pin_phases(&mut phases, &prior), [ToolChainLabel::Bleeding]);
+}
+
+#[test]
+fn artifacts_of_target_tree() {
+    let tmp = tempfile::tempdir().unwrap();
+    let release = tmp.path().join("target/wasm-gc/release");
+    std::fs::create_dir_all(release.join("build/main")).unwrap();
+    std::fs::create_dir_all(release.join("build/lib")).unwrap();
+    std::fs::write(release.join("build/main/main.wasm"), "").unwrap();
+    std::fs::write(release.join("build/lib/lib.core"), "").unwrap();
+    std::fs::create_dir_all(tmp.path().join("target/js/release")).unwrap();
+    std::fs::write(tmp.path().join("target/js/release/main.js"), "").unwrap();
+
+    assert_eq!(
+        list_artifacts(tmp.path(), "wasm-gc"),
+        vec!["build/lib/lib.core", "build/main/main.wasm"]
+    );
+    assert!(list_artifacts(tmp.path(), "wasm").is_empty());
+}
+
+#[test]
+fn phase_order() {
+    let labels = |order| {
+        plan_phases(false, true, false, order)
+            .iter()
+            .map(|phase| phase.label)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        labels(PhaseOrder::StableFirst),
+        [ToolChainLabel::Stable, ToolChainLabel::Bleeding]
+    );
+    assert_eq!(
+        labels(PhaseOrder::BleedingFirst),
+        [ToolChainLabel::Bleeding, ToolChainLabel::Stable]
+    );
+}
+
+#[test]
+fn interrupted_write_keeps_original() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("repos.txt");
+    std::fs::write(&path, "moonbitlang/core 0.1.0\n").unwrap();
+
+    let result = write_atomic_with(&path, |file| {
+        file.write_all(b"moonbitlang/co")?;
+        Err(std::io::ErrorKind::Interrupted.into())
+    });
+    assert!(result.is_err());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "moonbitlang/core 0.1.0\n"
+    );
+    // the temp file is cleaned up
+    assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+
+    with_file_lock(&path, || write_atomic(&path, "moonbitlang/core 0.2.0\n"))
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "moonbitlang/core 0.2.0\n"
+    );
+}
+
+#[test]
+fn tempdir_under_build_root() {
+    let root = tempfile::tempdir().unwrap();
+    let dir = tempdir_under(Some(root.path())).unwrap();
+    assert_eq!(dir.path().parent(), Some(root.path()));
+
+    let missing = root.path().join("missing");
+    let dir = tempdir_under(Some(&missing)).unwrap();
+    assert!(!dir.path().starts_with(&missing));
+    assert!(dir.path().is_dir());
+}
+
+#[test]
+fn deps_resolved_under_mooncakes() {
+    let tmp = tempfile::tempdir().unwrap();
+    assert!(resolved_deps(tmp.path()).is_empty());
+    std::fs::write(
+        tmp.path().join("moon.mod.json"),
+        r#"{ "name": "alice/app", "deps": { "moonbitlang/x": "0.4.6", "bob/y": "1.0.0" } }"#,
+    )
+    .unwrap();
+    let x = tmp.path().join(".mooncakes").join("moonbitlang").join("x");
+    std::fs::create_dir_all(&x).unwrap();
+    std::fs::write(
+        x.join("moon.mod.json"),
+        r#"{ "name": "moonbitlang/x", "version": "0.4.7" }"#,
+    )
+    .unwrap();
+    let deps = resolved_deps(tmp.path());
+    assert_eq!(deps["moonbitlang/x"], "0.4.7");
+    // not resolved, as declared
+    assert_eq!(deps["bob/y"], "1.0.0");
+}
+
+#[test]
+fn sweep_stale_tempdirs() {
+    let hour = Duration::from_secs(3600);
+    let tmp = tempfile::tempdir().unwrap();
+    let ours = tempdir_under(Some(tmp.path())).unwrap().into_path();
+    assert!(ours
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .starts_with(DEFAULT_TMP_PREFIX));
+    std::fs::write(ours.join("moon.mod.json"), "{}").unwrap();
+    let foreign = tmp.path().join("other-tool-abc");
+    std::fs::create_dir(&foreign).unwrap();
+    let file = tmp.path().join(format!("{}file", DEFAULT_TMP_PREFIX));
+    std::fs::write(&file, "").unwrap();
+
+    // nothing is old enough yet
+    let later = SystemTime::now() + 2 * hour;
+    let removed = sweep_stale(tmp.path(), DEFAULT_TMP_PREFIX, 3 * hour, later).unwrap();
+    assert!(removed.is_empty());
+    assert!(ours.is_dir());
+
+    let removed = sweep_stale(tmp.path(), DEFAULT_TMP_PREFIX, hour, later).unwrap();
+    assert_eq!(removed, std::slice::from_ref(&ours));
+    assert!(!ours.exists());
+    assert!(foreign.is_dir());
+    assert!(file.is_file());
+
+    assert!(sweep_stale(tmp.path(), "", hour, later).is_err());
+}
+
+#[test]
+fn offline_update_with_stale_registry_allowed() {
+    let offline = || {
+        MoonOpsError {
+        cmd: "moon update".to_string(),
+        kind: MoonOpsErrorKind::Network(
+            "fatal: unable to access 'https://mooncakes.io/git/index/': Could not resolve host: mooncakes.io"
+                .to_string(),
+        ),
+    }
+    };
+    assert!(is_network_failure(
+        "error: failed to connect to mooncakes.io: Connection refused"
+    ));
+    assert!(!is_network_failure("error: index is corrupted"));
+
+    let warning = stale_registry_warning(&offline(), true).unwrap();
+    assert!(warning.starts_with("warning: "), "{}", warning);
+    assert!(warning.contains("Could not resolve host"), "{}", warning);
+    assert_eq!(tolerate_stale_registry(Err(offline()), true).unwrap(), "");
+
+    // without the flag, or for other failures, it is still an error
+    assert!(stale_registry_warning(&offline(), false).is_none());
+    assert!(tolerate_stale_registry(Err(offline()), false).is_err());
+    let other = MoonOpsError {
+        cmd: "moon update".to_string(),
+        kind: MoonOpsErrorKind::IOError(std::io::ErrorKind::NotFound.into()),
+    };
+    assert!(tolerate_stale_registry(Err(other), true).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn exit_code_of_signal() {
+    let status = |script: &str| Command::new("sh").args(["-c", script]).status().unwrap();
+    assert_eq!(exit_code_of(&status("exit 0")), Some(0));
+    assert_eq!(exit_code_of(&status("exit 1")), Some(1));
+    assert_eq!(exit_code_of(&status("kill -SEGV $$")), Some(-11));
+    assert_eq!(exit_code_of(&status("kill -9 $$")), Some(-9));
+}
+
+#[test]
+fn latest_version_by_semver() {
+    let versions = |vs: &[&str]| vs.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+    let index_order = versions(&["0.10.0", "0.9.0", "1.0.0-rc.1", "0.2.0"]);
+    assert_eq!(latest_version(&index_order, true).unwrap(), "1.0.0-rc.1");
+    assert_eq!(latest_version(&index_order, false).unwrap(), "0.10.0");
+    // nothing else to pick
+    let prereleases = versions(&["0.1.0-alpha", "0.1.0-beta"]);
+    assert_eq!(latest_version(&prereleases, false).unwrap(), "0.1.0-beta");
+    // string order for tags that aren't semver
+    assert_eq!(latest_version(&versions(&["b", "a"]), false).unwrap(), "b");
+    assert!(latest_version(&[], true).is_none());
+}
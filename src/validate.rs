@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use crate::dashboard::{BuildState, MoonBuildDashboard, ToolChainLabel};
+
+/// Checks the invariants of a dashboard, returning one message per violation.
+pub fn validate(dashboard: &MoonBuildDashboard) -> Vec<String> {
+    let mut violations = vec![];
+    let n = dashboard.sources.len();
+
+    let mut indices = BTreeSet::new();
+    for (i, source) in dashboard.sources.iter().enumerate() {
+        let index = source.get_index();
+        if index >= n {
+            violations.push(format!("source #{} has index {} out of range", i, index));
+        }
+        if !indices.insert(index) {
+            violations.push(format!("duplicate source index {}", index));
+        }
+    }
+
+    let data: [(&str, &Vec<BuildState>); 2] = [
+        ("stable", &dashboard.stable_release_data),
+        ("bleeding", &dashboard.bleeding_release_data),
+    ];
+    for (toolchain, data) in data {
+        // `--use-local-toolchain` runs no bleeding phase
+        let single_phase = toolchain == "bleeding"
+            && dashboard.bleeding_toolchain_version.label == ToolChainLabel::Local;
+        if data.len() != n && !(single_phase && data.is_empty()) {
+            violations.push(format!(
+                "{} data has {} entries for {} sources",
+                toolchain,
+                data.len(),
+                n
+            ));
+        }
+        for state in data {
+            let Some(source) = dashboard.source_of(state) else {
+                violations.push(format!(
+                    "{} data refers to unknown source {}",
+                    toolchain, state.source
+                ));
+                continue;
+            };
+            if state.cbts.len() != source.revs().len() {
+                violations.push(format!(
+                    "{} data of source {} has {} results for {} revs",
+                    toolchain,
+                    state.source,
+                    state.cbts.len(),
+                    source.revs().len()
+                ));
+            }
+        }
+    }
+    violations
+}
+
+#[test]
+fn validate_fixtures() {
+    use crate::dashboard::{sample_dashboard, MooncakeSource};
+
+    assert!(validate(&sample_dashboard()).is_empty());
+
+    let mut dashboard = sample_dashboard();
+    dashboard.bleeding_release_data.pop();
+    assert_eq!(validate(&dashboard).len(), 1);
+
+    dashboard.bleeding_release_data.clear();
+    dashboard.bleeding_toolchain_version.label = ToolChainLabel::Local;
+    assert!(validate(&dashboard).is_empty());
+
+    let mut dashboard = sample_dashboard();
+    dashboard.sources[1] = dashboard.sources[1].clone().with_index(0);
+    let violations = validate(&dashboard);
+    assert!(violations.iter().any(|v| v.contains("duplicate")));
+
+    let mut dashboard = sample_dashboard();
+    dashboard.sources[1] = dashboard.sources[1].clone().with_index(7);
+    let violations = validate(&dashboard);
+    assert!(violations.iter().any(|v| v.contains("out of range")));
+    assert!(violations.iter().any(|v| v.contains("unknown source 1")));
+
+    let mut dashboard = sample_dashboard();
+    if let MooncakeSource::Git { rev, .. } = &mut dashboard.sources[0] {
+        rev.pop();
+    }
+    assert_eq!(validate(&dashboard).len(), 2);
+}
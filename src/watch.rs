@@ -0,0 +1,88 @@
+use std::{
+    ops::ControlFlow,
+    path::Path,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WatchError {
+    #[error("notify error")]
+    Notify(#[from] notify::Error),
+    #[error("watcher disconnected")]
+    Disconnected,
+}
+
+/// Calls `on_change` every time `file` is saved, until it returns `Break`.
+/// Saves within `debounce` of each other are reported once.
+pub fn watch_file(
+    file: &Path,
+    debounce: Duration,
+    mut on_change: impl FnMut() -> ControlFlow<()>,
+) -> Result<(), WatchError> {
+    let file = std::path::absolute(file).map_err(notify::Error::io)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    // editors often replace the file instead of writing it, watch the directory
+    let dir = file.parent().unwrap_or(Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    let is_change = |event: &notify::Result<notify::Event>| match event {
+        Ok(event) => {
+            matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| p.file_name() == file.file_name())
+        }
+        Err(_) => false,
+    };
+
+    loop {
+        let event = rx.recv().map_err(|_| WatchError::Disconnected)?;
+        if !is_change(&event) {
+            continue;
+        }
+        let mut deadline = Instant::now() + debounce;
+        loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(event) if is_change(&event) => deadline = Instant::now() + debounce,
+                Ok(_) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => break,
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(WatchError::Disconnected),
+            }
+        }
+        if on_change().is_break() {
+            return Ok(());
+        }
+    }
+}
+
+#[test]
+fn file_change_triggers_callback() {
+    let tmp = tempfile::tempdir().unwrap();
+    let file = tmp.path().join("repos.txt");
+    std::fs::write(&file, "").unwrap();
+
+    let writer = {
+        let file = file.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(300));
+            // rapid saves are debounced into one callback
+            for i in 0..3 {
+                std::fs::write(&file, format!("moonbitlang/core {}\n", i)).unwrap();
+            }
+        })
+    };
+    let mut calls = 0;
+    watch_file(&file, Duration::from_millis(200), || {
+        calls += 1;
+        ControlFlow::Break(())
+    })
+    .unwrap();
+    writer.join().unwrap();
+    assert_eq!(calls, 1);
+    assert!(std::fs::read_to_string(&file).unwrap().contains('2'));
+}
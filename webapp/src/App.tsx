@@ -23,7 +23,7 @@ interface MoonBuildDashboard {
   bleeding_release_data: BuildState[];
 }
 
-type Status = "Success" | "Failure";
+type Status = "Success" | "Failure" | "Timeout";
 
 interface ExecuteResult {
   status: Status;
@@ -37,8 +37,17 @@ interface BackendState {
   wasm: ExecuteResult;
   wasm_gc: ExecuteResult;
   js: ExecuteResult;
+  // missing in data written before the native backend was built
+  native?: ExecuteResult;
 }
 
+const BACKENDS: (keyof BackendState)[] = ['wasm', 'wasm_gc', 'js', 'native'];
+const BACKEND_LABELS = ['wasm', 'wasm gc', 'js', 'native'];
+
+// 某个后端是否在该条目中运行过
+const ranBackend = (entry: BuildState, backend: string) =>
+  entry.cbts.some(cbt => cbt?.check[backend as keyof BackendState] !== undefined);
+
 interface CBT {
   check: BackendState;
   build: BackendState;
@@ -216,12 +225,14 @@ const App = () => {
   };
 
   const getStatusStyle = (status: Status): string => {
+    if (status === "Timeout") return "bg-yellow-200 text-yellow-800";
     return status === "Success"
       ? "bg-green-200 text-green-800"
       : "bg-red-200 text-red-800";
   };
   
   const getStatusText = (status: Status, elapsed: number | null): string => {
+    if (status === "Timeout") return "t";
     return status === "Success" ? `${elapsed ?? '-'}` : "x";
   };
 
@@ -309,7 +320,7 @@ const App = () => {
             )}
           </td>
           <td 
-            colSpan={18}
+            colSpan={24}
             className={`py-2 px-4 ${
               summary.status === 'success' ? 'text-green-600 bg-green-50' :
               summary.status === 'warning' ? 'text-yellow-600 bg-yellow-50' :
@@ -349,9 +360,15 @@ const App = () => {
               {stableEntry.cbts[0] ? (
                 <>
                   {['check', 'build', 'test'].map(phase => (
-                    ['wasm', 'wasm_gc', 'js'].map(backend => {
-                      const result = stableEntry.cbts[0]?.[phase as keyof CBT]?.[backend as keyof BackendState];
-                      if (!result) return null;
+                    BACKENDS.map(backend => {
+                      const result = stableEntry.cbts[0]?.[phase as keyof CBT]?.[backend];
+                      if (!result) {
+                        return (
+                          <td key={`${phase}-${backend}`} className="py-2 px-4 border-r border-b text-center text-gray-400">
+                            -
+                          </td>
+                        );
+                      }
                       
                       return (
                         <td
@@ -368,7 +385,7 @@ const App = () => {
                   ))}
                 </>
               ) : (
-                <td colSpan={9} className="py-2 px-4 text-center text-gray-500">
+                <td colSpan={12} className="py-2 px-4 text-center text-gray-500">
                   No stable data available
                 </td>
               )}
@@ -376,9 +393,15 @@ const App = () => {
               {bleedingEntry?.cbts[0] ? (
                 <>
                   {['check', 'build', 'test'].map(phase => (
-                    ['wasm', 'wasm_gc', 'js'].map(backend => {
-                      const result = bleedingEntry.cbts[0]?.[phase as keyof CBT]?.[backend as keyof BackendState];
-                      if (!result) return null;
+                    BACKENDS.map(backend => {
+                      const result = bleedingEntry.cbts[0]?.[phase as keyof CBT]?.[backend];
+                      if (!result) {
+                        return (
+                          <td key={`bleeding-${phase}-${backend}`} className="py-2 px-4 border-r border-b text-center text-gray-400">
+                            -
+                          </td>
+                        );
+                      }
                       
                       return (
                         <td
@@ -395,7 +418,7 @@ const App = () => {
                   ))}
                 </>
               ) : (
-                <td colSpan={9} className="py-2 px-4 text-center text-gray-500">
+                <td colSpan={12} className="py-2 px-4 text-center text-gray-500">
                   No bleeding data available
                 </td>
               )}
@@ -423,9 +446,10 @@ const App = () => {
       return entry.cbts.every(cbt => {
         if (!cbt) return false;
         return ['check', 'build', 'test'].every(phase => 
-          ['wasm', 'wasm_gc', 'js'].every(backend => 
-            cbt[phase as keyof CBT][backend as keyof BackendState].status === "Success"
-          )
+          BACKENDS.every(backend => {
+            const result = cbt[phase as keyof CBT][backend];
+            return !result || result.status === "Success";
+          })
         );
       });
     });
@@ -434,14 +458,14 @@ const App = () => {
   // 检查单个项目的状态
   const checkItemStatus = (cbt: CBT | null, phase: string, backend: string) => {
     if (!cbt) return false;
-    return cbt[phase as keyof CBT][backend as keyof BackendState].status === "Success";
+    return cbt[phase as keyof CBT][backend as keyof BackendState]?.status === "Success";
   };
 
   // 检查工具链版本之间的差异（重点一）
   const checkToolchainDifference = (index: number, data: PlatformData) => {
     const platforms: Platform[] = ["mac", "windows", "linux"];
     const phases = ['check', 'build', 'test'];
-    const backends = ['wasm', 'wasm_gc', 'js'];
+    const backends = BACKENDS;
 
     for (const platform of platforms) {
       const platformData = data[platform];
@@ -453,6 +477,7 @@ const App = () => {
 
       for (const phase of phases) {
         for (const backend of backends) {
+          if (!ranBackend(stableEntry, backend) || !ranBackend(bleedingEntry, backend)) continue;
           const stableSuccess = stableEntry.cbts.some(cbt => checkItemStatus(cbt, phase, backend));
           const bleedingSuccess = bleedingEntry.cbts.some(cbt => checkItemStatus(cbt, phase, backend));
           
@@ -469,7 +494,7 @@ const App = () => {
   const checkPlatformDifference = (index: number, data: PlatformData, useBleedingEdge: boolean = false) => {
     const platforms: Platform[] = ["mac", "windows", "linux"];
     const phases = ['check', 'build', 'test'];
-    const backends = ['wasm', 'wasm_gc', 'js'];
+    const backends = BACKENDS;
 
     for (const phase of phases) {
       for (const backend of backends) {
@@ -482,7 +507,7 @@ const App = () => {
           const entry = useBleedingEdge ? 
             platformData.bleeding_release_data[index] : 
             platformData.stable_release_data[index];
-          if (!entry) continue;
+          if (!entry || !ranBackend(entry, backend)) continue;
           
           results.set(platform, entry.cbts.some(cbt => checkItemStatus(cbt, phase, backend)));
         }
@@ -502,7 +527,7 @@ const App = () => {
   const checkBackendDifference = (index: number, data: PlatformData, useBleedingEdge: boolean = false) => {
     const platforms: Platform[] = ["mac", "windows", "linux"];
     const phases = ['check', 'build', 'test'];
-    const backends = ['wasm', 'wasm_gc', 'js'];
+    const backends = BACKENDS;
 
     for (const platform of platforms) {
       const platformData = data[platform];
@@ -514,7 +539,7 @@ const App = () => {
       if (!entry) continue;
 
       for (const phase of phases) {
-        const backendResults = backends.map(backend => ({
+        const backendResults = backends.filter(backend => ranBackend(entry, backend)).map(backend => ({
           backend,
           success: entry.cbts.some(cbt => checkItemStatus(cbt, phase, backend))
         }));
@@ -534,7 +559,7 @@ const App = () => {
   const checkPhasesDifference = (index: number, data: PlatformData, useBleedingEdge: boolean = false) => {
     const platforms: Platform[] = ["mac", "windows", "linux"];
     const phases = ['check', 'build', 'test'];
-    const backends = ['wasm', 'wasm_gc', 'js'];
+    const backends = BACKENDS;
 
     for (const platform of platforms) {
       const platformData = data[platform];
@@ -545,7 +570,7 @@ const App = () => {
         platformData.stable_release_data[index];
       if (!entry) continue;
 
-      for (const backend of backends) {
+      for (const backend of backends.filter(backend => ranBackend(entry, backend))) {
         const phaseResults = phases.map(phase => ({
           phase,
           success: entry.cbts.some(cbt => checkItemStatus(cbt, phase, backend))
@@ -633,21 +658,21 @@ const App = () => {
                 <colgroup>
                   <col className="w-[15%]" />
                   <col className="w-[15%]" />
-                  {Array(18).fill(null).map((_, i) => (
-                    <col key={i} className="w-[3.888888%]" />
+                  {Array(24).fill(null).map((_, i) => (
+                    <col key={i} className="w-[2.916666%]" />
                   ))}
                 </colgroup>
                 <thead>
                   <tr>
                     <th rowSpan={3} className="py-2 px-4 text-left border-r bg-gray-200">Repository</th>
                     <th rowSpan={3} className="py-2 px-4 text-left border-r bg-gray-200">Version</th>
-                    <th colSpan={9} className="py-2 px-4 text-center bg-green-500 text-white border-r">
+                    <th colSpan={12} className="py-2 px-4 text-center bg-green-500 text-white border-r">
                       Stable Release
                       <div className="text-xs mt-1 font-normal">
                         {platformData.mac.stable_toolchain_version.moon_version} / moonc {platformData.mac.stable_toolchain_version.moonc_version}
                       </div>
                     </th>
-                    <th colSpan={9} className="py-2 px-4 text-center bg-red-600 text-white relative overflow-hidden">
+                    <th colSpan={12} className="py-2 px-4 text-center bg-red-600 text-white relative overflow-hidden">
                       <span className="absolute inset-0 flex items-center justify-left text-6xl text-yellow-900 opacity-40">⚡️</span>
                       <div className="relative">
                         Bleeding Edge Release
@@ -661,7 +686,7 @@ const App = () => {
                     {['Check(ms)', 'Build(ms)', 'Test(ms)', 'Check(ms)', 'Build(ms)', 'Test(ms)'].map((text, i) => (
                       <th 
                         key={i} 
-                        colSpan={3} 
+                        colSpan={4} 
                         className={`py-1 px-4 text-center text-sm bg-gray-100 border-b border-gray-200 ${i < 5 ? 'border-r' : ''}`}
                       >
                         {text}
@@ -669,12 +694,12 @@ const App = () => {
                     ))}
                   </tr>
                   <tr>
-                    {Array(18).fill(null).map((_, i) => (
+                    {Array(24).fill(null).map((_, i) => (
                       <th 
                         key={i}
-                        className={`py-1 px-4 text-center text-xs bg-gray-100 border-b border-gray-200 ${i < 17 ? 'border-r' : ''}`}
+                        className={`py-1 px-4 text-center text-xs bg-gray-100 border-b border-gray-200 ${i < 23 ? 'border-r' : ''}`}
                       >
-                        {['wasm', 'wasm gc', 'js'][i % 3]}
+                        {BACKEND_LABELS[i % 4]}
                       </th>
                     ))}
                   </tr>
@@ -687,8 +712,8 @@ const App = () => {
                 <colgroup>
                   <col className="w-[15%]" />
                   <col className="w-[15%]" />
-                  {Array(18).fill(null).map((_, i) => (
-                    <col key={i} className="w-[3.888888%]" />
+                  {Array(24).fill(null).map((_, i) => (
+                    <col key={i} className="w-[2.916666%]" />
                   ))}
                 </colgroup>
                 <tbody className="bg-white divide-y divide-gray-200">